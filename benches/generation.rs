@@ -0,0 +1,69 @@
+//! Benchmarks of overworld generation, comparing heights computed column by column with
+//! heights computed over whole chunk footprints. Both evaluate the noise functions once per
+//! column; the footprints share the blending of biomes, and the erosion if it's enabled.
+
+use bevy_3d::game::chunk::{
+    generator::{ore::OreVein, overworld::OverworldTerrain, TerrainGenerator},
+    Chunk,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const SEED: u32 = 42;
+const SEA_LEVEL: usize = 62;
+
+fn terrain() -> OverworldTerrain {
+    OverworldTerrain::new(SEED, SEA_LEVEL, OreVein::defaults(), false, false)
+}
+
+fn heights(c: &mut Criterion) {
+    let terrain = terrain();
+    let (min, max) = ([0, 0], [Chunk::WIDTH - 1, Chunk::WIDTH - 1]);
+    let mut group = c.benchmark_group("chunk heights");
+
+    group.bench_function("per column", |b| {
+        b.iter(|| {
+            for z in min[1]..=max[1] {
+                for x in min[0]..=max[0] {
+                    black_box(terrain.height_at(x, z));
+                }
+            }
+        })
+    });
+    group.bench_function("whole footprint", |b| {
+        b.iter(|| black_box(terrain.surface_heights(min, max)))
+    });
+    group.finish();
+}
+
+fn generate(c: &mut Criterion) {
+    let terrain = terrain();
+    let mut position = 0;
+
+    // a new chunk each time, so no cache of the generator is hit
+    c.bench_function("generate chunk", |b| {
+        b.iter(|| {
+            position += Chunk::WIDTH;
+            black_box(terrain.generate_chunk([position, 0, 0]))
+        })
+    });
+}
+
+fn decorate(c: &mut Criterion) {
+    let cached = terrain();
+    let chunk = cached.generate_chunk([0, 0, 0]);
+    let mut group = c.benchmark_group("decorate chunk");
+
+    group.bench_function("cached columns", |b| {
+        b.iter(|| black_box(cached.decorate(&chunk, [0, 0, 0])))
+    });
+
+    // a generator which hasn't generated the chunk has to evaluate the biomes again
+    let uncached = terrain();
+    group.bench_function("without columns", |b| {
+        b.iter(|| black_box(uncached.decorate(&chunk, [0, 0, 0])))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, heights, generate, decorate);
+criterion_main!(benches);
@@ -0,0 +1,105 @@
+//! Generates terrain without opening a window and exports it as glTF or OBJ, or as
+//! a PNG heightmap together with a colour map of the top blocks.
+//!
+//! Usage: `export_terrain <output.gltf|output.obj|output.png> [radius] [center_x] [center_z]`
+//! where radius and center are given in chunks.
+
+use std::{env, path::Path, process::ExitCode};
+
+use bevy_3d::game::{
+    chunk::{
+        generator::ChunkGenerator,
+        grid::{ChunkGridInner, GridCoordinates},
+        Chunk,
+    },
+    export::{heightmap::HeightmapExport, ExportFormat, ExportMesh, TextureAtlas},
+    structure::{StructureSets, STRUCTURES_PATH},
+};
+
+const ATLAS_PATH: &str = "assets/textures/texture-atlas.png";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let Some(output) = args.first() else {
+        eprintln!(
+            "usage: export_terrain <output.gltf|output.obj|output.png> [radius] [center_x] [center_z]"
+        );
+        return ExitCode::FAILURE;
+    };
+    // heightmaps aren't meshes, so they aren't one of the export formats
+    let is_heightmap = Path::new(output)
+        .extension()
+        .is_some_and(|extension| extension == "png");
+    let format = ExportFormat::from_path(output);
+    if format.is_none() && !is_heightmap {
+        eprintln!("unsupported format, expected .gltf, .obj or .png");
+        return ExitCode::FAILURE;
+    }
+
+    let number = |index: usize, default: isize| {
+        args.get(index)
+            .map_or(Ok(default), |arg| arg.parse::<isize>())
+    };
+    let (Ok(radius), Ok(center_x), Ok(center_z)) = (number(1, 2), number(2, 0), number(3, 0))
+    else {
+        eprintln!("radius and center have to be integers");
+        return ExitCode::FAILURE;
+    };
+
+    let generator = ChunkGenerator::default();
+    let grid = ChunkGridInner::default();
+    let coordinates: Vec<GridCoordinates> = (-radius..=radius)
+        .flat_map(|x| (-radius..=radius).map(move |z| (x, z)))
+        .map(|(x, z)| {
+            GridCoordinates::new(
+                (center_x + x) * Chunk::WIDTH,
+                0,
+                (center_z + z) * Chunk::WIDTH,
+            )
+        })
+        .collect();
+
+    for coordinates in &coordinates {
+        let chunk = generator.generate_chunk((*coordinates).into());
+        let features = generator.decorate(&chunk, (*coordinates).into());
+        grid.insert_generated(*coordinates, chunk, features);
+    }
+
+    // only structures which fit into the exported area are placed
+    let structures = StructureSets::load(STRUCTURES_PATH);
+
+    for coordinates in &coordinates {
+        grid.decorate(*coordinates);
+
+        for set in structures.iter() {
+            if set.starts_in(generator.seed(), *coordinates) {
+                set.place(&grid, &generator, *coordinates);
+            }
+        }
+    }
+
+    let result = match format {
+        Some(format) => TextureAtlas::load(ATLAS_PATH).and_then(|atlas| {
+            ExportMesh::from_chunks(&grid, coordinates, Default::default(), &atlas)
+                .export(output, format, &atlas)
+        }),
+        None => {
+            let min = [center_x - radius, center_z - radius].map(|n| n * Chunk::WIDTH);
+            let max = [center_x + radius + 1, center_z + radius + 1].map(|n| n * Chunk::WIDTH - 1);
+
+            HeightmapExport::from_grid(&grid, min, max).save(output)
+        }
+    };
+
+    match result {
+        Ok(()) => {
+            println!("exported {} chunks to {output}", (2 * radius + 1).pow(2));
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("export failed: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
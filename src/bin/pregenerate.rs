@@ -0,0 +1,317 @@
+//! Generates a square of chunks around the origin without opening a window, and writes
+//! them as Minecraft region files, e.g. to bake worlds on build servers.
+//!
+//! Usage: `pregenerate <region directory> [seed] [preset] [radius]` where radius is given
+//! in chunks. Presets are `overworld`, `ridged`, `superflat`, `void` and `debug`, or the
+//! path of a `.noise.ron` noise graph or `.png` heightmap.
+//!
+//! Chunks go through the same stages as in game, on all cores: generation, decoration,
+//! light, meshing and colliders. Meshes and colliders aren't stored, but are built so broken
+//! chunks are noticed and the timings cover everything a chunk needs before it's shown.
+//!
+//! Exits with 0 once all regions are written, 1 if generating or writing failed,
+//! and 2 if the arguments are invalid.
+
+use std::{
+    env, fs,
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy_3d::game::{
+    chunk::{
+        anvil::RegionWriter,
+        block_mapping::BlockMapping,
+        generator::{
+            graph::NoiseGraph,
+            heightmap::{HeightmapSettings, HeightmapTerrain},
+            ChunkGenerator, WorldPreset,
+        },
+        grid::{ChunkGridInner, GridCoordinates},
+        mesh_builder::MeshBuilderSettings,
+        Chunk,
+    },
+    structure::{StructureSets, STRUCTURES_PATH},
+};
+
+const USAGE: &str = "usage: pregenerate <region directory> [seed] [preset] [radius]";
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy)]
+enum Stage {
+    Generate,
+    Decorate,
+    Features,
+    Light,
+    Mesh,
+    Collider,
+    Convert,
+}
+
+impl Stage {
+    const ALL: [Stage; 7] = [
+        Stage::Generate,
+        Stage::Decorate,
+        Stage::Features,
+        Stage::Light,
+        Stage::Mesh,
+        Stage::Collider,
+        Stage::Convert,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Generate => "generate",
+            Stage::Decorate => "decorate",
+            Stage::Features => "features",
+            Stage::Light => "light",
+            Stage::Mesh => "mesh",
+            Stage::Collider => "collider",
+            Stage::Convert => "convert",
+        }
+    }
+}
+
+/// Time spent in each stage, summed over all threads.
+#[derive(Default)]
+struct Timings([AtomicU64; 7]);
+
+impl Timings {
+    fn time<T>(&self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.0[stage as usize].fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        result
+    }
+
+    fn get(&self, stage: Stage) -> Duration {
+        Duration::from_nanos(self.0[stage as usize].load(Ordering::Relaxed))
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let Some(output) = args.first() else {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    };
+    let seed = match args.get(1).map(|arg| arg.parse::<u32>()) {
+        None => ChunkGenerator::DEFAULT_SEED,
+        Some(Ok(seed)) => seed,
+        Some(Err(_)) => {
+            eprintln!("seed has to be a positive integer\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let radius = match args.get(3).map(|arg| arg.parse::<isize>()) {
+        None => 8,
+        Some(Ok(radius)) if radius >= 0 => radius,
+        Some(_) => {
+            eprintln!("radius has to be a positive integer\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let generator = match generator(seed, args.get(2).map_or("overworld", String::as_str)) {
+        Ok(generator) => generator,
+        Err(error) => {
+            eprintln!("{error}\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let coordinates: Vec<GridCoordinates> = (-radius..=radius)
+        .flat_map(|x| (-radius..=radius).map(move |z| (x, z)))
+        .map(|(x, z)| GridCoordinates::new(x * Chunk::WIDTH, 0, z * Chunk::WIDTH))
+        .collect();
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let grid = ChunkGridInner::default();
+    let writer = RegionWriter::new(output, BlockMapping::minecraft());
+    let structures = StructureSets::load(STRUCTURES_PATH);
+    let timings = Timings::default();
+    let start = Instant::now();
+
+    println!(
+        "generating {} chunks with seed {seed} on {threads} threads",
+        coordinates.len()
+    );
+
+    // features reach into neighbouring chunks, so all terrain is generated before any is placed
+    let generated = run_parallel("terrain", &coordinates, threads, |coordinates| {
+        let position = coordinates.into();
+        let chunk = timings.time(Stage::Generate, || generator.generate_chunk(position));
+        let features = timings.time(Stage::Decorate, || generator.decorate(&chunk, position));
+        grid.insert_generated(coordinates, chunk, features);
+        Ok(())
+    });
+
+    // only structures which fit into the generated area are placed
+    let placed = generated.and_then(|()| {
+        run_parallel("features", &coordinates, threads, |coordinates| {
+            timings.time(Stage::Features, || {
+                grid.decorate(coordinates);
+
+                for set in structures.iter() {
+                    if set.starts_in(generator.seed(), coordinates) {
+                        set.place(&grid, &generator, coordinates);
+                    }
+                }
+            });
+            Ok(())
+        })
+    });
+
+    let converted = placed.and_then(|()| {
+        run_parallel("chunks", &coordinates, threads, |coordinates| {
+            let light = timings
+                .time(Stage::Light, || grid.light(coordinates))
+                .ok_or_else(|| format!("chunk at {coordinates:?} is missing"))?;
+            let mesh = timings
+                .time(Stage::Mesh, || {
+                    grid.build_mesh(coordinates, &light, MeshBuilderSettings::default())
+                })
+                .ok_or_else(|| format!("chunk at {coordinates:?} is missing"))?;
+            timings.time(Stage::Collider, || mesh.collider());
+
+            let entry = grid
+                .get(&coordinates)
+                .ok_or_else(|| format!("chunk at {coordinates:?} is missing"))?;
+            let chunk = entry
+                .value()
+                .as_ref()
+                .ok_or_else(|| format!("chunk at {coordinates:?} is missing"))?;
+
+            timings
+                .time(Stage::Convert, || {
+                    writer.add_chunk(chunk, coordinates.into())
+                })
+                .map_err(|error| format!("failed to convert chunk at {coordinates:?}: {error}"))
+        })
+    });
+
+    let save_start = Instant::now();
+    let saved = converted.and_then(|()| {
+        writer
+            .save()
+            .map_err(|error| format!("failed to write regions to {output}: {error}"))
+    });
+
+    match saved {
+        Ok(regions) => {
+            let elapsed = start.elapsed();
+
+            println!(
+                "wrote {regions} regions to {output} in {:.1} s, {:.1} chunks/s",
+                elapsed.as_secs_f64(),
+                coordinates.len() as f64 / elapsed.as_secs_f64()
+            );
+            println!("time per stage, summed over all threads:");
+            for stage in Stage::ALL {
+                let time = timings.get(stage);
+                println!(
+                    "  {:<9} {:>9.1} ms  {:>7.2} ms/chunk",
+                    stage.name(),
+                    time.as_secs_f64() * 1000.0,
+                    time.as_secs_f64() * 1000.0 / coordinates.len().max(1) as f64
+                );
+            }
+            println!(
+                "  {:<9} {:>9.1} ms",
+                "save",
+                save_start.elapsed().as_secs_f64() * 1000.0
+            );
+
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Builds the generator of a preset given by name, or by the path of its file.
+fn generator(seed: u32, preset: &str) -> Result<ChunkGenerator, String> {
+    let preset = match preset {
+        "overworld" => WorldPreset::default(),
+        "ridged" => WorldPreset::ridged(),
+        "superflat" => WorldPreset::superflat(),
+        "void" => WorldPreset::Void,
+        "debug" => WorldPreset::Debug,
+        path if path.ends_with(".noise.ron") => {
+            let graph = fs::read_to_string(path)
+                .map_err(|error| error.to_string())
+                .and_then(|source| NoiseGraph::from_ron(&source).map_err(|e| e.to_string()))
+                .map_err(|error| format!("failed to load noise graph {path}: {error}"))?;
+
+            return Ok(ChunkGenerator::from_graph(seed, &graph));
+        }
+        path if path.ends_with(".png") => {
+            let settings = HeightmapSettings {
+                path: path.to_string(),
+                ..Default::default()
+            };
+            // loaded here rather than by the preset, which would fall back to an empty world
+            let terrain = HeightmapTerrain::load(settings)
+                .map_err(|error| format!("failed to load heightmap {path}: {error}"))?;
+
+            return Ok(ChunkGenerator::with_terrain(seed, terrain));
+        }
+        preset => return Err(format!("unknown preset {preset}")),
+    };
+
+    Ok(ChunkGenerator::new(seed, preset))
+}
+
+/// Runs `work` for every chunk on all threads, and prints the progress of the phase.
+/// Stops at the first error.
+fn run_parallel(
+    phase: &str,
+    coordinates: &[GridCoordinates],
+    threads: usize,
+    work: impl Fn(GridCoordinates) -> Result<(), String> + Sync,
+) -> Result<(), String> {
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let error = Mutex::new(None);
+    let start = Instant::now();
+    let last_report = Mutex::new(start);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                while !failed.load(Ordering::Relaxed) {
+                    let Some(&chunk) = coordinates.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+
+                    if let Err(message) = work(chunk) {
+                        failed.store(true, Ordering::Relaxed);
+                        error.lock().unwrap().get_or_insert(message);
+                        break;
+                    }
+
+                    let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    let mut last_report = last_report.lock().unwrap();
+
+                    if last_report.elapsed() >= PROGRESS_INTERVAL || done == coordinates.len() {
+                        *last_report = Instant::now();
+                        println!(
+                            "{phase}: {done}/{} chunks, {:.1} chunks/s",
+                            coordinates.len(),
+                            done as f64 / start.elapsed().as_secs_f64()
+                        );
+                    }
+                }
+            });
+        }
+    });
+
+    error.into_inner().unwrap().map_or(Ok(()), Err)
+}
@@ -0,0 +1,700 @@
+//! Reads Minecraft worlds stored in the Anvil format (`.mca` region files), so they can be
+//! explored in place of generated terrain. Supports chunks written by Minecraft 1.13 and newer.
+//! Generated chunks can be written as region files as well, in the format of Minecraft 1.20.
+//! https://minecraft.wiki/w/Region_file_format
+//! https://minecraft.wiki/w/Chunk_format
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use dashmap::DashMap;
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::ZlibEncoder,
+    Compression,
+};
+
+use crate::nbt::{self, NbtError, Tag};
+
+use super::{block_mapping::BlockMapping, BlockType, Chunk};
+
+const SECTOR_SIZE: usize = 4096;
+const REGION_WIDTH: i32 = 32;
+const CHUNK_WIDTH: isize = 16;
+const SECTION_HEIGHT: isize = 16;
+/// Since this data version (20w17a, 1.16) block state indices do not span multiple longs.
+const NON_SPANNING_DATA_VERSION: i64 = 2529;
+/// Data version of Minecraft 1.20.1, which written chunks are marked with.
+const DATA_VERSION: i32 = 3465;
+
+#[derive(Debug)]
+pub enum AnvilError {
+    Io(io::Error),
+    Nbt(NbtError),
+    UnsupportedCompression(u8),
+    Invalid(&'static str),
+}
+
+impl fmt::Display for AnvilError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnvilError::Io(error) => write!(f, "{error}"),
+            AnvilError::Nbt(error) => write!(f, "{error}"),
+            AnvilError::UnsupportedCompression(compression) => {
+                write!(f, "unsupported chunk compression {compression}")
+            }
+            AnvilError::Invalid(reason) => write!(f, "invalid region file: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for AnvilError {}
+
+impl From<io::Error> for AnvilError {
+    fn from(error: io::Error) -> Self {
+        AnvilError::Io(error)
+    }
+}
+
+impl From<NbtError> for AnvilError {
+    fn from(error: NbtError) -> Self {
+        AnvilError::Nbt(error)
+    }
+}
+
+type RegionCache = DashMap<(i32, i32), Option<Arc<RegionFile>>>;
+/// Compressed chunks by their position inside the region.
+type RegionChunks = BTreeMap<(i32, i32), Vec<u8>>;
+
+/// Minecraft world used as a source for chunks. Chunks which are not part of the world
+/// are generated by the `ChunkGenerator` instead.
+#[derive(Resource, Clone)]
+pub struct AnvilWorld {
+    /// Directory containing the `r.<x>.<z>.mca` files, usually `<world>/region`.
+    directory: PathBuf,
+    mapping: Arc<BlockMapping>,
+    /// Added to Minecraft y coordinates, since our worlds do not extend below zero.
+    y_offset: isize,
+    regions: Arc<RegionCache>,
+}
+
+impl AnvilWorld {
+    pub const DEFAULT_Y_OFFSET: isize = 64;
+
+    pub fn open(directory: impl Into<PathBuf>, mapping: BlockMapping) -> Self {
+        Self {
+            directory: directory.into(),
+            mapping: Arc::new(mapping),
+            y_offset: Self::DEFAULT_Y_OFFSET,
+            regions: Default::default(),
+        }
+    }
+
+    pub fn with_y_offset(mut self, y_offset: isize) -> Self {
+        self.y_offset = y_offset;
+        self
+    }
+
+    /// Converts the Minecraft chunks covered by the chunk at `position`.
+    /// Returns `None` if none of them exist in the world.
+    pub fn load_chunk(&self, position: [isize; 3]) -> Result<Option<Chunk>, AnvilError> {
+        let mut chunk = Chunk::new(position.into());
+        let mut found = false;
+        let chunks_per_axis = Chunk::WIDTH / CHUNK_WIDTH;
+
+        for offset_x in 0..chunks_per_axis {
+            for offset_z in 0..chunks_per_axis {
+                let chunk_x = (position[0].div_euclid(CHUNK_WIDTH) + offset_x) as i32;
+                let chunk_z = (position[2].div_euclid(CHUNK_WIDTH) + offset_z) as i32;
+
+                let Some(region) = self.region(
+                    chunk_x.div_euclid(REGION_WIDTH),
+                    chunk_z.div_euclid(REGION_WIDTH),
+                )?
+                else {
+                    continue;
+                };
+
+                if let Some(data) = region.read_chunk(
+                    chunk_x.rem_euclid(REGION_WIDTH),
+                    chunk_z.rem_euclid(REGION_WIDTH),
+                )? {
+                    self.convert_chunk(
+                        &data,
+                        &mut chunk,
+                        [offset_x * CHUNK_WIDTH, offset_z * CHUNK_WIDTH],
+                    )?;
+                    found = true;
+                }
+            }
+        }
+
+        Ok(found.then_some(chunk))
+    }
+
+    fn region(&self, x: i32, z: i32) -> Result<Option<Arc<RegionFile>>, AnvilError> {
+        if let Some(region) = self.regions.get(&(x, z)) {
+            return Ok(region.clone());
+        }
+
+        let path = self.directory.join(format!("r.{x}.{z}.mca"));
+        let region = match fs::read(path) {
+            Ok(data) => Some(Arc::new(RegionFile::new(data)?)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+
+        self.regions.insert((x, z), region.clone());
+
+        Ok(region)
+    }
+
+    fn convert_chunk(
+        &self,
+        data: &Tag,
+        chunk: &mut Chunk,
+        [offset_x, offset_z]: [isize; 2],
+    ) -> Result<(), AnvilError> {
+        let data_version = data.get("DataVersion").and_then(Tag::as_i64).unwrap_or(0);
+        // before 1.18 the chunk data was nested in a `Level` compound
+        let (sections, legacy) = match data.get("sections") {
+            Some(sections) => (sections, false),
+            None => (
+                data.get("Level")
+                    .and_then(|level| level.get("Sections"))
+                    .ok_or(AnvilError::Invalid("chunk without sections"))?,
+                true,
+            ),
+        };
+
+        for section in sections.as_list().unwrap_or_default() {
+            let Some(section_y) = section.get("Y").and_then(Tag::as_i64) else {
+                continue;
+            };
+
+            let (palette, states) = if legacy {
+                (section.get("Palette"), section.get("BlockStates"))
+            } else {
+                let block_states = section.get("block_states");
+                (
+                    block_states.and_then(|states| states.get("palette")),
+                    block_states.and_then(|states| states.get("data")),
+                )
+            };
+
+            // sections without palette only contain light data
+            let Some(palette) = palette.and_then(Tag::as_list) else {
+                continue;
+            };
+
+            let blocks: Vec<Option<BlockType>> = palette
+                .iter()
+                .map(|state| {
+                    state
+                        .get("Name")
+                        .and_then(Tag::as_str)
+                        .and_then(|name| self.mapping.to_block(name))
+                })
+                .collect();
+
+            let indices = match states.and_then(Tag::as_long_array) {
+                Some(states) => unpack_indices(
+                    states,
+                    blocks.len(),
+                    data_version < NON_SPANNING_DATA_VERSION,
+                )?,
+                // a single block fills the whole section
+                None => vec![0; 4096],
+            };
+
+            for (index, palette_index) in indices.into_iter().enumerate() {
+                let index = index as isize;
+                let x = offset_x + index % CHUNK_WIDTH;
+                let z = offset_z + index / CHUNK_WIDTH % CHUNK_WIDTH;
+                let y = section_y as isize * SECTION_HEIGHT
+                    + index / (CHUNK_WIDTH * CHUNK_WIDTH)
+                    + self.y_offset;
+
+                if !(Chunk::LOWER_BOUND..Chunk::HEIGHT).contains(&y) {
+                    continue;
+                }
+
+                let block = *blocks
+                    .get(palette_index)
+                    .ok_or(AnvilError::Invalid("block state outside of palette"))?;
+                chunk.set(x as usize, y as usize, z as usize, block);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Extracts the 4096 palette indices of a section from their bit-packed representation.
+/// Indices use at least 4 bits and, before 1.16, may be split across two longs.
+fn unpack_indices(
+    states: &[i64],
+    palette_length: usize,
+    spanning: bool,
+) -> Result<Vec<usize>, AnvilError> {
+    let bits = (usize::BITS - palette_length.saturating_sub(1).leading_zeros()).max(4) as usize;
+    let mask = (1_u64 << bits) - 1;
+    let values_per_long = 64 / bits;
+
+    (0..4096)
+        .map(|index| {
+            let value = if spanning {
+                let bit = index * bits;
+                let (long, offset) = (bit / 64, bit % 64);
+                let mut value = *states
+                    .get(long)
+                    .ok_or(AnvilError::Invalid("block states too short"))?
+                    as u64
+                    >> offset;
+
+                if offset + bits > 64 {
+                    let next = *states
+                        .get(long + 1)
+                        .ok_or(AnvilError::Invalid("block states too short"))?
+                        as u64;
+                    value |= next << (64 - offset);
+                }
+
+                value
+            } else {
+                let long = *states
+                    .get(index / values_per_long)
+                    .ok_or(AnvilError::Invalid("block states too short"))?
+                    as u64;
+                long >> (index % values_per_long * bits)
+            };
+
+            Ok((value & mask) as usize)
+        })
+        .collect()
+}
+
+/// A region file containing up to 32 by 32 compressed chunks.
+pub struct RegionFile {
+    data: Vec<u8>,
+}
+
+impl RegionFile {
+    pub fn new(data: Vec<u8>) -> Result<Self, AnvilError> {
+        if data.len() < 2 * SECTOR_SIZE {
+            return Err(AnvilError::Invalid("missing header"));
+        }
+
+        Ok(Self { data })
+    }
+
+    /// Reads the chunk at the given position inside the region, if it has been generated.
+    pub fn read_chunk(&self, x: i32, z: i32) -> Result<Option<Tag>, AnvilError> {
+        let location = 4 * (x + z * REGION_WIDTH) as usize;
+        let [a, b, c, sectors] = [0, 1, 2, 3].map(|i| self.data[location + i]);
+        let offset = u32::from_be_bytes([0, a, b, c]) as usize * SECTOR_SIZE;
+
+        if offset == 0 || sectors == 0 {
+            return Ok(None);
+        }
+
+        let header = self
+            .data
+            .get(offset..offset + 5)
+            .ok_or(AnvilError::Invalid("chunk outside of file"))?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let compression = header[4];
+        let payload = self
+            .data
+            .get(offset + 5..offset + 4 + length)
+            .ok_or(AnvilError::Invalid("chunk outside of file"))?;
+
+        let (_, tag) = match compression {
+            1 => nbt::read(&mut GzDecoder::new(payload))?,
+            2 => nbt::read(&mut ZlibDecoder::new(payload))?,
+            3 => nbt::read(&mut &payload[..])?,
+            // lz4 and chunks stored in external files are not supported
+            compression => return Err(AnvilError::UnsupportedCompression(compression)),
+        };
+
+        Ok(Some(tag))
+    }
+
+    /// Lays out zlib compressed chunks as a region file.
+    fn from_chunks(chunks: &RegionChunks) -> Result<Self, AnvilError> {
+        let mut data = vec![0; 2 * SECTOR_SIZE];
+
+        for (&(x, z), chunk) in chunks {
+            let offset = data.len() / SECTOR_SIZE;
+            let length = chunk.len() + 1;
+            let sectors = (4 + length).div_ceil(SECTOR_SIZE);
+            let sectors = u8::try_from(sectors)
+                .map_err(|_| AnvilError::Invalid("chunk larger than 1 MiB"))?;
+
+            let location = 4 * (x + z * REGION_WIDTH) as usize;
+            let [_, a, b, c] = (offset as u32).to_be_bytes();
+            data[location..location + 4].copy_from_slice(&[a, b, c, sectors]);
+
+            data.write_all(&(length as u32).to_be_bytes())?;
+            // zlib
+            data.push(2);
+            data.extend_from_slice(chunk);
+            data.resize(data.len().div_ceil(SECTOR_SIZE) * SECTOR_SIZE, 0);
+        }
+
+        Self::new(data)
+    }
+}
+
+/// Region files being written, collecting the converted chunks until they are saved.
+/// Chunks can be added from several threads at once.
+pub struct RegionWriter {
+    /// Directory the `r.<x>.<z>.mca` files are written to.
+    directory: PathBuf,
+    mapping: Arc<BlockMapping>,
+    /// Subtracted from our y coordinates, the counterpart of [`AnvilWorld::with_y_offset`].
+    y_offset: isize,
+    regions: DashMap<(i32, i32), RegionChunks>,
+}
+
+impl RegionWriter {
+    pub fn new(directory: impl Into<PathBuf>, mapping: BlockMapping) -> Self {
+        Self {
+            directory: directory.into(),
+            mapping: Arc::new(mapping),
+            y_offset: AnvilWorld::DEFAULT_Y_OFFSET,
+            regions: Default::default(),
+        }
+    }
+
+    pub fn with_y_offset(mut self, y_offset: isize) -> Self {
+        self.y_offset = y_offset;
+        self
+    }
+
+    /// Converts a chunk into the Minecraft chunks it covers.
+    pub fn add_chunk(&self, chunk: &Chunk, position: [isize; 3]) -> Result<(), AnvilError> {
+        let chunks_per_axis = Chunk::WIDTH / CHUNK_WIDTH;
+
+        for offset_x in 0..chunks_per_axis {
+            for offset_z in 0..chunks_per_axis {
+                let chunk_x = (position[0].div_euclid(CHUNK_WIDTH) + offset_x) as i32;
+                let chunk_z = (position[2].div_euclid(CHUNK_WIDTH) + offset_z) as i32;
+                let tag = self.convert_chunk(
+                    chunk,
+                    [chunk_x, chunk_z],
+                    [offset_x * CHUNK_WIDTH, offset_z * CHUNK_WIDTH],
+                );
+
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                nbt::write(&mut encoder, "", &tag)?;
+                let data = encoder.finish()?;
+
+                self.regions
+                    .entry((
+                        chunk_x.div_euclid(REGION_WIDTH),
+                        chunk_z.div_euclid(REGION_WIDTH),
+                    ))
+                    .or_default()
+                    .insert(
+                        (
+                            chunk_x.rem_euclid(REGION_WIDTH),
+                            chunk_z.rem_euclid(REGION_WIDTH),
+                        ),
+                        data,
+                    );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes all regions which chunks have been added to, replacing existing files.
+    /// Returns the number of written files.
+    pub fn save(&self) -> Result<usize, AnvilError> {
+        fs::create_dir_all(&self.directory)?;
+
+        for region in self.regions.iter() {
+            let (x, z) = *region.key();
+            let data = RegionFile::from_chunks(region.value())?;
+
+            fs::write(self.directory.join(format!("r.{x}.{z}.mca")), data.data)?;
+        }
+
+        Ok(self.regions.len())
+    }
+
+    fn convert_chunk(
+        &self,
+        chunk: &Chunk,
+        [chunk_x, chunk_z]: [i32; 2],
+        [offset_x, offset_z]: [isize; 2],
+    ) -> Tag {
+        let min_section = (-self.y_offset).div_euclid(SECTION_HEIGHT);
+        let max_section = (Chunk::HEIGHT - 1 - self.y_offset).div_euclid(SECTION_HEIGHT);
+
+        let sections = (min_section..=max_section)
+            .map(|section_y| {
+                let mut palette = Vec::new();
+                let mut palette_indices = HashMap::<Option<BlockType>, usize>::default();
+                let indices: Vec<usize> = (0..4096)
+                    .map(|index| {
+                        let x = offset_x + index % CHUNK_WIDTH;
+                        let z = offset_z + index / CHUNK_WIDTH % CHUNK_WIDTH;
+                        let y = section_y * SECTION_HEIGHT
+                            + index / (CHUNK_WIDTH * CHUNK_WIDTH)
+                            + self.y_offset;
+                        let block = (Chunk::LOWER_BOUND..Chunk::HEIGHT)
+                            .contains(&y)
+                            .then(|| chunk.get([x, y, z]))
+                            .flatten();
+
+                        *palette_indices.entry(block).or_insert_with(|| {
+                            palette.push(block);
+                            palette.len() - 1
+                        })
+                    })
+                    .collect();
+
+                let palette = palette
+                    .into_iter()
+                    .map(|block| {
+                        Tag::Compound(BTreeMap::from([(
+                            "Name".to_string(),
+                            Tag::String(self.mapping.to_name(block)),
+                        )]))
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut block_states = BTreeMap::new();
+                // sections filled with a single block don't store indices
+                if palette.len() > 1 {
+                    block_states.insert(
+                        "data".to_string(),
+                        Tag::LongArray(pack_indices(&indices, palette.len())),
+                    );
+                }
+                block_states.insert("palette".to_string(), Tag::List(palette));
+
+                Tag::Compound(BTreeMap::from([
+                    ("Y".to_string(), Tag::Byte(section_y as i8)),
+                    ("block_states".to_string(), Tag::Compound(block_states)),
+                ]))
+            })
+            .collect();
+
+        Tag::Compound(BTreeMap::from([
+            ("DataVersion".to_string(), Tag::Int(DATA_VERSION)),
+            ("xPos".to_string(), Tag::Int(chunk_x)),
+            ("yPos".to_string(), Tag::Int(min_section as i32)),
+            ("zPos".to_string(), Tag::Int(chunk_z)),
+            (
+                "Status".to_string(),
+                Tag::String("minecraft:full".to_string()),
+            ),
+            ("sections".to_string(), Tag::List(sections)),
+        ]))
+    }
+}
+
+/// Packs palette indices the way chunks since 1.16 store them, see [`unpack_indices`].
+fn pack_indices(indices: &[usize], palette_length: usize) -> Vec<i64> {
+    let bits = (usize::BITS - palette_length.saturating_sub(1).leading_zeros()).max(4) as usize;
+    let values_per_long = 64 / bits;
+
+    indices
+        .chunks(values_per_long)
+        .map(|values| {
+            values
+                .iter()
+                .enumerate()
+                .fold(0_u64, |long, (index, &value)| {
+                    long | (value as u64) << (index * bits)
+                }) as i64
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    /// Region with two hand-built chunks of a single section at y 0: chunk 0, 0 written by 1.14
+    /// with indices spanning longs, and chunk 1, 0 written by 1.17 without.
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/region");
+    /// Palette of both fixture sections. 17 entries take 5 bits, which don't divide 64.
+    const PALETTE: [&str; 17] = [
+        "air",
+        "stone",
+        "dirt",
+        "sand",
+        "grass_block",
+        "gravel",
+        "granite",
+        "coal_ore",
+        "iron_ore",
+        "gold_ore",
+        "diamond_ore",
+        "oak_log",
+        "oak_leaves",
+        "poppy",
+        "snow_block",
+        "deepslate",
+        "mossy_cobblestone",
+    ];
+
+    /// Palette index of a block of a fixture section, by its index in the section.
+    fn fixture_index(index: usize, multiplier: usize) -> usize {
+        (index * multiplier + index / 16) % PALETTE.len()
+    }
+
+    fn fixture_region() -> RegionFile {
+        RegionFile::new(fs::read(Path::new(FIXTURE).join("r.0.0.mca")).unwrap()).unwrap()
+    }
+
+    /// Packs indices the way chunks before 1.16 store them.
+    fn pack_spanning(indices: &[usize], bits: usize) -> Vec<i64> {
+        let mut longs = vec![0_u64; (indices.len() * bits).div_ceil(64)];
+
+        for (index, &value) in indices.iter().enumerate() {
+            let bit = index * bits;
+            longs[bit / 64] |= (value as u64) << (bit % 64);
+
+            if bit % 64 + bits > 64 {
+                longs[bit / 64 + 1] |= (value as u64) >> (64 - bit % 64);
+            }
+        }
+
+        longs.into_iter().map(|long| long as i64).collect()
+    }
+
+    #[test]
+    fn unpacks_spanning_indices() {
+        let indices: Vec<usize> = (0..4096).map(|index| fixture_index(index, 7)).collect();
+        let states = pack_spanning(&indices, 5);
+
+        assert_eq!(states.len(), 320);
+        assert_eq!(unpack_indices(&states, 17, true).unwrap(), indices);
+    }
+
+    #[test]
+    fn unpacks_non_spanning_indices() {
+        let indices: Vec<usize> = (0..4096).map(|index| fixture_index(index, 5)).collect();
+        let states = pack_indices(&indices, 17);
+
+        // 12 indices per long, the remaining 4 bits are unused
+        assert_eq!(states.len(), 4096_usize.div_ceil(12));
+        assert_eq!(unpack_indices(&states, 17, false).unwrap(), indices);
+        assert_ne!(unpack_indices(&states, 17, true).unwrap(), indices);
+    }
+
+    #[test]
+    fn uses_at_least_four_bits() {
+        let indices: Vec<usize> = (0..4096).map(|index| index % 2).collect();
+        let states = pack_indices(&indices, 2);
+
+        assert_eq!(states.len(), 256);
+        assert_eq!(unpack_indices(&states, 2, false).unwrap(), indices);
+    }
+
+    #[test]
+    fn rejects_short_block_states() {
+        assert!(unpack_indices(&[0; 100], 17, true).is_err());
+        assert!(unpack_indices(&[0; 100], 17, false).is_err());
+    }
+
+    #[test]
+    fn reads_fixture_chunks() {
+        let region = fixture_region();
+        let data_version = |x, z| {
+            region
+                .read_chunk(x, z)
+                .unwrap()
+                .and_then(|chunk| chunk.get("DataVersion").and_then(Tag::as_i64))
+        };
+
+        assert_eq!(data_version(0, 0), Some(1976));
+        assert_eq!(data_version(1, 0), Some(2730));
+        assert!(region.read_chunk(0, 1).unwrap().is_none());
+        assert!(region.read_chunk(31, 31).unwrap().is_none());
+    }
+
+    #[test]
+    fn converts_fixture_chunks() {
+        let mapping = BlockMapping::minecraft();
+        let world = AnvilWorld::open(FIXTURE, BlockMapping::minecraft()).with_y_offset(0);
+        let chunk = world.load_chunk([0, 0, 0]).unwrap().unwrap();
+
+        for x in 0..Chunk::WIDTH {
+            for y in 0..Chunk::HEIGHT {
+                for z in 0..Chunk::WIDTH {
+                    let expected = if y < SECTION_HEIGHT && z < CHUNK_WIDTH {
+                        let index =
+                            ((y * CHUNK_WIDTH + z) * CHUNK_WIDTH + x % CHUNK_WIDTH) as usize;
+                        // the first Minecraft chunk spans longs, the second doesn't
+                        let multiplier = if x < CHUNK_WIDTH { 7 } else { 5 };
+                        let name = PALETTE[fixture_index(index, multiplier)];
+                        mapping.to_block(&format!("minecraft:{name}"))
+                    } else {
+                        None
+                    };
+
+                    assert_eq!(chunk.get([x, y, z]), expected, "block at {x}, {y}, {z}");
+                }
+            }
+        }
+
+        assert!(world.load_chunk([0, 0, 32 * 16]).unwrap().is_none());
+    }
+
+    #[test]
+    fn round_trips_written_regions() {
+        let directory =
+            std::env::temp_dir().join(format!("anvil-round-trip-{}", std::process::id()));
+        let position = [32, 0, -64];
+        let mut chunk = Chunk::new(position.into());
+
+        for x in 0..Chunk::WIDTH {
+            for y in 0..Chunk::HEIGHT {
+                for z in 0..Chunk::WIDTH {
+                    let index = (x * 31 + y * 7 + z * 13) as usize % (BlockType::ALL.len() + 1);
+                    let block = BlockType::ALL.get(index).copied();
+                    chunk.set(x as usize, y as usize, z as usize, block);
+                }
+            }
+        }
+
+        let writer = RegionWriter::new(&directory, BlockMapping::minecraft());
+        writer.add_chunk(&chunk, position).unwrap();
+        // all four Minecraft chunks lie in region 0, -1
+        assert_eq!(writer.save().unwrap(), 1);
+
+        let mapping = BlockMapping::minecraft();
+        let loaded = AnvilWorld::open(&directory, BlockMapping::minecraft())
+            .load_chunk(position)
+            .unwrap()
+            .unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        for x in 0..Chunk::WIDTH {
+            for y in 0..Chunk::HEIGHT {
+                for z in 0..Chunk::WIDTH {
+                    // blocks sharing a Minecraft name come back as the first block of that name
+                    let expected = chunk
+                        .get([x, y, z])
+                        .and_then(|block| mapping.to_block(&mapping.to_name(Some(block))));
+
+                    assert_eq!(loaded.get([x, y, z]), expected, "block at {x}, {y}, {z}");
+                }
+            }
+        }
+    }
+}
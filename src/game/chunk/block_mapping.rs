@@ -0,0 +1,92 @@
+use std::{fmt, fs, io, path::Path};
+
+use bevy::utils::HashMap;
+use serde::Deserialize;
+
+use super::BlockType;
+
+/// Table translating block names of other games and tools into block types and back.
+#[derive(Debug, Clone)]
+pub struct BlockMapping {
+    to_block: HashMap<String, Option<BlockType>>,
+    to_name: HashMap<Option<BlockType>, String>,
+    fallback: Option<BlockType>,
+}
+
+#[derive(Deserialize)]
+struct BlockMappingFile {
+    blocks: Vec<(String, Option<BlockType>)>,
+    fallback: Option<BlockType>,
+}
+
+#[derive(Debug)]
+pub enum BlockMappingError {
+    Io(io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for BlockMappingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockMappingError::Io(error) => write!(f, "{error}"),
+            BlockMappingError::Ron(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for BlockMappingError {}
+
+impl BlockMapping {
+    /// The mapping for Minecraft block names which ships with the game.
+    pub fn minecraft() -> Self {
+        Self::from_ron(include_str!("../../../assets/block_mappings/minecraft.ron"))
+            .expect("built-in block mapping should be valid")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BlockMappingError> {
+        let source = fs::read_to_string(path).map_err(BlockMappingError::Io)?;
+        Self::from_ron(&source)
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, BlockMappingError> {
+        let file: BlockMappingFile = ron::from_str(source).map_err(BlockMappingError::Ron)?;
+        let mut mapping = Self {
+            to_block: HashMap::default(),
+            to_name: HashMap::default(),
+            fallback: file.fallback,
+        };
+
+        for (name, block) in file.blocks {
+            mapping.to_name.entry(block).or_insert_with(|| name.clone());
+            mapping.to_block.insert(name, block);
+        }
+
+        Ok(mapping)
+    }
+
+    /// Looks up an external block name. Block state properties, e.g. `[axis=y]`, are ignored.
+    /// Names which are not part of the table resolve to the fallback block.
+    pub fn to_block(&self, name: &str) -> Option<BlockType> {
+        let name = name.split_once('[').map_or(name, |(name, _)| name);
+
+        self.to_block.get(name).copied().unwrap_or(self.fallback)
+    }
+
+    /// Returns the external name for a block. Blocks without an entry in the table
+    /// keep their own name.
+    pub fn to_name(&self, block: Option<BlockType>) -> String {
+        self.to_name
+            .get(&block)
+            .cloned()
+            .unwrap_or_else(|| match block {
+                Some(block) => block.name().to_string(),
+                None => "minecraft:air".to_string(),
+            })
+    }
+}
+
+impl Default for BlockMapping {
+    fn default() -> Self {
+        Self::minecraft()
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics},
+    prelude::*,
+};
+
+use crate::settings::Settings;
+
+/// Time the main thread may spend integrating generated chunks into the world in a frame,
+/// e.g. inserting meshes and colliders or despawning chunks.
+///
+/// The budget is what's left of the target frame time after everything else the last frame
+/// did, so integration speeds up on fast machines and backs off when frames get slow.
+/// Work done by the engine later in the frame, like uploading meshes, counts against the
+/// next frame.
+#[derive(Resource, Default)]
+pub struct IntegrationBudget {
+    budget: Duration,
+    spent: Duration,
+    /// Items left over by the systems, reported as diagnostics at the start of the next frame.
+    backlogs: Vec<(DiagnosticId, usize)>,
+}
+
+impl IntegrationBudget {
+    /// Integration always gets at least this much time, so chunks keep streaming in on
+    /// machines which can't reach the target frame time.
+    const MIN_BUDGET: Duration = Duration::from_millis(1);
+
+    /// Chunks waiting for their mesh and collider to be inserted.
+    pub const MESH_BACKLOG: DiagnosticId =
+        DiagnosticId::from_u128(0x3d1e_9a4c_0b27_4f6e_8c51_7e2a_96d0_4b13);
+    /// Chunks waiting to be despawned.
+    pub const DESPAWN_BACKLOG: DiagnosticId =
+        DiagnosticId::from_u128(0x8f42_61b5_d3ce_4a09_b7f8_25c6_1e9d_a370);
+    /// Time spent integrating chunks in the last frame, in milliseconds.
+    pub const INTEGRATION_TIME: DiagnosticId =
+        DiagnosticId::from_u128(0xc5a7_0e83_19f4_4d62_a0b9_6f3e_d812_57c4);
+
+    pub fn diagnostics() -> [Diagnostic; 3] {
+        [
+            Diagnostic::new(Self::MESH_BACKLOG, "chunk_mesh_backlog", 20),
+            Diagnostic::new(Self::DESPAWN_BACKLOG, "chunk_despawn_backlog", 20),
+            Diagnostic::new(Self::INTEGRATION_TIME, "chunk_integration", 20).with_suffix("ms"),
+        ]
+    }
+
+    /// Runs `integrate` for the items until the budget of the frame is used up, but at least
+    /// for one item, so integration never stalls. Returns the number of integrated items.
+    ///
+    /// The next item is only pulled once the budget has been checked, so lazy iterators may
+    /// do part of the work themselves, e.g. in a `filter_map`, without it getting lost.
+    pub fn run<T>(
+        &mut self,
+        items: impl IntoIterator<Item = T>,
+        mut integrate: impl FnMut(T),
+    ) -> usize {
+        let start = Instant::now();
+        let mut integrated = 0;
+        let mut items = items.into_iter();
+
+        while integrated == 0 || self.spent + start.elapsed() < self.budget {
+            let Some(item) = items.next() else {
+                break;
+            };
+
+            integrate(item);
+            integrated += 1;
+        }
+
+        self.spent += start.elapsed();
+
+        integrated
+    }
+
+    /// Reports how many items are left for later frames, e.g. under [`Self::MESH_BACKLOG`].
+    pub fn report_backlog(&mut self, id: DiagnosticId, backlog: usize) {
+        self.backlogs.push((id, backlog));
+    }
+
+    /// Sets the budget of the new frame, from the time the last one took.
+    pub fn begin_frame(
+        mut budget: ResMut<Self>,
+        mut diagnostics: Diagnostics,
+        time: Res<Time>,
+        settings: Res<Settings>,
+    ) {
+        diagnostics.add_measurement(Self::INTEGRATION_TIME, || {
+            budget.spent.as_secs_f64() * 1000.0
+        });
+        for (id, backlog) in budget.backlogs.drain(..) {
+            diagnostics.add_measurement(id, || backlog as f64);
+        }
+
+        let target = Duration::from_secs_f32(settings.target_frame_time.max(0.0) / 1000.0);
+        let others = time.delta().saturating_sub(budget.spent);
+
+        budget.budget = target.saturating_sub(others).max(Self::MIN_BUDGET);
+        budget.spent = Duration::ZERO;
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+
+use super::{grid::GridCoordinates, Chunk};
+
+/// Recently unloaded chunks, so areas the player returns to are shown again without
+/// generating them anew. Edits made to the chunks are kept as well.
+///
+/// Once more chunks than the capacity are cached, the least recently unloaded ones are dropped.
+#[derive(Resource, Default)]
+pub struct ChunkCache {
+    chunks: HashMap<GridCoordinates, (u64, Chunk)>,
+    /// Cached chunks from least to most recently unloaded. Entries of chunks which have
+    /// been taken out again are skipped, and cleaned up once they pile up.
+    order: VecDeque<(u64, GridCoordinates)>,
+    next_stamp: u64,
+    capacity: usize,
+}
+
+impl ChunkCache {
+    /// Changes how many chunks are kept, dropping the oldest ones if there are too many.
+    /// A capacity of 0 disables the cache.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    pub fn insert(&mut self, coordinates: GridCoordinates, chunk: Chunk) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+
+        self.chunks.insert(coordinates, (stamp, chunk));
+        self.order.push_back((stamp, coordinates));
+        self.evict();
+    }
+
+    /// Takes a chunk out of the cache, e.g. because it's loaded again.
+    pub fn take(&mut self, coordinates: GridCoordinates) -> Option<Chunk> {
+        self.chunks.remove(&coordinates).map(|(_, chunk)| chunk)
+    }
+
+    pub fn contains(&self, coordinates: GridCoordinates) -> bool {
+        self.chunks.contains_key(&coordinates)
+    }
+
+    /// Drops all chunks, e.g. when the world is generated anew.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.order.clear();
+    }
+
+    fn evict(&mut self) {
+        while self.chunks.len() > self.capacity {
+            let Some((stamp, coordinates)) = self.order.pop_front() else {
+                break;
+            };
+
+            if Self::is_current(&self.chunks, stamp, coordinates) {
+                self.chunks.remove(&coordinates);
+            }
+        }
+
+        if self.order.len() > 2 * self.chunks.len() {
+            let chunks = &self.chunks;
+            self.order
+                .retain(|&(stamp, coordinates)| Self::is_current(chunks, stamp, coordinates));
+        }
+    }
+
+    /// Whether the entry in the order belongs to the chunk which is cached at its coordinates.
+    fn is_current(
+        chunks: &HashMap<GridCoordinates, (u64, Chunk)>,
+        stamp: u64,
+        coordinates: GridCoordinates,
+    ) -> bool {
+        chunks
+            .get(&coordinates)
+            .is_some_and(|(current, _)| *current == stamp)
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    cmp,
+    sync::atomic::{AtomicIsize, Ordering},
+};
+
+use bevy::utils::{HashMap, HashSet};
+use dashmap::{DashMap, DashSet};
+
+use crate::utils::ToUsize;
+
+use super::{generator::columns::ColumnGrid, grid::GridCoordinates, BlockType, Chunk};
+
+/// A block placed by a feature, e.g. a tree, at a world position which may lie
+/// in a neighbouring chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockWrite {
+    pub position: [isize; 3],
+    pub block: BlockType,
+}
+
+impl BlockWrite {
+    /// Features never replace terrain, and where they overlap each other the more
+    /// substantial block wins, e.g. a trunk grows through the leaves of another tree.
+    fn priority(block: Option<BlockType>) -> (u8, u8) {
+        let rank = match block {
+            None => 0,
+            Some(BlockType::Flower) => 1,
+            Some(BlockType::Leaves) => 2,
+            Some(BlockType::Log | BlockType::MossyStone) => 3,
+            Some(_) => 4,
+        };
+
+        (rank, block.map_or(0, |block| block as u8))
+    }
+
+    /// Writes the block into the chunk containing it, unless the block already there wins.
+    /// Overlapping writes end up the same no matter in which order they are applied,
+    /// so chunks don't depend on the order their neighbours were decorated in.
+    /// Returns whether the chunk was changed.
+    pub fn apply(self, chunk: &mut Chunk) -> bool {
+        let local = local_position(self.position);
+
+        if Self::priority(Some(self.block)) <= Self::priority(chunk.get(local)) {
+            return false;
+        }
+
+        set_local(chunk, local, Some(self.block));
+
+        true
+    }
+}
+
+fn local_position([x, y, z]: [isize; 3]) -> [isize; 3] {
+    [x.rem_euclid(Chunk::WIDTH), y, z.rem_euclid(Chunk::WIDTH)]
+}
+
+fn set_local(chunk: &mut Chunk, local: [isize; 3], block: Option<BlockType>) {
+    let [x, y, z] = local.map(|n| n.to_usize());
+    chunk.set(x, y, z, block);
+}
+
+/// Keeps track of which chunks have been decorated, and of all feature and structure blocks
+/// written into each chunk. The writes are kept after they have been applied, so chunks which
+/// are unloaded and generated again get the features of their neighbours back. Once a chunk and
+/// everything around it is gone for good, it's [forgotten](Self::forget).
+///
+/// Structures replace terrain and features, including with air. Positions claimed by a structure
+/// are never written by features, so it doesn't matter whether a chunk is decorated before or
+/// after the structures around it are placed.
+#[derive(Default)]
+pub struct Decorations {
+    /// Features of chunks which have been generated but not decorated yet.
+    pending: DashMap<GridCoordinates, Vec<BlockWrite>>,
+    /// Blocks of features, only the one winning at each position is kept.
+    writes: DashMap<GridCoordinates, HashMap<[isize; 3], BlockType>>,
+    structures: DashMap<GridCoordinates, HashMap<[isize; 3], Option<BlockType>>>,
+    decorated: DashSet<GridCoordinates>,
+    /// Start chunks of placed structures, together with the salt of their structure set.
+    placed_structures: DashSet<(GridCoordinates, u32)>,
+    /// Heights of the terrain of generated chunks before anything was written into them.
+    terrain_heights: DashMap<GridCoordinates, ColumnGrid<Option<isize>>>,
+    /// How far placed structures reach from their start chunk, in chunks.
+    structure_reach: AtomicIsize,
+}
+
+impl Decorations {
+    /// Marks a chunk as decorated. Returns `false` if it already was.
+    pub fn mark_decorated(&self, coordinates: GridCoordinates) -> bool {
+        self.decorated.insert(coordinates)
+    }
+
+    pub fn is_decorated(&self, coordinates: GridCoordinates) -> bool {
+        self.decorated.contains(&coordinates)
+    }
+
+    /// Keeps the features of a generated chunk until it's decorated.
+    pub fn set_pending(&self, coordinates: GridCoordinates, features: Vec<BlockWrite>) {
+        if !self.is_decorated(coordinates) {
+            self.pending.insert(coordinates, features);
+        }
+    }
+
+    pub fn take_pending(&self, coordinates: GridCoordinates) -> Vec<BlockWrite> {
+        self.pending
+            .remove(&coordinates)
+            .map(|(_, features)| features)
+            .unwrap_or_default()
+    }
+
+    /// Marks a structure as placed. Returns `false` if it already was.
+    pub fn mark_structure_placed(&self, start: GridCoordinates, salt: u32) -> bool {
+        self.placed_structures.insert((start, salt))
+    }
+
+    pub fn is_structure_placed(&self, start: GridCoordinates, salt: u32) -> bool {
+        self.placed_structures.contains(&(start, salt))
+    }
+
+    pub fn set_terrain_heights(
+        &self,
+        coordinates: GridCoordinates,
+        heights: ColumnGrid<Option<isize>>,
+    ) {
+        self.terrain_heights.insert(coordinates, heights);
+    }
+
+    /// Height of the terrain of a column as it was generated, `None` if its chunk hasn't been recorded.
+    pub fn terrain_height(
+        &self,
+        coordinates: GridCoordinates,
+        x: isize,
+        z: isize,
+    ) -> Option<Option<isize>> {
+        self.terrain_heights
+            .get(&coordinates)
+            .map(|heights| *heights.get(x, z))
+    }
+
+    pub fn push(&self, coordinates: GridCoordinates, write: BlockWrite) {
+        let mut writes = self.writes.entry(coordinates).or_default();
+        let block = writes.entry(write.position).or_insert(write.block);
+        *block = cmp::max_by_key(*block, write.block, |block| {
+            BlockWrite::priority(Some(*block))
+        });
+    }
+
+    /// Records a block of a structure. Where structures overlap, the result doesn't depend
+    /// on the order they are placed in. Returns the block the position ends up with.
+    pub fn push_structure(
+        &self,
+        start: GridCoordinates,
+        coordinates: GridCoordinates,
+        position: [isize; 3],
+        block: Option<BlockType>,
+    ) -> Option<BlockType> {
+        let reach = cmp::max(
+            (coordinates.x - start.x).abs(),
+            (coordinates.z - start.z).abs(),
+        ) / Chunk::WIDTH;
+        self.structure_reach.fetch_max(reach, Ordering::Relaxed);
+
+        let mut blocks = self.structures.entry(coordinates).or_default();
+        let merged = blocks.entry(position).or_insert(block);
+        *merged = cmp::max_by_key(*merged, block, |block| block.map(|block| block as u8));
+
+        *merged
+    }
+
+    /// Writes a block of a feature into a chunk, unless a structure claimed the position.
+    pub fn apply_feature(
+        &self,
+        coordinates: GridCoordinates,
+        write: BlockWrite,
+        chunk: &mut Chunk,
+    ) -> bool {
+        let claimed = self
+            .structures
+            .get(&coordinates)
+            .is_some_and(|blocks| blocks.contains_key(&write.position));
+
+        !claimed && write.apply(chunk)
+    }
+
+    /// Applies all writes into a chunk. Returns whether the chunk was changed.
+    pub fn apply(&self, coordinates: GridCoordinates, chunk: &mut Chunk) -> bool {
+        let mut changed = false;
+
+        if let Some(writes) = self.writes.get(&coordinates) {
+            for (&position, &block) in writes.iter() {
+                changed |= self.apply_feature(coordinates, BlockWrite { position, block }, chunk);
+            }
+        }
+
+        if let Some(blocks) = self.structures.get(&coordinates) {
+            for (position, block) in blocks.iter() {
+                let local = local_position(*position);
+
+                if chunk.get(local) != *block {
+                    set_local(chunk, local, *block);
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Chunks anything is recorded for, including the start chunks of placed structures.
+    pub fn recorded_chunks(&self) -> HashSet<GridCoordinates> {
+        let mut chunks = HashSet::new();
+        chunks.extend(self.pending.iter().map(|entry| *entry.key()));
+        chunks.extend(self.writes.iter().map(|entry| *entry.key()));
+        chunks.extend(self.structures.iter().map(|entry| *entry.key()));
+        chunks.extend(self.terrain_heights.iter().map(|entry| *entry.key()));
+        chunks.extend(self.decorated.iter().map(|coordinates| *coordinates));
+        chunks.extend(self.placed_structures.iter().map(|placed| placed.0));
+
+        chunks
+    }
+
+    /// How far in chunks a chunk may get blocks from others, by features of its direct
+    /// neighbours or by structures starting further away.
+    pub fn reach(&self) -> isize {
+        self.structure_reach.load(Ordering::Relaxed).max(1)
+    }
+
+    /// Forgets everything recorded for a chunk. Its neighbours are marked as not decorated and
+    /// the structures which may reach into it as not placed, so they write their blocks again
+    /// once they're generated again, which ends up the same as the first time.
+    ///
+    /// Chunks within [`Self::reach`] of the chunk must not be loaded, since they wouldn't
+    /// be decorated again.
+    pub fn forget(&self, coordinates: GridCoordinates) {
+        self.pending.remove(&coordinates);
+        self.writes.remove(&coordinates);
+        self.structures.remove(&coordinates);
+        self.terrain_heights.remove(&coordinates);
+
+        for x in -1..=1 {
+            for z in -1..=1 {
+                self.decorated
+                    .remove(&(coordinates + [x * Chunk::WIDTH, 0, z * Chunk::WIDTH]));
+            }
+        }
+
+        let reach = self.reach() * Chunk::WIDTH;
+        self.placed_structures.retain(|(start, _)| {
+            (start.x - coordinates.x).abs() > reach || (start.z - coordinates.z).abs() > reach
+        });
+    }
+
+    pub fn clear(&self) {
+        self.pending.clear();
+        self.writes.clear();
+        self.structures.clear();
+        self.decorated.clear();
+        self.placed_structures.clear();
+        self.terrain_heights.clear();
+    }
+}
@@ -0,0 +1,82 @@
+use bevy::{
+    math::Affine3A,
+    prelude::*,
+    render::primitives::{Aabb, Frustum},
+};
+
+use crate::vec3;
+
+use super::{grid::GridCoordinates, Chunk};
+
+/// Chunks waiting for a generation task, so the ones the player needs first are
+/// generated first instead of all of them competing for the task pool at once.
+#[derive(Resource, Default)]
+pub struct GenerationQueue {
+    /// Chunks with their priority, sorted so the most urgent one is last.
+    chunks: Vec<(f32, GridCoordinates)>,
+    sorted: bool,
+}
+
+impl GenerationQueue {
+    /// Chunks outside of the view are generated as if they were this many times farther away.
+    const OUT_OF_VIEW_PENALTY: f32 = 2.0;
+
+    pub fn push(&mut self, coordinates: GridCoordinates) {
+        self.chunks.push((f32::INFINITY, coordinates));
+        self.sorted = false;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Whether chunks have been added since the queue has last been sorted.
+    pub fn is_sorted(&self) -> bool {
+        self.sorted
+    }
+
+    /// Orders the chunks by their distance to the viewer, preferring chunks in its view.
+    pub fn sort(&mut self, viewer: Vec3, frustum: Option<&Frustum>) {
+        for (priority, coordinates) in &mut self.chunks {
+            *priority = Self::priority(*coordinates, viewer, frustum);
+        }
+
+        self.chunks
+            .sort_unstable_by(|(a, _), (b, _)| b.total_cmp(a));
+        self.sorted = true;
+    }
+
+    /// Drops the chunks for which `keep` returns `false`.
+    pub fn retain(&mut self, mut keep: impl FnMut(GridCoordinates) -> bool) {
+        self.chunks.retain(|(_, coordinates)| keep(*coordinates));
+    }
+
+    /// Takes the most urgent chunk.
+    pub fn pop(&mut self) -> Option<GridCoordinates> {
+        self.chunks.pop().map(|(_, coordinates)| coordinates)
+    }
+
+    /// Lower values are more urgent.
+    fn priority(coordinates: GridCoordinates, viewer: Vec3, frustum: Option<&Frustum>) -> f32 {
+        let min = Vec3::from(coordinates);
+        let max = min + vec3!(Chunk::WIDTH, Chunk::HEIGHT, Chunk::WIDTH);
+        let center = (min + max) / 2.0;
+        let distance = Vec2::new(center.x - viewer.x, center.z - viewer.z).length();
+
+        // without a frustum, e.g. before the first frame has been rendered, every chunk is in view
+        let in_view = frustum.is_none_or(|frustum| {
+            frustum.intersects_obb(
+                &Aabb::from_min_max(min, max),
+                &Affine3A::IDENTITY,
+                false,
+                false,
+            )
+        });
+
+        if in_view {
+            distance
+        } else {
+            distance * Self::OUT_OF_VIEW_PENALTY
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::ops::{Add, Mul};
+
+use bevy::prelude::*;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+
+use crate::game::chunk::BlockType;
+
+use super::derive_seed;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum Biome {
+    Ocean,
+    Plains,
+    Desert,
+    Mountains,
+    Tundra,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 5] = [
+        Biome::Ocean,
+        Biome::Plains,
+        Biome::Desert,
+        Biome::Mountains,
+        Biome::Tundra,
+    ];
+
+    /// Mountain surfaces above this height are covered in snow.
+    pub const SNOW_LINE: usize = 130;
+
+    pub fn from_climate(climate: Climate) -> Self {
+        if climate.continentalness < -0.25 {
+            Biome::Ocean
+        } else if climate.continentalness > 0.35 {
+            Biome::Mountains
+        } else if climate.temperature < -0.25 {
+            Biome::Tundra
+        } else if climate.temperature > 0.25 && climate.humidity < 0.1 {
+            Biome::Desert
+        } else {
+            Biome::Plains
+        }
+    }
+
+    pub fn shape(self) -> HeightShape {
+        use Biome::*;
+
+        let (base, amplitude, ridges, roughness) = match self {
+            Ocean => (40.0, 8.0, 0.0, 2.0),
+            Plains => (66.0, 6.0, 0.0, 2.0),
+            Desert => (68.0, 10.0, 0.0, 1.0),
+            Mountains => (80.0, 90.0, 1.0, 14.0),
+            Tundra => (70.0, 12.0, 0.3, 4.0),
+        };
+
+        HeightShape {
+            base,
+            amplitude,
+            ridges,
+            roughness,
+        }
+    }
+
+    /// Top block of a column with the given height.
+    pub fn surface(self, height: usize) -> BlockType {
+        use Biome::*;
+
+        match self {
+            Ocean => BlockType::Gravel,
+            Plains => BlockType::Grass,
+            Desert => BlockType::Sand,
+            Mountains if height >= Self::SNOW_LINE => BlockType::Snow,
+            Mountains => BlockType::Stone,
+            Tundra => BlockType::Snow,
+        }
+    }
+
+    /// Block between the surface and the stone below.
+    pub fn subsurface(self) -> BlockType {
+        use Biome::*;
+
+        match self {
+            Ocean => BlockType::Sand,
+            Plains | Tundra => BlockType::Dirt,
+            Desert => BlockType::Sand,
+            Mountains => BlockType::Stone,
+        }
+    }
+
+    /// Block of beaches and lake shores.
+    pub fn shore(self) -> BlockType {
+        use Biome::*;
+
+        match self {
+            Ocean | Plains | Desert => BlockType::Sand,
+            Mountains | Tundra => BlockType::Gravel,
+        }
+    }
+
+    pub fn subsurface_depth(self) -> usize {
+        use Biome::*;
+
+        match self {
+            Ocean => 2,
+            Plains | Tundra => 3,
+            Desert => 5,
+            Mountains => 0,
+        }
+    }
+
+    pub fn decorations(self) -> DecorationRules {
+        use Biome::*;
+
+        match self {
+            Ocean => DecorationRules::default(),
+            Plains => DecorationRules {
+                trees: 0.004,
+                boulders: 0.0005,
+                flowers: 0.03,
+            },
+            Desert => DecorationRules {
+                boulders: 0.0002,
+                ..default()
+            },
+            Mountains => DecorationRules {
+                trees: 0.001,
+                boulders: 0.003,
+                ..default()
+            },
+            Tundra => DecorationRules {
+                trees: 0.0015,
+                boulders: 0.001,
+                ..default()
+            },
+        }
+    }
+}
+
+/// Climate values of a column, roughly within `-1.0..=1.0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Climate {
+    pub temperature: f64,
+    pub humidity: f64,
+    /// Low values are far out at sea, high values deep inland.
+    pub continentalness: f64,
+}
+
+/// Parameters of the height function of a biome. Shapes are blended linearly across
+/// biome borders, so all parameters have to make sense when interpolated.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HeightShape {
+    pub base: f64,
+    /// Maximum distance of the terrain from the base height.
+    pub amplitude: f64,
+    /// How much of the terrain comes from ridged noise instead of rolling hills, in `0.0..=1.0`.
+    pub ridges: f64,
+    /// Strength in blocks of the 3D noise that adds overhangs and arches to the surface.
+    pub roughness: f64,
+}
+
+impl HeightShape {
+    /// Computes the height from rolling hill noise in `-1.0..=1.0` and ridge noise in `0.0..=1.0`.
+    pub fn height(self, hills: f64, ridges: f64) -> f64 {
+        self.base + self.amplitude * ((1.0 - self.ridges) * hills + self.ridges * ridges)
+    }
+}
+
+impl Add for HeightShape {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            base: self.base + rhs.base,
+            amplitude: self.amplitude + rhs.amplitude,
+            ridges: self.ridges + rhs.ridges,
+            roughness: self.roughness + rhs.roughness,
+        }
+    }
+}
+
+impl Mul<f64> for HeightShape {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Self {
+            base: self.base * rhs,
+            amplitude: self.amplitude * rhs,
+            ridges: self.ridges * rhs,
+            roughness: self.roughness * rhs,
+        }
+    }
+}
+
+/// Chances per surface column of a biome to be decorated with a feature.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DecorationRules {
+    pub trees: f32,
+    pub boulders: f32,
+    pub flowers: f32,
+}
+
+/// Picks biomes from temperature, humidity and continentalness noise maps.
+pub struct BiomeSource {
+    temperature: Fbm<Perlin>,
+    humidity: Fbm<Perlin>,
+    continentalness: Fbm<Perlin>,
+}
+
+impl BiomeSource {
+    pub fn new(seed: u32) -> Self {
+        let climate = |salt: u32, frequency: f64| {
+            Fbm::<Perlin>::new(derive_seed(seed, salt))
+                .set_octaves(3)
+                .set_frequency(frequency)
+        };
+
+        Self {
+            temperature: climate(1, 1.0 / 800.0),
+            humidity: climate(2, 1.0 / 700.0),
+            continentalness: climate(3, 1.0 / 1200.0),
+        }
+    }
+
+    pub fn climate_at(&self, x: isize, z: isize) -> Climate {
+        let point = [x as f64, z as f64];
+
+        Climate {
+            temperature: self.temperature.get(point),
+            humidity: self.humidity.get(point),
+            continentalness: self.continentalness.get(point),
+        }
+    }
+
+    pub fn biome_at(&self, x: isize, z: isize) -> Biome {
+        Biome::from_climate(self.climate_at(x, z))
+    }
+}
@@ -0,0 +1,58 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use super::derive_seed;
+
+/// Carves tunnels and large caverns out of the terrain.
+///
+/// Tunnels follow the lines where two noise fields are both close to zero, which
+/// gives long winding worm-like passages. Caverns are where a third, flattened noise
+/// field is high.
+pub struct CaveCarver {
+    tunnels: [Perlin; 2],
+    caverns: Fbm<Perlin>,
+}
+
+impl CaveCarver {
+    /// Caves close up when getting closer to the surface than this.
+    const SURFACE_DEPTH: f64 = 12.0;
+    /// Caves don't cut through the bottommost layers of the world.
+    const FLOOR: f64 = 6.0;
+    const TUNNEL_FREQUENCY: f64 = 1.0 / 64.0;
+    const TUNNEL_RADIUS: f64 = 0.07;
+    const CAVERN_THRESHOLD: f64 = 0.45;
+    const CAVERN_CEILING: f64 = 56.0;
+
+    pub fn new(seed: u32) -> Self {
+        Self {
+            tunnels: [
+                Perlin::new(derive_seed(seed, 6)),
+                Perlin::new(derive_seed(seed, 7)),
+            ],
+            caverns: Fbm::<Perlin>::new(derive_seed(seed, 8))
+                .set_octaves(2)
+                .set_frequency(1.0 / 96.0),
+        }
+    }
+
+    /// Density of the caves at a position, negative within caves. `surface` is the height
+    /// of the terrain in the column.
+    pub fn density(&self, [x, y, z]: [isize; 3], surface: f64) -> f64 {
+        let (x, y, z) = (x as f64, y as f64, z as f64);
+
+        let point = [x, y, z].map(|n| n * Self::TUNNEL_FREQUENCY);
+        let tunnel = self.tunnels[0]
+            .get(point)
+            .abs()
+            .max(self.tunnels[1].get(point).abs());
+        let tunnels = (tunnel - Self::TUNNEL_RADIUS) * 40.0;
+
+        // squashed vertically, so caverns are wide rather than tall
+        let cavern = self.caverns.get([x, y * 2.0, z]);
+        let caverns =
+            (Self::CAVERN_THRESHOLD - cavern) * 30.0 + (y - Self::CAVERN_CEILING).max(0.0) * 2.0;
+
+        let guard = (y - (surface - Self::SURFACE_DEPTH)).max(0.0) + (Self::FLOOR - y).max(0.0);
+
+        tunnels.min(caverns) + guard * 4.0
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use dashmap::DashMap;
+use noise::NoiseFn;
+
+use crate::utils::ToUsize;
+
+use super::biome::Biome;
+
+/// Values of all columns in a rectangle of the world, stored row by row along the x axis.
+///
+/// Generators fill grids for whole chunk footprints, so work shared by the columns, like
+/// blending biomes and eroding the area, is done once, and later passes can reuse the values
+/// without evaluating the noise again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnGrid<T> {
+    min: [isize; 2],
+    width: usize,
+    values: Vec<T>,
+}
+
+impl<T> ColumnGrid<T> {
+    /// Covers all columns from `min` to `max`, inclusive. `value` is called row by row.
+    pub fn from_fn(
+        min: [isize; 2],
+        max: [isize; 2],
+        mut value: impl FnMut(isize, isize) -> T,
+    ) -> Self {
+        let values = (min[1]..=max[1])
+            .flat_map(|z| (min[0]..=max[0]).map(move |x| (x, z)))
+            .map(|(x, z)| value(x, z))
+            .collect();
+
+        Self {
+            min,
+            width: (max[0] - min[0] + 1).max(0).to_usize(),
+            values,
+        }
+    }
+
+    /// Value of a column in world coordinates, which has to lie within the grid.
+    pub fn get(&self, x: isize, z: isize) -> &T {
+        let (x, z) = ((x - self.min[0]).to_usize(), (z - self.min[1]).to_usize());
+
+        &self.values[z * self.width + x]
+    }
+
+    pub fn min(&self) -> [isize; 2] {
+        self.min
+    }
+
+    /// Values row by row along the x axis.
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<T> {
+        self.values
+    }
+}
+
+impl ColumnGrid<f64> {
+    /// Evaluates a 2D noise function for all columns from `min` to `max`, inclusive.
+    ///
+    /// The noise is still evaluated one column at a time, as noise functions only take
+    /// single points.
+    pub fn sample(noise: &impl NoiseFn<f64, 2>, min: [isize; 2], max: [isize; 2]) -> Self {
+        Self::from_fn(min, max, |x, z| noise.get([x as f64, z as f64]))
+    }
+}
+
+/// Surface heights and biomes of the columns of a chunk, kept after its terrain has been
+/// generated so later passes like decoration don't have to evaluate the noise again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkColumns {
+    /// Height of the surface before overhangs and caves, including lakes and rivers.
+    pub heights: ColumnGrid<f32>,
+    /// Biomes, for generators that have biomes.
+    pub biomes: Option<ColumnGrid<Biome>>,
+}
+
+/// The columns of recently generated chunks, by the position of the chunk.
+#[derive(Default)]
+pub struct ColumnCache {
+    chunks: DashMap<[isize; 2], Arc<ChunkColumns>>,
+    /// Cached chunks, from the oldest to the newest.
+    order: Mutex<VecDeque<[isize; 2]>>,
+}
+
+impl ColumnCache {
+    /// Number of chunks kept in the cache, enough for the loaded area at the largest render distance.
+    const CACHED_CHUNKS: usize = 65 * 65;
+
+    pub fn get(&self, position: [isize; 3]) -> Option<Arc<ChunkColumns>> {
+        self.chunks
+            .get(&[position[0], position[2]])
+            .map(|columns| columns.clone())
+    }
+
+    pub fn insert(&self, position: [isize; 3], columns: ChunkColumns) {
+        let key = [position[0], position[2]];
+        let mut order = self.order.lock().unwrap();
+
+        if self.chunks.insert(key, Arc::new(columns)).is_none() {
+            order.push_back(key);
+        }
+
+        // evicted chunks are simply computed again when they are generated the next time
+        while order.len() > Self::CACHED_CHUNKS {
+            if let Some(oldest) = order.pop_front() {
+                self.chunks.remove(&oldest);
+            }
+        }
+    }
+}
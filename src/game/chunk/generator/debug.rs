@@ -0,0 +1,52 @@
+use crate::{
+    game::chunk::{BlockType, Chunk},
+    utils::ToUsize,
+};
+
+use super::TerrainGenerator;
+
+/// Lays out every block type in a grid, starting at the world origin,
+/// with a free block between neighbours so all faces are visible.
+pub struct DebugTerrain;
+
+impl DebugTerrain {
+    pub const HEIGHT: isize = 70;
+    const SPACING: isize = 2;
+
+    /// Returns the block placed at the given world column, if any.
+    pub fn block_at(x: isize, z: isize) -> Option<BlockType> {
+        let columns = (BlockType::ALL.len() as f32).sqrt().ceil() as isize;
+
+        if x < 0 || z < 0 || x % Self::SPACING != 0 || z % Self::SPACING != 0 {
+            return None;
+        }
+
+        let (column, row) = (x / Self::SPACING, z / Self::SPACING);
+
+        if column >= columns {
+            return None;
+        }
+
+        BlockType::ALL
+            .get((row * columns + column).to_usize())
+            .copied()
+    }
+}
+
+impl TerrainGenerator for DebugTerrain {
+    fn generate_chunk(&self, position: [isize; 3]) -> Chunk {
+        let mut chunk = Chunk::new(position.into());
+
+        for x in 0..Chunk::WIDTH.to_usize() {
+            for z in 0..Chunk::WIDTH.to_usize() {
+                if let Some(block) =
+                    Self::block_at(position[0] + x as isize, position[2] + z as isize)
+                {
+                    chunk.set(x, Self::HEIGHT.to_usize(), z, Some(block));
+                }
+            }
+        }
+
+        chunk
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{game::chunk::Chunk, utils::ToUsize};
+
+/// Values of a density function across a chunk, sampled on a coarse grid and
+/// interpolated trilinearly in between. Positive densities are solid.
+///
+/// Samples lie on the borders of the chunk too, so neighbouring chunks interpolate
+/// between the same values and line up seamlessly.
+pub struct DensityGrid {
+    samples: Vec<f64>,
+}
+
+impl DensityGrid {
+    pub const CELL_WIDTH: isize = 4;
+    pub const CELL_HEIGHT: isize = 8;
+
+    const SAMPLES_X: usize = (Chunk::WIDTH / Self::CELL_WIDTH + 1) as usize;
+    const SAMPLES_Y: usize = (Chunk::HEIGHT / Self::CELL_HEIGHT + 1) as usize;
+
+    /// Calls `column` for every sampled column with its world position and the samples
+    /// to fill, from the bottom up. Sample `i` is at height [`DensityGrid::sample_height`].
+    pub fn new(position: [isize; 3], mut column: impl FnMut(isize, isize, &mut [f64])) -> Self {
+        let mut samples = vec![0.0; Self::SAMPLES_X * Self::SAMPLES_X * Self::SAMPLES_Y];
+
+        for (index, column_samples) in samples.chunks_mut(Self::SAMPLES_Y).enumerate() {
+            let x = (index / Self::SAMPLES_X) as isize * Self::CELL_WIDTH;
+            let z = (index % Self::SAMPLES_X) as isize * Self::CELL_WIDTH;
+
+            column(position[0] + x, position[2] + z, column_samples);
+        }
+
+        Self { samples }
+    }
+
+    pub fn sample_height(index: usize) -> isize {
+        index as isize * Self::CELL_HEIGHT
+    }
+
+    /// Interpolated density at a position within the chunk.
+    pub fn get(&self, x: usize, y: usize, z: usize) -> f64 {
+        let (cell_width, cell_height) = (Self::CELL_WIDTH.to_usize(), Self::CELL_HEIGHT.to_usize());
+        let [cx, cy, cz] = [x / cell_width, y / cell_height, z / cell_width];
+        let tx = (x % cell_width) as f64 / cell_width as f64;
+        let ty = (y % cell_height) as f64 / cell_height as f64;
+        let tz = (z % cell_width) as f64 / cell_width as f64;
+
+        let sample = |dx: usize, dy: usize, dz: usize| {
+            self.samples[((cx + dx) * Self::SAMPLES_X + cz + dz) * Self::SAMPLES_Y + cy + dy]
+        };
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+
+        let x00 = lerp(sample(0, 0, 0), sample(1, 0, 0), tx);
+        let x10 = lerp(sample(0, 1, 0), sample(1, 1, 0), tx);
+        let x01 = lerp(sample(0, 0, 1), sample(1, 0, 1), tx);
+        let x11 = lerp(sample(0, 1, 1), sample(1, 1, 1), tx);
+
+        lerp(lerp(x00, x10, ty), lerp(x01, x11, ty), tz)
+    }
+}
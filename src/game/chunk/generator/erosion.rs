@@ -0,0 +1,304 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use dashmap::DashMap;
+
+use crate::utils::ToUsize;
+
+use super::random::Random;
+
+/// Region of the world at a version of the heightmap.
+type RegionKey = (u32, [isize; 2]);
+
+/// Height changes of a region, including its margin.
+type RegionOffsets = Arc<OnceLock<Vec<f32>>>;
+
+/// Hydraulic erosion of the terrain's heightmap. Droplets of water run downhill, carving
+/// valleys where they speed up and depositing the sediment where they slow down.
+///
+/// The world is divided into square regions which are eroded independently and cached.
+/// Each region is simulated with a margin reaching into its neighbours, and where the
+/// margins overlap the results are blended, so there are no seams at region borders.
+pub struct Erosion {
+    seed: u32,
+    /// Offsets and weights of the cells a droplet erodes around its position.
+    brush: Vec<([isize; 2], f64)>,
+    regions: DashMap<RegionKey, RegionOffsets>,
+    /// Cached regions, from the oldest to the newest.
+    order: Mutex<VecDeque<RegionKey>>,
+}
+
+impl Erosion {
+    pub const REGION: isize = 128;
+    /// Width of the area around a region which is simulated with it. At most half a region.
+    const MARGIN: isize = 32;
+    const SIZE: isize = Self::REGION + 2 * Self::MARGIN;
+    /// Number of regions kept in the cache.
+    const CACHED_REGIONS: usize = 256;
+
+    const DROPLETS: usize = 24_000;
+    const LIFETIME: usize = 32;
+    const BRUSH_RADIUS: isize = 3;
+    /// How much droplets keep their direction instead of following the slope.
+    const INERTIA: f64 = 0.05;
+    /// Sediment a droplet can carry, relative to its speed, water and the slope.
+    const CAPACITY: f64 = 4.0;
+    const MIN_CAPACITY: f64 = 0.01;
+    const ERODE_SPEED: f64 = 0.3;
+    const DEPOSIT_SPEED: f64 = 0.3;
+    const EVAPORATION: f64 = 0.02;
+    const GRAVITY: f64 = 4.0;
+    /// Erosion never moves the terrain further than this, in blocks.
+    const MAX_OFFSET: f64 = 16.0;
+
+    pub fn new(seed: u32) -> Self {
+        let radius = Self::BRUSH_RADIUS;
+        let mut brush: Vec<_> = (-radius..=radius)
+            .flat_map(|z| (-radius..=radius).map(move |x| [x, z]))
+            .map(|[x, z]| {
+                let distance = ((x * x + z * z) as f64).sqrt();
+                ([x, z], radius as f64 - distance)
+            })
+            .filter(|(_, weight)| *weight > 0.0)
+            .collect();
+        let total: f64 = brush.iter().map(|(_, weight)| weight).sum();
+
+        for (_, weight) in &mut brush {
+            *weight /= total;
+        }
+
+        Self {
+            seed,
+            brush,
+            regions: DashMap::new(),
+            order: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Erodes all regions affecting the area from `min` to `max`, inclusive, unless they
+    /// are cached already.
+    ///
+    /// `heightmap` receives the minimum corner and width of a square and returns the heights
+    /// of the terrain before erosion within it, row by row along the x axis. `version` tells
+    /// apart different heightmaps of the same world, e.g. at different scales.
+    pub fn area(
+        &self,
+        version: u32,
+        min: [isize; 2],
+        max: [isize; 2],
+        heightmap: impl Fn([isize; 2], usize) -> Vec<f64>,
+    ) -> ErodedArea {
+        let [start_x, start_z] = min.map(|n| (n - Self::MARGIN).div_euclid(Self::REGION));
+        let [end_x, end_z] = max.map(|n| (n + Self::MARGIN).div_euclid(Self::REGION));
+
+        let regions = (start_x..=end_x)
+            .flat_map(|x| (start_z..=end_z).map(move |z| [x, z]))
+            .map(|region| {
+                let offsets = self.region(version, region);
+                offsets.get_or_init(|| self.erode(region, &heightmap));
+                (region, offsets)
+            })
+            .collect();
+
+        ErodedArea { regions }
+    }
+
+    fn region(&self, version: u32, region: [isize; 2]) -> RegionOffsets {
+        let key = (version, region);
+
+        if let Some(offsets) = self.regions.get(&key) {
+            return offsets.clone();
+        }
+
+        let offsets = self.regions.entry(key).or_default().clone();
+        let mut order = self.order.lock().unwrap();
+
+        if !order.contains(&key) {
+            order.push_back(key);
+        }
+
+        // evicted regions are simply eroded again, the result is the same
+        while order.len() > Self::CACHED_REGIONS {
+            if let Some(oldest) = order.pop_front() {
+                self.regions.remove(&oldest);
+            }
+        }
+
+        offsets
+    }
+
+    /// Simulates the droplets of a region and returns how far each column was moved.
+    fn erode(
+        &self,
+        [region_x, region_z]: [isize; 2],
+        heightmap: impl Fn([isize; 2], usize) -> Vec<f64>,
+    ) -> Vec<f32> {
+        let origin = [region_x, region_z].map(|n| n * Self::REGION - Self::MARGIN);
+        let original = heightmap(origin, Self::SIZE.to_usize());
+        let mut heights = original.clone();
+        let mut random = Random::at(self.seed, 13, &[region_x, region_z]);
+
+        for _ in 0..Self::DROPLETS {
+            self.droplet(&mut heights, &mut random);
+        }
+
+        heights
+            .iter()
+            .zip(&original)
+            .map(|(eroded, height)| (eroded - height).clamp(-Self::MAX_OFFSET, Self::MAX_OFFSET))
+            .map(|offset| offset as f32)
+            .collect()
+    }
+
+    fn droplet(&self, heights: &mut [f64], random: &mut Random) {
+        let limit = (Self::SIZE - 1) as f64;
+        let mut position = [random.range_f64(0.0..limit), random.range_f64(0.0..limit)];
+        let mut direction = [0.0; 2];
+        let mut speed = 1.0;
+        let mut water = 1.0;
+        let mut sediment = 0.0;
+
+        for _ in 0..Self::LIFETIME {
+            let (height, gradient) = Self::sample(heights, position);
+
+            direction = [0, 1].map(|axis| {
+                direction[axis] * Self::INERTIA - gradient[axis] * (1.0 - Self::INERTIA)
+            });
+            let length = direction[0].hypot(direction[1]);
+
+            if length < f64::EPSILON {
+                break;
+            }
+
+            let previous = position;
+            position = [0, 1].map(|axis| position[axis] + direction[axis] / length);
+
+            if position.iter().any(|n| !(0.0..limit).contains(n)) {
+                break;
+            }
+
+            let delta = Self::sample(heights, position).0 - height;
+            let capacity = (-delta * speed * water * Self::CAPACITY).max(Self::MIN_CAPACITY);
+
+            if sediment > capacity || delta > 0.0 {
+                // fill up the pit the droplet ran into, or drop what it can't carry anymore
+                let amount = if delta > 0.0 {
+                    delta.min(sediment)
+                } else {
+                    (sediment - capacity) * Self::DEPOSIT_SPEED
+                };
+
+                sediment -= amount;
+                Self::deposit(heights, previous, amount);
+            } else {
+                // never dig deeper than the droplet descends, or it would carve pits
+                let amount = ((capacity - sediment) * Self::ERODE_SPEED).min(-delta);
+
+                sediment += amount;
+                self.erode_around(heights, previous, amount);
+            }
+
+            speed = (speed * speed - delta * Self::GRAVITY).max(0.0).sqrt();
+            water *= 1.0 - Self::EVAPORATION;
+        }
+    }
+
+    /// Interpolated height and gradient at a position within the heightmap.
+    fn sample(heights: &[f64], [x, z]: [f64; 2]) -> (f64, [f64; 2]) {
+        let index = Self::index([x as isize, z as isize]);
+        let size = Self::SIZE.to_usize();
+        let (tx, tz) = (x.fract(), z.fract());
+
+        let near_west = heights[index];
+        let near_east = heights[index + 1];
+        let far_west = heights[index + size];
+        let far_east = heights[index + size + 1];
+
+        let gradient = [
+            (near_east - near_west) * (1.0 - tz) + (far_east - far_west) * tz,
+            (far_west - near_west) * (1.0 - tx) + (far_east - near_east) * tx,
+        ];
+        let height = (near_west * (1.0 - tx) + near_east * tx) * (1.0 - tz)
+            + (far_west * (1.0 - tx) + far_east * tx) * tz;
+
+        (height, gradient)
+    }
+
+    /// Spreads sediment over the four corners around a position.
+    fn deposit(heights: &mut [f64], [x, z]: [f64; 2], amount: f64) {
+        let index = Self::index([x as isize, z as isize]);
+        let size = Self::SIZE.to_usize();
+        let (tx, tz) = (x.fract(), z.fract());
+
+        heights[index] += amount * (1.0 - tx) * (1.0 - tz);
+        heights[index + 1] += amount * tx * (1.0 - tz);
+        heights[index + size] += amount * (1.0 - tx) * tz;
+        heights[index + size + 1] += amount * tx * tz;
+    }
+
+    fn erode_around(&self, heights: &mut [f64], [x, z]: [f64; 2], amount: f64) {
+        let center = [x as isize, z as isize];
+
+        for ([offset_x, offset_z], weight) in &self.brush {
+            let cell = [center[0] + offset_x, center[1] + offset_z];
+
+            if cell.iter().all(|n| (0..Self::SIZE).contains(n)) {
+                heights[Self::index(cell)] -= amount * weight;
+            }
+        }
+    }
+
+    fn index([x, z]: [isize; 2]) -> usize {
+        (z * Self::SIZE + x).to_usize()
+    }
+
+    /// Share of a region in the height of a column on one axis, given the position of the
+    /// column relative to the region. Within the overlapping margins the shares of both
+    /// regions smoothly fade into each other and add up to one.
+    fn weight(local: isize) -> f64 {
+        let overlap = (2 * Self::MARGIN) as f64;
+        let fade = |t: f64| t * t * (3.0 - 2.0 * t);
+
+        if !(-Self::MARGIN..Self::REGION + Self::MARGIN).contains(&local) {
+            0.0
+        } else if local < Self::MARGIN {
+            fade((local + Self::MARGIN) as f64 / overlap)
+        } else if local >= Self::REGION - Self::MARGIN {
+            fade((Self::REGION + Self::MARGIN - local) as f64 / overlap)
+        } else {
+            1.0
+        }
+    }
+}
+
+/// The eroded regions around an area, e.g. a chunk.
+pub struct ErodedArea {
+    regions: Vec<([isize; 2], RegionOffsets)>,
+}
+
+impl ErodedArea {
+    /// How far the erosion moved the terrain in a column within the area.
+    pub fn offset_at(&self, x: isize, z: isize) -> f64 {
+        self.regions
+            .iter()
+            .map(|([region_x, region_z], offsets)| {
+                let local = [
+                    x - region_x * Erosion::REGION,
+                    z - region_z * Erosion::REGION,
+                ];
+                let weight = Erosion::weight(local[0]) * Erosion::weight(local[1]);
+
+                if weight == 0.0 {
+                    return 0.0;
+                }
+
+                let index = Erosion::index(local.map(|n| n + Erosion::MARGIN));
+                let offset = offsets.get().and_then(|offsets| offsets.get(index));
+
+                weight * offset.copied().unwrap_or_default() as f64
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,90 @@
+use crate::game::chunk::{decoration::BlockWrite, BlockType};
+
+use super::{biome::DecorationRules, random::Random};
+
+/// Things placed on top of the terrain once it has been generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Tree,
+    Boulder,
+    Flower,
+}
+
+impl Feature {
+    /// Highest a feature reaches above the block it's placed at.
+    pub const MAX_HEIGHT: isize = 8;
+
+    /// Picks the feature of a column from a random number in `0.0..1.0`.
+    pub fn pick(rules: DecorationRules, roll: f64) -> Option<Self> {
+        let chances = [
+            (Feature::Tree, rules.trees),
+            (Feature::Boulder, rules.boulders),
+            (Feature::Flower, rules.flowers),
+        ];
+        let mut threshold = 0.0;
+
+        chances.into_iter().find_map(|(feature, chance)| {
+            threshold += f64::from(chance);
+            (roll < threshold).then_some(feature)
+        })
+    }
+
+    pub fn grows_on(self, ground: BlockType) -> bool {
+        use BlockType::*;
+
+        match self {
+            Feature::Tree => matches!(ground, Grass | Dirt | Snow),
+            Feature::Boulder => matches!(ground, Grass | Dirt | Snow | Stone | Sand | Gravel),
+            Feature::Flower => ground == Grass,
+        }
+    }
+
+    /// Places the feature with its base at `origin`, the block above the ground.
+    pub fn place(self, [x, y, z]: [isize; 3], random: &mut Random, writes: &mut Vec<BlockWrite>) {
+        let mut write = |position, block| writes.push(BlockWrite { position, block });
+
+        match self {
+            Feature::Tree => {
+                let trunk = random.range(4..7);
+                let top = y + trunk - 1;
+
+                for y in y..=top {
+                    write([x, y, z], BlockType::Log);
+                }
+
+                // two wide layers around the top of the trunk, two narrow ones above
+                for dy in -2..=1 {
+                    let radius: isize = if dy < 0 { 2 } else { 1 };
+
+                    for dx in -radius..=radius {
+                        for dz in -radius..=radius {
+                            let is_corner = dx.abs() == radius && dz.abs() == radius;
+
+                            if is_corner && (dy == 1 || random.chance(0.5)) {
+                                continue;
+                            }
+
+                            write([x + dx, top + dy, z + dz], BlockType::Leaves);
+                        }
+                    }
+                }
+            }
+            Feature::Boulder => {
+                let radius = random.range_f64(1.0..2.2);
+                let extent = radius.ceil() as isize;
+
+                // the lower half is buried, terrain is never replaced by features
+                for dx in -extent..=extent {
+                    for dy in -extent..=extent {
+                        for dz in -extent..=extent {
+                            if ((dx * dx + dy * dy + dz * dz) as f64) <= radius * radius {
+                                write([x + dx, y + dy, z + dz], BlockType::MossyStone);
+                            }
+                        }
+                    }
+                }
+            }
+            Feature::Flower => write([x, y, z], BlockType::Flower),
+        }
+    }
+}
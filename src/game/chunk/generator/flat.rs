@@ -0,0 +1,48 @@
+use bevy::prelude::*;
+
+use crate::{
+    game::chunk::{BlockType, Chunk},
+    utils::ToUsize,
+};
+
+use super::TerrainGenerator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct SuperflatLayer {
+    pub block: BlockType,
+    pub thickness: usize,
+}
+
+/// Flat world built from horizontal layers, stacked from the bottom of the world upwards.
+pub struct SuperflatTerrain {
+    /// Block of every level, from the bottom of the world to the top layer.
+    column: Vec<BlockType>,
+}
+
+impl SuperflatTerrain {
+    pub fn new(layers: Vec<SuperflatLayer>) -> Self {
+        let column = layers
+            .into_iter()
+            .flat_map(|layer| std::iter::repeat_n(layer.block, layer.thickness))
+            .take(Chunk::HEIGHT.to_usize())
+            .collect();
+
+        Self { column }
+    }
+}
+
+impl TerrainGenerator for SuperflatTerrain {
+    fn generate_chunk(&self, position: [isize; 3]) -> Chunk {
+        let mut chunk = Chunk::new(position.into());
+
+        for x in 0..Chunk::WIDTH.to_usize() {
+            for z in 0..Chunk::WIDTH.to_usize() {
+                for (y, block) in self.column.iter().enumerate() {
+                    chunk.set(x, y, z, Some(*block));
+                }
+            }
+        }
+
+        chunk
+    }
+}
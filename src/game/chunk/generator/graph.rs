@@ -0,0 +1,573 @@
+//! Terrain described by noise graphs in RON files (`.noise.ron`), so terrain can be
+//! designed without recompiling.
+//!
+//! A graph is a tree of noise nodes returning the height of the terrain in every column,
+//! together with rules deciding which blocks the columns are built from:
+//!
+//! ```ron
+//! (
+//!     height: ScaleBias(
+//!         source: Fbm(octaves: 5, frequency: 0.005),
+//!         scale: 24.0,
+//!         bias: 64.0,
+//!     ),
+//!     water_level: Some(60),
+//!     blocks: [
+//!         (block: Sand, max_depth: Some(3), max_height: Some(62)),
+//!         (block: Grass, max_depth: Some(0)),
+//!         (block: Dirt, max_depth: Some(3)),
+//!         (block: Stone),
+//!     ],
+//! )
+//! ```
+
+use std::{fmt, io};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use noise::{MultiFractal, NoiseFn, RidgedMulti, ScalePoint, Seedable};
+use serde::Deserialize;
+
+use crate::{
+    game::chunk::{BlockType, Chunk},
+    utils::ToUsize,
+};
+
+use super::{derive_seed, random::Random, TerrainGenerator};
+
+type Noise = Box<dyn NoiseFn<f64, 2> + Send + Sync>;
+
+/// Noise function of a graph. Sources return values roughly within `-1.0..=1.0`,
+/// which combinators and modifiers turn into other values.
+#[derive(Debug, Clone, Deserialize)]
+pub enum NoiseNode {
+    Perlin {
+        #[serde(default = "default_frequency")]
+        frequency: f64,
+    },
+    OpenSimplex {
+        #[serde(default = "default_frequency")]
+        frequency: f64,
+    },
+    /// Cells around random points, returning a random value per cell
+    /// or the distance to the closest point.
+    Worley {
+        #[serde(default = "default_frequency")]
+        frequency: f64,
+        #[serde(default)]
+        distance: bool,
+    },
+    Ridged(Fractal),
+    Fbm(Fractal),
+    Constant(f64),
+    Add(Box<NoiseNode>, Box<NoiseNode>),
+    Multiply(Box<NoiseNode>, Box<NoiseNode>),
+    /// Returns `b` where `control` lies between `lower` and `upper` and `a` elsewhere,
+    /// with smooth transitions `falloff` wide.
+    Select {
+        control: Box<NoiseNode>,
+        a: Box<NoiseNode>,
+        b: Box<NoiseNode>,
+        lower: f64,
+        upper: f64,
+        #[serde(default)]
+        falloff: f64,
+    },
+    /// Blends from `a` at a `control` of -1 to `b` at a `control` of 1.
+    Blend {
+        control: Box<NoiseNode>,
+        a: Box<NoiseNode>,
+        b: Box<NoiseNode>,
+    },
+    ScaleBias {
+        source: Box<NoiseNode>,
+        #[serde(default = "default_scale")]
+        scale: f64,
+        #[serde(default)]
+        bias: f64,
+    },
+    /// Maps values along a spline through the points, given as `(input, output)`.
+    /// Needs at least four points.
+    Curve {
+        source: Box<NoiseNode>,
+        points: Vec<(f64, f64)>,
+    },
+    /// Flattens values into terraces between the points. Needs at least two points.
+    Terrace {
+        source: Box<NoiseNode>,
+        points: Vec<f64>,
+        #[serde(default)]
+        invert: bool,
+    },
+    /// Randomly moves the points the source is sampled at.
+    Turbulence {
+        source: Box<NoiseNode>,
+        #[serde(default = "default_frequency")]
+        frequency: f64,
+        #[serde(default = "default_scale")]
+        power: f64,
+        #[serde(default = "default_roughness")]
+        roughness: usize,
+    },
+}
+
+/// Basis noise of fractal noise.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum Basis {
+    #[default]
+    Perlin,
+    OpenSimplex,
+}
+
+/// Octaves of basis noise added on top of each other.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct Fractal {
+    pub basis: Basis,
+    pub octaves: usize,
+    pub frequency: f64,
+    /// How much the frequency grows from one octave to the next.
+    pub lacunarity: f64,
+    /// How much the amplitude shrinks from one octave to the next.
+    pub persistence: f64,
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Self {
+            basis: Basis::Perlin,
+            octaves: 6,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            persistence: 0.5,
+        }
+    }
+}
+
+fn default_frequency() -> f64 {
+    1.0
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_roughness() -> usize {
+    3
+}
+
+/// Decides the block at a depth below the surface of a column. The first rule which applies wins.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct BlockRule {
+    pub block: BlockType,
+    /// Only applies this many blocks below the surface at most, 0 being the surface itself.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// Only applies to columns whose surface is at least this high.
+    #[serde(default)]
+    pub min_height: Option<usize>,
+    /// Only applies to columns whose surface is at most this high.
+    #[serde(default)]
+    pub max_height: Option<usize>,
+}
+
+impl BlockRule {
+    fn applies(&self, depth: usize, height: usize) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+            && self
+                .min_height
+                .is_none_or(|min_height| height >= min_height)
+            && self
+                .max_height
+                .is_none_or(|max_height| height <= max_height)
+    }
+}
+
+/// Terrain described by a noise graph.
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+pub struct NoiseGraph {
+    /// Height of the terrain in blocks.
+    pub height: NoiseNode,
+    /// Everything open to the sky up to this height is filled with water.
+    #[serde(default)]
+    pub water_level: Option<usize>,
+    /// Blocks where no rule applies are stone.
+    #[serde(default)]
+    pub blocks: Vec<BlockRule>,
+}
+
+#[derive(Debug)]
+pub enum NoiseGraphError {
+    Io(io::Error),
+    Ron(ron::error::SpannedError),
+    Invalid(&'static str),
+}
+
+impl fmt::Display for NoiseGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoiseGraphError::Io(error) => write!(f, "{error}"),
+            NoiseGraphError::Ron(error) => write!(f, "{error}"),
+            NoiseGraphError::Invalid(reason) => write!(f, "invalid noise graph: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for NoiseGraphError {}
+
+impl From<io::Error> for NoiseGraphError {
+    fn from(error: io::Error) -> Self {
+        NoiseGraphError::Io(error)
+    }
+}
+
+impl NoiseGraph {
+    pub fn from_ron(source: &str) -> Result<Self, NoiseGraphError> {
+        // lets fractals be written as `Fbm(octaves: 4)` instead of `Fbm((octaves: 4))`
+        let options = ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::UNWRAP_VARIANT_NEWTYPES);
+        let graph: Self = options.from_str(source).map_err(NoiseGraphError::Ron)?;
+        graph.height.validate()?;
+
+        Ok(graph)
+    }
+}
+
+impl NoiseNode {
+    /// Checks the parameters the noise functions would panic on.
+    fn validate(&self) -> Result<(), NoiseGraphError> {
+        use NoiseNode::*;
+
+        match self {
+            Perlin { .. }
+            | OpenSimplex { .. }
+            | Worley { .. }
+            | Ridged(_)
+            | Fbm(_)
+            | Constant(_) => Ok(()),
+            Add(a, b) | Multiply(a, b) => a.validate().and_then(|_| b.validate()),
+            Select { control, a, b, .. } | Blend { control, a, b } => [control, a, b]
+                .into_iter()
+                .try_for_each(|node| node.validate()),
+            // the noise functions skip points with the same input, and assert on the rest
+            Curve { points, .. } if distinct_inputs(points.iter().map(|(input, _)| *input)) < 4 => {
+                Err(NoiseGraphError::Invalid(
+                    "curves need at least four points with distinct inputs",
+                ))
+            }
+            Terrace { points, .. } if distinct_inputs(points.iter().copied()) < 2 => Err(
+                NoiseGraphError::Invalid("terraces need at least two distinct points"),
+            ),
+            ScaleBias { source, .. }
+            | Curve { source, .. }
+            | Terrace { source, .. }
+            | Turbulence { source, .. } => source.validate(),
+        }
+    }
+
+    /// Builds the noise function of the node. Every source gets its own seed, derived from
+    /// the world seed and the number of sources built before it.
+    fn build(&self, seed: u32, sources: &mut u32) -> Noise {
+        use NoiseNode::*;
+
+        let next_seed = |sources: &mut u32| {
+            *sources += 1;
+            derive_seed(seed, 100 + *sources)
+        };
+
+        match self {
+            Perlin { frequency } => Box::new(
+                ScalePoint::new(noise::Perlin::new(next_seed(sources))).set_scale(*frequency),
+            ),
+            OpenSimplex { frequency } => Box::new(
+                ScalePoint::new(noise::OpenSimplex::new(next_seed(sources))).set_scale(*frequency),
+            ),
+            Worley {
+                frequency,
+                distance,
+            } => Box::new(Cells {
+                seed: next_seed(sources),
+                frequency: *frequency,
+                distance: *distance,
+            }),
+            Ridged(fractal) => match fractal.basis {
+                Basis::Perlin => Box::new(
+                    fractal.apply(RidgedMulti::<noise::Perlin>::default(), next_seed(sources)),
+                ),
+                Basis::OpenSimplex => Box::new(fractal.apply(
+                    RidgedMulti::<noise::OpenSimplex>::default(),
+                    next_seed(sources),
+                )),
+            },
+            Fbm(fractal) => match fractal.basis {
+                Basis::Perlin => Box::new(
+                    fractal.apply(noise::Fbm::<noise::Perlin>::default(), next_seed(sources)),
+                ),
+                Basis::OpenSimplex => Box::new(fractal.apply(
+                    noise::Fbm::<noise::OpenSimplex>::default(),
+                    next_seed(sources),
+                )),
+            },
+            Constant(value) => Box::new(noise::Constant::new(*value)),
+            Add(a, b) => Box::new(noise::Add::new(
+                a.build(seed, sources),
+                b.build(seed, sources),
+            )),
+            Multiply(a, b) => Box::new(noise::Multiply::new(
+                a.build(seed, sources),
+                b.build(seed, sources),
+            )),
+            Select {
+                control,
+                a,
+                b,
+                lower,
+                upper,
+                falloff,
+            } => Box::new(
+                noise::Select::new(
+                    a.build(seed, sources),
+                    b.build(seed, sources),
+                    control.build(seed, sources),
+                )
+                .set_bounds(*lower, *upper)
+                .set_falloff(*falloff),
+            ),
+            Blend { control, a, b } => Box::new(noise::Blend::new(
+                a.build(seed, sources),
+                b.build(seed, sources),
+                control.build(seed, sources),
+            )),
+            ScaleBias {
+                source,
+                scale,
+                bias,
+            } => Box::new(
+                noise::ScaleBias::new(source.build(seed, sources))
+                    .set_scale(*scale)
+                    .set_bias(*bias),
+            ),
+            Curve { source, points } => Box::new(points.iter().fold(
+                noise::Curve::new(source.build(seed, sources)),
+                |curve, &(input, output)| curve.add_control_point(input, output),
+            )),
+            Terrace {
+                source,
+                points,
+                invert,
+            } => Box::new(
+                points
+                    .iter()
+                    .fold(
+                        noise::Terrace::new(source.build(seed, sources)),
+                        |terrace, &point| terrace.add_control_point(point),
+                    )
+                    .invert_terraces(*invert),
+            ),
+            Turbulence {
+                source,
+                frequency,
+                power,
+                roughness,
+            } => {
+                let source = source.build(seed, sources);
+
+                Box::new(
+                    noise::Turbulence::<_, noise::Perlin>::new(source)
+                        .set_seed(next_seed(sources))
+                        .set_frequency(*frequency)
+                        .set_power(*power)
+                        .set_roughness(*roughness),
+                )
+            }
+        }
+    }
+}
+
+/// Number of inputs `noise::Curve` and `noise::Terrace` keep as control points, which
+/// ignore inputs closer than `f64::EPSILON` to one they already have.
+fn distinct_inputs(inputs: impl IntoIterator<Item = f64>) -> usize {
+    let mut kept: Vec<f64> = Vec::new();
+
+    for input in inputs {
+        if !kept
+            .iter()
+            .any(|other| (other - input).abs() < f64::EPSILON)
+        {
+            kept.push(input);
+        }
+    }
+
+    kept.len()
+}
+
+impl Fractal {
+    fn apply<F: MultiFractal + Seedable>(&self, noise: F, seed: u32) -> F {
+        // the sources of fractals are only rebuilt with the seed by the setters
+        noise
+            .set_seed(seed)
+            .set_octaves(self.octaves)
+            .set_frequency(self.frequency)
+            .set_lacunarity(self.lacunarity)
+            .set_persistence(self.persistence)
+    }
+}
+
+/// Worley noise. The one of the noise crate can't be shared between threads.
+struct Cells {
+    seed: u32,
+    frequency: f64,
+    distance: bool,
+}
+
+impl NoiseFn<f64, 2> for Cells {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        let point = point.map(|n| n * self.frequency);
+        let [cell_x, cell_z] = point.map(|n| n.floor() as isize);
+
+        // every cell has one random point, the closest one lies in the cell or a neighbour
+        let (distance, value) = (-1..=1)
+            .flat_map(|dz| (-1..=1).map(move |dx| [cell_x + dx, cell_z + dz]))
+            .map(|cell| {
+                let mut random = Random::at(self.seed, 0, &cell);
+                let [x, z] = cell.map(|n| n as f64 + random.next_f64());
+                let distance = (x - point[0]).hypot(z - point[1]);
+
+                (distance, random.next_f64())
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap_or_default();
+
+        if self.distance {
+            // the closest point is hardly ever further away than one and a half cells
+            distance * 2.0 / 1.5 - 1.0
+        } else {
+            value * 2.0 - 1.0
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct NoiseGraphLoader;
+
+impl AssetLoader for NoiseGraphLoader {
+    type Asset = NoiseGraph;
+    type Settings = ();
+    type Error = NoiseGraphError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        use bevy::asset::AsyncReadExt;
+
+        Box::pin(async move {
+            let mut source = String::new();
+            reader.read_to_string(&mut source).await?;
+
+            NoiseGraph::from_ron(&source)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["noise.ron"]
+    }
+}
+
+/// Terrain built from the height and block rules of a noise graph.
+pub struct GraphTerrain {
+    height: Noise,
+    water_level: Option<usize>,
+    blocks: Vec<BlockRule>,
+}
+
+impl GraphTerrain {
+    pub fn new(seed: u32, graph: &NoiseGraph) -> Self {
+        Self {
+            height: graph.height.build(seed, &mut 0),
+            water_level: graph.water_level,
+            blocks: graph.blocks.clone(),
+        }
+    }
+
+    fn block(&self, depth: usize, height: usize) -> BlockType {
+        self.blocks
+            .iter()
+            .find(|rule| rule.applies(depth, height))
+            .map_or(BlockType::Stone, |rule| rule.block)
+    }
+}
+
+impl TerrainGenerator for GraphTerrain {
+    fn generate_chunk(&self, position: [isize; 3]) -> Chunk {
+        let mut chunk = Chunk::new(position.into());
+
+        for x in 0..Chunk::WIDTH.to_usize() {
+            for z in 0..Chunk::WIDTH.to_usize() {
+                let point = [position[0] + x as isize, position[2] + z as isize];
+                let height = self.height.get(point.map(|n| n as f64)).round();
+                let height = height.clamp(0.0, (Chunk::HEIGHT - 1) as f64) as usize;
+
+                for y in 0..=height {
+                    chunk.set(x, y, z, Some(self.block(height - y, height)));
+                }
+
+                if let Some(water_level) = self.water_level {
+                    for y in height + 1..=water_level.min(Chunk::HEIGHT.to_usize() - 1) {
+                        chunk.set(x, y, z, Some(BlockType::Water));
+                    }
+                }
+            }
+        }
+
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(height: &str) -> Result<NoiseGraph, NoiseGraphError> {
+        NoiseGraph::from_ron(&format!("(height: {height}, blocks: [(block: Stone)])"))
+    }
+
+    #[test]
+    fn loads_shipped_graphs() {
+        NoiseGraph::from_ron(include_str!("../../../../assets/noise/islands.noise.ron")).unwrap();
+    }
+
+    #[test]
+    fn rejects_curves_without_four_distinct_inputs() {
+        let duplicated = graph("Curve(source: Perlin(), points: [(0, 0), (0, 0), (0, 0), (0, 0)])");
+        let too_few = graph("Curve(source: Perlin(), points: [(-1, 0), (0, 1), (0, 2), (1, 0)])");
+
+        assert!(matches!(duplicated, Err(NoiseGraphError::Invalid(_))));
+        assert!(matches!(too_few, Err(NoiseGraphError::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_terraces_without_two_distinct_points() {
+        let duplicated = graph("Terrace(source: Perlin(), points: [0.5, 0.5, 0.5])");
+
+        assert!(matches!(duplicated, Err(NoiseGraphError::Invalid(_))));
+    }
+
+    #[test]
+    fn builds_valid_curves_and_terraces() {
+        let graph = graph(
+            "Terrace(
+                source: Curve(source: Perlin(), points: [(-1, -1), (0, 0), (0, 0), (0.5, 0.2), (1, 1)]),
+                points: [-1, 0, 0, 1],
+            )",
+        )
+        .unwrap();
+
+        GraphTerrain::new(0, &graph).generate_chunk([0, 0, 0]);
+    }
+}
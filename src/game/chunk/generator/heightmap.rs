@@ -0,0 +1,293 @@
+use std::fmt;
+
+use bevy::{prelude::*, render::render_resource::TextureFormat};
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma, RgbaImage};
+
+use crate::{
+    game::chunk::{BlockType, Chunk},
+    utils::ToUsize,
+};
+
+use super::TerrainGenerator;
+
+/// What lies beyond the borders of a heightmap image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Reflect)]
+pub enum HeightmapEdges {
+    /// The pixels at the border continue forever.
+    #[default]
+    Clamp,
+    /// The image repeats in every direction.
+    Tile,
+}
+
+/// Mask image choosing the surface block where it's bright.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct SplatLayer {
+    /// PNG in `assets/`, like the heightmap.
+    pub path: String,
+    pub block: BlockType,
+}
+
+/// Heightmap image a world is generated from, placed with its top left corner at the origin.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct HeightmapSettings {
+    /// Grayscale PNG in `assets/`, black being the lowest point and white the highest.
+    pub path: String,
+    /// Width of a pixel in blocks.
+    pub scale: f64,
+    /// Height of black pixels.
+    pub min_height: usize,
+    /// Height of white pixels.
+    pub max_height: usize,
+    pub edges: HeightmapEdges,
+    /// Everything open to the sky up to this height is filled with water.
+    pub water_level: Option<usize>,
+    /// Surface blocks of the areas covered by the masks. Masks are stretched over the
+    /// heightmap, and later layers win where they overlap.
+    pub splats: Vec<SplatLayer>,
+}
+
+impl Default for HeightmapSettings {
+    fn default() -> Self {
+        Self {
+            path: "heightmaps/island.png".to_string(),
+            scale: 1.0,
+            min_height: 40,
+            max_height: 120,
+            edges: HeightmapEdges::Clamp,
+            water_level: Some(62),
+            splats: Vec::new(),
+        }
+    }
+}
+
+/// Terrain shaped by a grayscale image, with surface blocks chosen by splat maps.
+pub struct HeightmapTerrain {
+    heights: ImageBuffer<Luma<u16>, Vec<u16>>,
+    splats: Vec<(GrayImage, BlockType)>,
+    settings: HeightmapSettings,
+}
+
+/// Images of a heightmap world, loaded through the asset server and reloaded when they change.
+#[derive(Debug, Clone)]
+pub struct HeightmapImages {
+    settings: HeightmapSettings,
+    heights: Handle<Image>,
+    splats: Vec<Handle<Image>>,
+}
+
+/// An image with a pixel format heightmaps can't be read from.
+#[derive(Debug)]
+pub struct UnsupportedImage(pub TextureFormat);
+
+impl fmt::Display for UnsupportedImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported pixel format {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedImage {}
+
+impl HeightmapImages {
+    /// Starts loading the images. Images which have been loaded before are shared.
+    pub fn load(settings: HeightmapSettings, asset_server: &AssetServer) -> Self {
+        Self {
+            heights: asset_server.load(settings.path.clone()),
+            splats: settings
+                .splats
+                .iter()
+                .map(|layer| asset_server.load(layer.path.clone()))
+                .collect(),
+            settings,
+        }
+    }
+
+    pub fn settings(&self) -> &HeightmapSettings {
+        &self.settings
+    }
+
+    /// Whether the image is the heightmap or one of its masks.
+    pub fn contains(&self, id: AssetId<Image>) -> bool {
+        self.heights.id() == id || self.splats.iter().any(|splat| splat.id() == id)
+    }
+
+    /// Terrain shaped by the images, `None` until all of them have been loaded.
+    pub fn terrain(
+        &self,
+        images: &Assets<Image>,
+    ) -> Option<Result<HeightmapTerrain, UnsupportedImage>> {
+        let heights = images.get(&self.heights)?;
+        let splats = self
+            .splats
+            .iter()
+            .map(|splat| images.get(splat))
+            .collect::<Option<Vec<_>>>()?;
+
+        let terrain = || {
+            // 8 bit images are stretched to 16 bits, so both are sampled the same way
+            let heights = Self::decode(heights)?.to_luma16();
+            let splats = splats
+                .into_iter()
+                .zip(&self.settings.splats)
+                .map(|(image, layer)| Ok((Self::decode(image)?.to_luma8(), layer.block)))
+                .collect::<Result<_, _>>()?;
+
+            Ok(HeightmapTerrain {
+                heights,
+                splats,
+                settings: self.settings.clone(),
+            })
+        };
+
+        Some(terrain())
+    }
+
+    /// Reads the pixels of the formats PNGs are loaded as.
+    fn decode(image: &Image) -> Result<DynamicImage, UnsupportedImage> {
+        let [width, height] = [image.width(), image.height()];
+        let words = || {
+            image
+                .data
+                .chunks_exact(2)
+                .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+                .collect::<Vec<_>>()
+        };
+        let format = image.texture_descriptor.format;
+
+        match format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {
+                RgbaImage::from_raw(width, height, image.data.clone()).map(DynamicImage::from)
+            }
+            TextureFormat::R16Uint => {
+                ImageBuffer::from_raw(width, height, words()).map(DynamicImage::ImageLuma16)
+            }
+            TextureFormat::Rg16Uint => {
+                ImageBuffer::from_raw(width, height, words()).map(DynamicImage::ImageLumaA16)
+            }
+            TextureFormat::Rgba16Unorm => {
+                ImageBuffer::from_raw(width, height, words()).map(DynamicImage::ImageRgba16)
+            }
+            _ => None,
+        }
+        .ok_or(UnsupportedImage(format))
+    }
+}
+
+impl HeightmapTerrain {
+    /// Depth of the dirt below grass.
+    const SUBSURFACE_DEPTH: usize = 3;
+
+    /// Reads the images from files, relative to the working directory rather than `assets/`,
+    /// e.g. for tools which are given their paths. The game loads them as [`HeightmapImages`].
+    pub fn load(settings: HeightmapSettings) -> Result<Self, image::ImageError> {
+        // 8 bit images are stretched to 16 bits, so both are sampled the same way
+        let heights = image::open(&settings.path)?.to_luma16();
+        let splats = settings
+            .splats
+            .iter()
+            .map(|layer| Ok((image::open(&layer.path)?.to_luma8(), layer.block)))
+            .collect::<Result<_, image::ImageError>>()?;
+
+        Ok(Self {
+            heights,
+            splats,
+            settings,
+        })
+    }
+
+    /// Pixel of an image with the given size, following the edge mode outside of it.
+    fn pixel(&self, [x, z]: [isize; 2], [width, height]: [u32; 2]) -> [u32; 2] {
+        let wrap = |n: isize, length: u32| match self.settings.edges {
+            HeightmapEdges::Clamp => n.clamp(0, length as isize - 1) as u32,
+            HeightmapEdges::Tile => n.rem_euclid(length as isize) as u32,
+        };
+
+        [wrap(x, width), wrap(z, height)]
+    }
+
+    /// Height of a column, interpolated between the pixels around it.
+    fn height_at(&self, x: isize, z: isize) -> f64 {
+        let scale = self.settings.scale.max(f64::EPSILON);
+        let [x, z] = [x, z].map(|n| n as f64 / scale);
+        let [cell_x, cell_z] = [x, z].map(|n| n.floor() as isize);
+        let [tx, tz] = [x - cell_x as f64, z - cell_z as f64];
+
+        let sample = |dx: isize, dz: isize| {
+            let [x, z] = self.pixel([cell_x + dx, cell_z + dz], self.heights.dimensions().into());
+            f64::from(self.heights.get_pixel(x, z).0[0]) / f64::from(u16::MAX)
+        };
+
+        let near = sample(0, 0) * (1.0 - tx) + sample(1, 0) * tx;
+        let far = sample(0, 1) * (1.0 - tx) + sample(1, 1) * tx;
+        let value = near * (1.0 - tz) + far * tz;
+
+        let (min, max) = (self.settings.min_height, self.settings.max_height);
+        min as f64 + value * (max as f64 - min as f64)
+    }
+
+    fn surface(&self, x: isize, z: isize, height: usize) -> BlockType {
+        let scale = self.settings.scale.max(f64::EPSILON);
+        let [x, z] = [x, z].map(|n| (n as f64 / scale).floor() as isize);
+        let [width, depth] = self.heights.dimensions().into();
+
+        let splat = self.splats.iter().rev().find(|(mask, _)| {
+            // masks may have another resolution than the heightmap
+            let [mask_x, mask_z] = self.pixel([x, z], [width, depth]);
+            let [mask_width, mask_depth]: [u32; 2] = mask.dimensions().into();
+            let mask_x = (u64::from(mask_x) * u64::from(mask_width) / u64::from(width)) as u32;
+            let mask_z = (u64::from(mask_z) * u64::from(mask_depth) / u64::from(depth)) as u32;
+
+            mask.get_pixel(mask_x, mask_z).0[0] >= 128
+        });
+
+        match splat {
+            Some((_, block)) => *block,
+            None if self
+                .settings
+                .water_level
+                .is_some_and(|level| height <= level + 1) =>
+            {
+                BlockType::Sand
+            }
+            None => BlockType::Grass,
+        }
+    }
+}
+
+impl TerrainGenerator for HeightmapTerrain {
+    fn generate_chunk(&self, position: [isize; 3]) -> Chunk {
+        let mut chunk = Chunk::new(position.into());
+        let top = Chunk::HEIGHT.to_usize() - 1;
+
+        for x in 0..Chunk::WIDTH.to_usize() {
+            for z in 0..Chunk::WIDTH.to_usize() {
+                let (world_x, world_z) = (position[0] + x as isize, position[2] + z as isize);
+                let height = self.height_at(world_x, world_z).round();
+                let height = height.clamp(0.0, top as f64) as usize;
+                let surface = self.surface(world_x, world_z, height);
+                let subsurface = match surface {
+                    BlockType::Grass => BlockType::Dirt,
+                    block => block,
+                };
+
+                for y in 0..=height {
+                    let block = match height - y {
+                        0 => surface,
+                        depth if depth <= Self::SUBSURFACE_DEPTH => subsurface,
+                        _ => BlockType::Stone,
+                    };
+
+                    chunk.set(x, y, z, Some(block));
+                }
+
+                if let Some(water_level) = self.settings.water_level {
+                    for y in height + 1..=water_level.min(top) {
+                        chunk.set(x, y, z, Some(BlockType::Water));
+                    }
+                }
+            }
+        }
+
+        chunk
+    }
+}
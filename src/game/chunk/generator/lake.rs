@@ -0,0 +1,106 @@
+use std::f64::consts::TAU;
+
+use super::random::Random;
+
+/// A lake in a depression of the terrain, with a flat water surface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lake {
+    pub center: [isize; 2],
+    pub radius: f64,
+    /// Height of the topmost water block.
+    pub level: usize,
+}
+
+impl Lake {
+    /// Maximum depth of the bowl carved for the lake.
+    const DEPTH: f64 = 6.0;
+
+    /// Depth of the lake bed below the water level, or `None` outside of the lake.
+    pub fn depth_at(&self, x: isize, z: isize) -> Option<f64> {
+        let dx = (x - self.center[0]) as f64;
+        let dz = (z - self.center[1]) as f64;
+        let distance = (dx * dx + dz * dz).sqrt() / self.radius;
+
+        (distance < 1.0).then_some((1.0 - distance * distance) * Self::DEPTH)
+    }
+
+    /// Whether the lake can cover a column, including its shore.
+    pub fn reaches(&self, x: isize, z: isize, shore: f64) -> bool {
+        let dx = (x - self.center[0]) as f64;
+        let dz = (z - self.center[1]) as f64;
+
+        (dx * dx + dz * dz).sqrt() < self.radius + shore
+    }
+}
+
+/// Places at most one lake in every region of the world.
+pub struct LakePlanner {
+    seed: u32,
+}
+
+impl LakePlanner {
+    pub const REGION: isize = 128;
+    const CHANCE: f64 = 0.35;
+    const MIN_RADIUS: f64 = 10.0;
+    const MAX_RADIUS: f64 = 28.0;
+    /// Number of points on the rim of a lake the terrain height is checked at.
+    const RIM_SAMPLES: usize = 12;
+
+    pub fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+
+    /// Returns the lakes which may reach into the area from `min` to `max`, inclusive.
+    ///
+    /// `level` receives the center and the points on the rim of a lake and returns its
+    /// water level, or `None` if there should be no lake there. The water level has to
+    /// be below the rim so the water stays in the depression.
+    pub fn lakes_near(
+        &self,
+        min: [isize; 2],
+        max: [isize; 2],
+        mut level: impl FnMut([isize; 2], &[[isize; 2]]) -> Option<usize>,
+    ) -> Vec<Lake> {
+        let margin = Self::MAX_RADIUS as isize;
+        let [start_x, start_z] = min.map(|n| (n - margin).div_euclid(Self::REGION));
+        let [end_x, end_z] = max.map(|n| (n + margin).div_euclid(Self::REGION));
+
+        let mut lakes = Vec::new();
+
+        for region_x in start_x..=end_x {
+            for region_z in start_z..=end_z {
+                let mut random = Random::at(self.seed, 10, &[region_x, region_z]);
+
+                if !random.chance(Self::CHANCE) {
+                    continue;
+                }
+
+                // keep lakes of neighbouring regions from overlapping
+                let inset = margin;
+                let center = [region_x, region_z].map(|region| {
+                    region * Self::REGION + random.range(inset..Self::REGION - inset)
+                });
+                let radius = random.range_f64(Self::MIN_RADIUS..Self::MAX_RADIUS);
+                let rim: Vec<_> = (0..Self::RIM_SAMPLES)
+                    .map(|i| {
+                        let angle = i as f64 / Self::RIM_SAMPLES as f64 * TAU;
+                        [
+                            center[0] + (angle.cos() * radius).round() as isize,
+                            center[1] + (angle.sin() * radius).round() as isize,
+                        ]
+                    })
+                    .collect();
+
+                if let Some(level) = level(center, &rim) {
+                    lakes.push(Lake {
+                        center,
+                        radius,
+                        level,
+                    });
+                }
+            }
+        }
+
+        lakes
+    }
+}
@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use super::{decoration::BlockWrite, BlockType, Chunk};
+
+use self::{
+    biome::Biome,
+    columns::ChunkColumns,
+    debug::DebugTerrain,
+    flat::{SuperflatLayer, SuperflatTerrain},
+    graph::{GraphTerrain, NoiseGraph},
+    heightmap::HeightmapSettings,
+    ore::OreVein,
+    overworld::OverworldTerrain,
+    ridged::{RidgedNoise, RidgedTerrain},
+    river::River,
+    void::VoidTerrain,
+};
+
+pub mod biome;
+pub mod cave;
+pub mod columns;
+pub mod debug;
+pub mod density;
+pub mod erosion;
+pub mod feature;
+pub mod flat;
+pub mod graph;
+pub mod heightmap;
+pub mod lake;
+pub mod ore;
+pub mod overworld;
+pub mod random;
+pub mod ridged;
+pub mod river;
+pub mod strata;
+pub mod void;
+
+/// Fills chunks with the blocks of a world. Implementations have to be deterministic,
+/// chunks may only depend on the generator's configuration and their position.
+pub trait TerrainGenerator: Send + Sync {
+    fn generate_chunk(&self, position: [isize; 3]) -> Chunk;
+
+    /// Places features like trees on a generated chunk. Features may reach into the
+    /// neighbouring chunks, so they are returned as writes at world positions.
+    fn decorate(&self, _chunk: &Chunk, _position: [isize; 3]) -> Vec<BlockWrite> {
+        Vec::new()
+    }
+
+    /// Biome of a column, for generators that have biomes.
+    fn biome_at(&self, _x: isize, _z: isize) -> Option<Biome> {
+        None
+    }
+
+    /// Rivers which may affect the area from `min` to `max`, inclusive, for generators
+    /// that have rivers.
+    fn rivers_near(&self, _min: [isize; 2], _max: [isize; 2]) -> Vec<River> {
+        Vec::new()
+    }
+
+    /// Heights and biomes of the columns of a chunk, for generators which keep them after
+    /// generating the chunk. `None` if they aren't kept or have been evicted since.
+    fn columns(&self, _position: [isize; 3]) -> Option<Arc<ChunkColumns>> {
+        None
+    }
+}
+
+/// Derives the seed of a single noise function from the world seed,
+/// so the noise functions of a generator don't repeat each other.
+pub fn derive_seed(seed: u32, salt: u32) -> u32 {
+    let hash = (u64::from(seed) << 32 | u64::from(salt)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+
+    // noise functions seed their octaves with consecutive numbers, which must not overflow
+    (hash >> 33) as u32
+}
+
+/// The built-in terrain generators a world can be created with.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub enum WorldPreset {
+    Overworld {
+        sea_level: usize,
+        ores: Vec<OreVein>,
+        /// Whether valleys are carved into the terrain by hydraulic erosion.
+        erosion: bool,
+        /// Whether rivers flow from high ground to the sea.
+        rivers: bool,
+    },
+    Ridged {
+        erosion: bool,
+        noise: RidgedNoise,
+    },
+    Superflat {
+        layers: Vec<SuperflatLayer>,
+    },
+    Void,
+    /// Terrain shaped by a grayscale image in `assets/`. The world stays empty until the
+    /// image has been loaded, or if it can't be.
+    Heightmap(HeightmapSettings),
+    /// Terrain described by a noise graph asset, e.g. `noise/islands.noise.ron`.
+    /// The world stays empty until the graph has been loaded.
+    Graph {
+        path: String,
+    },
+    /// Every block type on display, for checking textures and meshing.
+    Debug,
+}
+
+impl Default for WorldPreset {
+    fn default() -> Self {
+        WorldPreset::Overworld {
+            sea_level: 62,
+            ores: OreVein::defaults(),
+            erosion: true,
+            rivers: true,
+        }
+    }
+}
+
+impl WorldPreset {
+    pub fn ridged() -> Self {
+        WorldPreset::Ridged {
+            erosion: true,
+            noise: RidgedNoise::default(),
+        }
+    }
+
+    pub fn superflat() -> Self {
+        WorldPreset::Superflat {
+            layers: vec![
+                SuperflatLayer {
+                    block: BlockType::Stone,
+                    thickness: 63,
+                },
+                SuperflatLayer {
+                    block: BlockType::Grass,
+                    thickness: 1,
+                },
+            ],
+        }
+    }
+}
+
+/// Generates chunks with the terrain generator of the current world.
+/// Cloning is cheap, the generator is shared with all generation tasks.
+#[derive(Resource, Clone)]
+pub struct ChunkGenerator {
+    seed: u32,
+    terrain: Arc<dyn TerrainGenerator>,
+}
+
+impl ChunkGenerator {
+    pub const DEFAULT_SEED: u32 = 0;
+
+    /// Creates the generator of a preset.
+    pub fn new(seed: u32, preset: WorldPreset) -> Self {
+        let terrain: Arc<dyn TerrainGenerator> = match preset {
+            WorldPreset::Overworld {
+                sea_level,
+                ores,
+                erosion,
+                rivers,
+            } => Arc::new(OverworldTerrain::new(
+                seed, sea_level, ores, erosion, rivers,
+            )),
+            WorldPreset::Ridged { erosion, noise } => {
+                Arc::new(RidgedTerrain::new(seed, erosion, noise))
+            }
+            WorldPreset::Superflat { layers } => Arc::new(SuperflatTerrain::new(layers)),
+            // images are loaded by the asset server, see `HeightmapImages`
+            WorldPreset::Void | WorldPreset::Heightmap(_) | WorldPreset::Graph { .. } => {
+                Arc::new(VoidTerrain)
+            }
+            WorldPreset::Debug => Arc::new(DebugTerrain),
+        };
+
+        Self { seed, terrain }
+    }
+
+    /// Uses a custom terrain generator instead of one of the presets.
+    pub fn with_terrain(seed: u32, terrain: impl TerrainGenerator + 'static) -> Self {
+        Self {
+            seed,
+            terrain: Arc::new(terrain),
+        }
+    }
+
+    /// Uses the terrain described by a noise graph.
+    pub fn from_graph(seed: u32, graph: &NoiseGraph) -> Self {
+        Self::with_terrain(seed, GraphTerrain::new(seed, graph))
+    }
+
+    pub fn generate_chunk(&self, position: [isize; 3]) -> Chunk {
+        self.terrain.generate_chunk(position)
+    }
+
+    pub fn decorate(&self, chunk: &Chunk, position: [isize; 3]) -> Vec<BlockWrite> {
+        self.terrain.decorate(chunk, position)
+    }
+
+    pub fn biome_at(&self, x: isize, z: isize) -> Option<Biome> {
+        self.terrain.biome_at(x, z)
+    }
+
+    pub fn rivers_near(&self, min: [isize; 2], max: [isize; 2]) -> Vec<River> {
+        self.terrain.rivers_near(min, max)
+    }
+
+    pub fn columns(&self, position: [isize; 3]) -> Option<Arc<ChunkColumns>> {
+        self.terrain.columns(position)
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl Default for ChunkGenerator {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SEED, WorldPreset::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, thread};
+
+    use super::*;
+
+    /// Positions spread over several regions of the world, including negative ones.
+    const POSITIONS: [[isize; 3]; 6] = [
+        [0, 0, 0],
+        [32, 0, 0],
+        [-32, 0, 64],
+        [256, 0, -512],
+        [-1024, 0, -1024],
+        [4096, 0, 2048],
+    ];
+
+    fn fingerprints(
+        generator: &ChunkGenerator,
+        positions: &[[isize; 3]],
+    ) -> HashMap<[isize; 3], u64> {
+        positions
+            .iter()
+            .map(|&position| (position, generator.generate_chunk(position).fingerprint()))
+            .collect()
+    }
+
+    /// Fails whenever the generated terrain changes. If the change is intended,
+    /// update the fingerprints.
+    #[test]
+    fn fingerprints_are_stable() {
+        let cases = [
+            (0, WorldPreset::default(), [0, 0, 0], 16005959617317417122),
+            (
+                0,
+                WorldPreset::default(),
+                [-1024, 0, -1024],
+                12731409195765180632,
+            ),
+            (
+                1337,
+                WorldPreset::default(),
+                [256, 0, -512],
+                14891802436777813132,
+            ),
+            (42, WorldPreset::ridged(), [-32, 0, 64], 3357140259188256517),
+            (
+                42,
+                WorldPreset::superflat(),
+                [32, 0, 0],
+                6471646587312863013,
+            ),
+        ];
+
+        for (seed, preset, position, fingerprint) in cases {
+            let generator = ChunkGenerator::new(seed, preset.clone());
+
+            assert_eq!(
+                generator.generate_chunk(position).fingerprint(),
+                fingerprint,
+                "seed {seed}, {preset:?} at {position:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn seeds_change_terrain() {
+        let first = ChunkGenerator::new(1, WorldPreset::default());
+        let second = ChunkGenerator::new(2, WorldPreset::default());
+
+        assert_ne!(
+            fingerprints(&first, &POSITIONS),
+            fingerprints(&second, &POSITIONS)
+        );
+    }
+
+    /// Caches shared between chunks, like erosion and rivers, must not make chunks
+    /// depend on what has been generated before them.
+    #[test]
+    fn generation_order_and_threads_do_not_matter() {
+        for preset in [WorldPreset::default(), WorldPreset::ridged()] {
+            let in_order = ChunkGenerator::new(7, preset.clone());
+            let expected = fingerprints(&in_order, &POSITIONS);
+
+            let mut reversed = POSITIONS;
+            reversed.reverse();
+            let backwards = ChunkGenerator::new(7, preset.clone());
+            assert_eq!(fingerprints(&backwards, &reversed), expected, "{preset:?}");
+
+            let parallel = ChunkGenerator::new(7, preset.clone());
+            let generated = thread::scope(|scope| {
+                let threads: Vec<_> = reversed
+                    .chunks(2)
+                    .map(|positions| scope.spawn(|| fingerprints(&parallel, positions)))
+                    .collect();
+
+                threads
+                    .into_iter()
+                    .flat_map(|thread| thread.join().unwrap())
+                    .collect::<HashMap<_, _>>()
+            });
+            assert_eq!(generated, expected, "{preset:?}");
+        }
+    }
+}
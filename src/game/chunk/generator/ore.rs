@@ -0,0 +1,161 @@
+use std::f64::consts::{PI, TAU};
+
+use bevy::prelude::*;
+
+use crate::{
+    game::chunk::{BlockType, Chunk},
+    utils::ToUsize,
+};
+
+use super::random::Random;
+
+/// Configuration of the deposits of one kind of ore.
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct OreVein {
+    pub block: BlockType,
+    /// Lowest height the centers of deposits are placed at.
+    pub min_height: usize,
+    /// Highest height the centers of deposits are placed at.
+    pub max_height: usize,
+    /// Approximate number of blocks in a deposit.
+    pub size: usize,
+    /// Average number of deposits per chunk.
+    pub frequency: f32,
+}
+
+impl OreVein {
+    pub fn defaults() -> Vec<OreVein> {
+        vec![
+            OreVein {
+                block: BlockType::CoalOre,
+                min_height: 5,
+                max_height: 128,
+                size: 14,
+                frequency: 12.0,
+            },
+            OreVein {
+                block: BlockType::IronOre,
+                min_height: 5,
+                max_height: 72,
+                size: 9,
+                frequency: 8.0,
+            },
+            OreVein {
+                block: BlockType::GoldOre,
+                min_height: 5,
+                max_height: 32,
+                size: 8,
+                frequency: 2.0,
+            },
+            OreVein {
+                block: BlockType::DiamondOre,
+                min_height: 1,
+                max_height: 16,
+                size: 6,
+                frequency: 0.8,
+            },
+        ]
+    }
+
+    /// Whether ore may replace the block.
+    fn replaces(block: Option<BlockType>) -> bool {
+        matches!(
+            block,
+            Some(BlockType::Stone | BlockType::Deepslate | BlockType::Granite)
+        )
+    }
+}
+
+/// Places ore deposits into chunks.
+///
+/// The deposits of every chunk are generated from a random number generator seeded with
+/// the chunk's position. Deposits may reach into neighbouring chunks, so a chunk also
+/// places the parts of its neighbours' deposits which reach into it. This way the ores of a
+/// chunk don't depend on the order chunks are generated in.
+pub struct OrePlacer {
+    seed: u32,
+    veins: Vec<OreVein>,
+}
+
+impl OrePlacer {
+    pub fn new(seed: u32, veins: Vec<OreVein>) -> Self {
+        Self { seed, veins }
+    }
+
+    pub fn place(&self, chunk: &mut Chunk, position: [isize; 3]) {
+        for dx in -1..=1 {
+            for dz in -1..=1 {
+                let source = [
+                    position[0] + dx * Chunk::WIDTH,
+                    position[2] + dz * Chunk::WIDTH,
+                ];
+
+                for (index, vein) in self.veins.iter().enumerate() {
+                    let mut random = Random::at(self.seed, 20 + index as u32, &source);
+                    let frequency = f64::from(vein.frequency.max(0.0));
+                    let count = frequency as usize + usize::from(random.chance(frequency.fract()));
+
+                    for _ in 0..count {
+                        Self::place_deposit(chunk, position, source, vein, &mut random);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Places a deposit as a chain of spheres along a short line, which are largest in its middle.
+    fn place_deposit(
+        chunk: &mut Chunk,
+        position: [isize; 3],
+        source: [isize; 2],
+        vein: &OreVein,
+        random: &mut Random,
+    ) {
+        let max_height = vein.max_height.max(vein.min_height + 1) as isize;
+        let center = Vec3::new(
+            (source[0] + random.range(0..Chunk::WIDTH)) as f32,
+            random.range(vein.min_height as isize..max_height) as f32,
+            (source[1] + random.range(0..Chunk::WIDTH)) as f32,
+        );
+        let yaw = random.range_f64(0.0..TAU);
+        let pitch = random.range_f64(-0.5..0.5);
+        let direction = Vec3::new(
+            (yaw.cos() * pitch.cos()) as f32,
+            pitch.sin() as f32,
+            (yaw.sin() * pitch.cos()) as f32,
+        );
+        let length = vein.size as f32 / 4.0;
+        let radius = (vein.size as f64).cbrt() * 0.45;
+        let steps = vein.size.max(1);
+
+        for step in 0..steps {
+            let t = step as f64 / steps as f64;
+            let sphere = center + direction * length * (t as f32 - 0.5);
+            let radius = (radius * (0.6 + 0.8 * (PI * t).sin())) as f32;
+
+            let min = (sphere - radius).floor();
+            let max = (sphere + radius).ceil();
+
+            for x in min.x as isize..=max.x as isize {
+                for y in min.y as isize..=max.y as isize {
+                    for z in min.z as isize..=max.z as isize {
+                        let block = Vec3::new(x as f32, y as f32, z as f32) + 0.5;
+                        let local = [x - position[0], y, z - position[2]];
+
+                        if block.distance_squared(sphere) > radius * radius
+                            || !(0..Chunk::WIDTH).contains(&local[0])
+                            || !(0..Chunk::HEIGHT).contains(&local[1])
+                            || !(0..Chunk::WIDTH).contains(&local[2])
+                            || !OreVein::replaces(chunk.get(local))
+                        {
+                            continue;
+                        }
+
+                        let [x, y, z] = local.map(|n| n.to_usize());
+                        chunk.set(x, y, z, Some(vein.block));
+                    }
+                }
+            }
+        }
+    }
+}
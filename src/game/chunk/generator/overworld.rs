@@ -0,0 +1,538 @@
+use std::sync::Arc;
+
+use noise::{Fbm, MultiFractal, NoiseFn, OpenSimplex, Perlin, RidgedMulti, Seedable};
+
+use crate::{
+    game::chunk::{decoration::BlockWrite, BlockType, Chunk},
+    utils::ToUsize,
+};
+
+use super::{
+    biome::{Biome, BiomeSource, HeightShape},
+    cave::CaveCarver,
+    columns::{ChunkColumns, ColumnCache, ColumnGrid},
+    density::DensityGrid,
+    derive_seed,
+    erosion::{ErodedArea, Erosion},
+    feature::Feature,
+    lake::{Lake, LakePlanner},
+    ore::{OrePlacer, OreVein},
+    random::Random,
+    river::{River, RiverPlanner},
+    strata::Strata,
+    TerrainGenerator,
+};
+
+/// Terrain made of biomes, each with its own height shaping and surface blocks.
+///
+/// The terrain is a density function: a gradient around the biome's height, disturbed
+/// by 3D noise for overhangs and arches, with caves carved out of it. Everything open
+/// to the sky below the sea level is filled with water. The heights can be worn down by
+/// erosion, which carves valleys before any of this is applied, and rivers run through
+/// channels cut into the terrain from high ground down to the sea. Underground, the stone is split
+/// into strata and contains ore deposits. Its surface is decorated with trees, boulders and
+/// flowers depending on the biome.
+pub struct OverworldTerrain {
+    seed: u32,
+    sea_level: usize,
+    biomes: BiomeSource,
+    lakes: LakePlanner,
+    rivers: Option<RiverPlanner>,
+    erosion: Option<Erosion>,
+    hills: Fbm<Perlin>,
+    ridges: RidgedMulti<OpenSimplex>,
+    overhangs: Fbm<Perlin>,
+    caves: CaveCarver,
+    strata: Strata,
+    ores: OrePlacer,
+    columns: ColumnCache,
+}
+
+impl OverworldTerrain {
+    /// How far below the height of a column surface blocks are placed on exposed ground.
+    const SURFACE_THICKNESS: f64 = 8.0;
+    /// Columns this close to the water level get shore blocks, forming beaches.
+    const SHORE_HEIGHT: isize = 2;
+    /// Width of the shore around lakes.
+    const LAKE_SHORE: f64 = 3.0;
+    /// Width of the banks beside the channels of rivers.
+    const RIVER_BANK: f64 = 2.0;
+
+    pub fn new(
+        seed: u32,
+        sea_level: usize,
+        ores: Vec<OreVein>,
+        erosion: bool,
+        rivers: bool,
+    ) -> Self {
+        Self {
+            seed,
+            sea_level,
+            biomes: BiomeSource::new(seed),
+            lakes: LakePlanner::new(seed),
+            rivers: rivers.then(|| RiverPlanner::new(seed, sea_level)),
+            erosion: erosion.then(|| Erosion::new(seed)),
+            hills: Fbm::<Perlin>::new(derive_seed(seed, 4))
+                .set_octaves(4)
+                .set_frequency(1.0 / 96.0),
+            ridges: RidgedMulti::<OpenSimplex>::default()
+                .set_seed(derive_seed(seed, 5))
+                .set_octaves(4)
+                .set_frequency(1.0 / 256.0),
+            overhangs: Fbm::<Perlin>::new(derive_seed(seed, 9))
+                .set_octaves(3)
+                .set_frequency(1.0 / 48.0),
+            caves: CaveCarver::new(seed),
+            strata: Strata::new(seed),
+            ores: OrePlacer::new(seed, ores),
+            columns: ColumnCache::default(),
+        }
+    }
+
+    pub fn sea_level(&self) -> usize {
+        self.sea_level
+    }
+
+    pub fn biome_at(&self, x: isize, z: isize) -> Biome {
+        self.biomes.biome_at(x, z)
+    }
+
+    /// Height of the surface of a column, before lakes, overhangs and caves are added.
+    pub fn height_at(&self, x: isize, z: isize) -> usize {
+        let shapes = ShapeGrid::new(&self.biomes, [x, z], [x, z]);
+        let erosion = self.erosion([x, z], [x, z]);
+        let height = self
+            .eroded_height(shapes.shape_at(x, z), erosion.as_ref(), x, z)
+            .round();
+
+        height.clamp(0.0, (Chunk::HEIGHT - 1) as f64) as usize
+    }
+
+    /// Heights of the surface from `min` to `max`, inclusive, before lakes, overhangs and caves
+    /// are added. The counterpart of [`OverworldTerrain::height_at`] for whole areas, which
+    /// blends the biomes and erodes the area once instead of for every column.
+    pub fn surface_heights(&self, min: [isize; 2], max: [isize; 2]) -> ColumnGrid<f64> {
+        let shapes = ShapeGrid::new(&self.biomes, min, max);
+        let erosion = self.erosion(min, max);
+
+        self.heights(&shapes, erosion.as_ref(), min, max)
+    }
+
+    fn height(&self, shape: HeightShape, x: isize, z: isize) -> f64 {
+        let point = [x as f64, z as f64];
+        let ridges = (self.ridges.get(point) + 1.0) / 2.0;
+
+        shape.height(self.hills.get(point), ridges)
+    }
+
+    /// Heights of all columns from `min` to `max`, inclusive, which have to be covered by
+    /// the shapes. Each noise function is sampled for the whole area before they're combined,
+    /// one column at a time.
+    fn heights(
+        &self,
+        shapes: &ShapeGrid,
+        erosion: Option<&ErodedArea>,
+        min: [isize; 2],
+        max: [isize; 2],
+    ) -> ColumnGrid<f64> {
+        let hills = ColumnGrid::sample(&self.hills, min, max);
+        let ridges = ColumnGrid::sample(&self.ridges, min, max);
+        // both grids are in the same order as the one being built
+        let mut noise = hills.values().iter().zip(ridges.values());
+
+        ColumnGrid::from_fn(min, max, |x, z| {
+            let (hills, ridges) = noise.next().unwrap_or((&0.0, &0.0));
+            let offset = erosion.map_or(0.0, |erosion| erosion.offset_at(x, z));
+
+            shapes.shape_at(x, z).height(*hills, (ridges + 1.0) / 2.0) + offset
+        })
+    }
+
+    fn eroded_height(
+        &self,
+        shape: HeightShape,
+        erosion: Option<&ErodedArea>,
+        x: isize,
+        z: isize,
+    ) -> f64 {
+        let offset = erosion.map_or(0.0, |erosion| erosion.offset_at(x, z));
+
+        self.height(shape, x, z) + offset
+    }
+
+    /// Erosion of the area from `min` to `max`, inclusive, if it's enabled.
+    fn erosion(&self, min: [isize; 2], max: [isize; 2]) -> Option<ErodedArea> {
+        let erosion = self.erosion.as_ref()?;
+
+        Some(erosion.area(0, min, max, |min, size| {
+            let max = min.map(|n| n + size as isize - 1);
+            let shapes = ShapeGrid::new(&self.biomes, min, max);
+
+            self.heights(&shapes, None, min, max).into_values()
+        }))
+    }
+
+    /// Lakes reaching into the area from `min` to `max`. They lie in depressions above
+    /// the sea level, with the water level just below the lowest point of their rim.
+    pub fn lakes_near(&self, min: [isize; 2], max: [isize; 2]) -> Vec<Lake> {
+        self.lakes.lakes_near(min, max, |[x, z], rim| {
+            // mountains are too steep to hold water
+            if matches!(self.biome_at(x, z), Biome::Ocean | Biome::Mountains) {
+                return None;
+            }
+
+            let rim_height = rim.iter().map(|&[x, z]| self.height_at(x, z)).min()?;
+            let level = rim_height.checked_sub(1)?;
+
+            (level > self.sea_level + Self::SHORE_HEIGHT.to_usize()).then_some(level)
+        })
+    }
+
+    /// Rivers which may affect the area from `min` to `max`, inclusive, if they're enabled.
+    ///
+    /// Rivers are planned on the heights of the biomes without blending or erosion,
+    /// which are cheap to sample across the large areas rivers flow through.
+    pub fn rivers_near(&self, min: [isize; 2], max: [isize; 2]) -> Vec<River> {
+        let Some(rivers) = &self.rivers else {
+            return Vec::new();
+        };
+
+        rivers.rivers_near(min, max, |x, z| {
+            self.height(self.biome_at(x, z).shape(), x, z)
+        })
+    }
+
+    /// Lowers the height of a column within a lake to its bed, and raises its shore above the
+    /// water where the rim dips lower than the sampled points.
+    fn apply_lakes(lakes: &[Lake], height: f64, x: isize, z: isize) -> (f64, Option<WaterColumn>) {
+        lakes
+            .iter()
+            .filter(|lake| lake.reaches(x, z, Self::LAKE_SHORE))
+            .fold((height, None), |(height, column), lake| {
+                match lake.depth_at(x, z) {
+                    Some(depth) => (
+                        height.min(lake.level as f64 - depth),
+                        Some(WaterColumn {
+                            level: lake.level,
+                            in_water: true,
+                        }),
+                    ),
+                    None => (
+                        height.max(lake.level as f64 + 1.0),
+                        column.or(Some(WaterColumn {
+                            level: lake.level,
+                            in_water: false,
+                        })),
+                    ),
+                }
+            })
+    }
+
+    /// Lowers the height of a column to the bed of the closest river, or towards its water
+    /// level in the valley beside the channel. Banks are raised above the water where the
+    /// terrain beside a river lies lower than its water level.
+    fn apply_rivers(
+        &self,
+        rivers: &[River],
+        (height, column): (f64, Option<WaterColumn>),
+        x: isize,
+        z: isize,
+    ) -> (f64, Option<WaterColumn>) {
+        // lakes keep their flat surface, rivers flowing into them end at their shore
+        if column.is_some_and(|column| column.in_water) {
+            return (height, column);
+        }
+
+        let Some(section) = rivers
+            .iter()
+            .filter_map(|river| river.section_at(x, z))
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+        else {
+            return (height, column);
+        };
+        let bank = section.level as f64 + 1.0;
+
+        if let Some(depth) = section.depth() {
+            let column = WaterColumn {
+                level: section.level,
+                in_water: true,
+            };
+
+            return (height.min(section.level as f64 - depth), Some(column));
+        }
+
+        let height = if height > bank {
+            bank + (height - bank) * section.valley_slope()
+        } else {
+            height
+        };
+
+        // at the mouth the river is level with the sea, which needs no banks
+        if section.distance < section.width + Self::RIVER_BANK && section.level > self.sea_level {
+            let column = column.or(Some(WaterColumn {
+                level: section.level,
+                in_water: false,
+            }));
+
+            return (height.max(bank), column);
+        }
+
+        (height, column)
+    }
+
+    fn density(
+        &self,
+        position: [isize; 3],
+        shape: HeightShape,
+        height: f64,
+        water_level: usize,
+    ) -> f64 {
+        // there is nothing holding back the water under overhangs, so they fade out towards it
+        let above_water = (position[1] - water_level as isize) as f64 / Self::SURFACE_THICKNESS;
+        let terrain = height - position[1] as f64
+            + shape.roughness
+                * above_water.clamp(0.0, 1.0)
+                * self.overhangs.get(position.map(|n| n as f64));
+
+        if terrain <= 0.0 || height < water_level as f64 + Self::SURFACE_THICKNESS {
+            // caves can only make it emptier, and must not open up under water
+            return terrain;
+        }
+
+        terrain.min(self.caves.density(position, height))
+    }
+
+    fn water_level(&self, water: Option<WaterColumn>) -> usize {
+        water.map_or(0, |water| water.level).max(self.sea_level)
+    }
+}
+
+/// How a column is affected by a lake or river which reaches it.
+#[derive(Debug, Clone, Copy)]
+struct WaterColumn {
+    level: usize,
+    /// Whether the column is covered by the water, or only part of its shore.
+    in_water: bool,
+}
+
+impl TerrainGenerator for OverworldTerrain {
+    fn generate_chunk(&self, position: [isize; 3]) -> Chunk {
+        let mut chunk = Chunk::new(position.into());
+        let min = [position[0], position[2]];
+        // the density grid samples the far borders of the chunk as well
+        let max = min.map(|n| n + Chunk::WIDTH);
+        let shapes = ShapeGrid::new(&self.biomes, min, max);
+        let lakes = self.lakes_near(min, max);
+        let rivers = self.rivers_near(min, max);
+        let erosion = self.erosion(min, max);
+        let heights = self.heights(&shapes, erosion.as_ref(), min, max);
+        let columns = ColumnGrid::from_fn(min, max, |x, z| {
+            let lake = Self::apply_lakes(&lakes, *heights.get(x, z), x, z);
+            self.apply_rivers(&rivers, lake, x, z)
+        });
+        let biomes = ColumnGrid::from_fn(min, max.map(|n| n - 1), |x, z| {
+            self.biomes.biome_at(x, z)
+        });
+
+        let density = DensityGrid::new(position, |x, z, samples| {
+            let shape = shapes.shape_at(x, z);
+            let (height, water) = *columns.get(x, z);
+            let water_level = self.water_level(water);
+
+            for (index, sample) in samples.iter_mut().enumerate() {
+                let y = DensityGrid::sample_height(index);
+                *sample = self.density([x, y, z], shape, height, water_level);
+            }
+        });
+        let strata = self.strata.sample(position);
+
+        for x in 0..Chunk::WIDTH.to_usize() {
+            for z in 0..Chunk::WIDTH.to_usize() {
+                let (world_x, world_z) = (position[0] + x as isize, position[2] + z as isize);
+                let shape = shapes.shape_at(world_x, world_z);
+                let (height, water) = *columns.get(world_x, world_z);
+                let water_level = self.water_level(water);
+                let biome = *biomes.get(world_x, world_z);
+                let is_shore = water.is_some()
+                    || (height.round() as isize - water_level as isize).abs() <= Self::SHORE_HEIGHT;
+                // blocks below air count as surface near the top of the terrain,
+                // cave floors further down stay stone
+                let surface_bottom = height - shape.roughness - Self::SURFACE_THICKNESS;
+                let mut depth = 0;
+                let mut covered = false;
+
+                for y in (0..Chunk::HEIGHT.to_usize()).rev() {
+                    // lakes and rivers are enforced block by block, the interpolated density
+                    // would smooth away the parts of their shores holding back the water
+                    let water_solid = water.and_then(|water| {
+                        if water.in_water {
+                            (y <= water.level).then_some(y as f64 <= height)
+                        } else {
+                            (y <= water.level + 1).then_some(true)
+                        }
+                    });
+                    // the bottom layer is never carved, so nothing falls out of the world
+                    let solid =
+                        y == 0 || water_solid.unwrap_or_else(|| density.get(x, y, z) > 0.0);
+
+                    if !solid {
+                        if y <= water_level && (!covered || water_solid.is_some()) {
+                            chunk.set(x, y, z, Some(BlockType::Water));
+                        }
+                        depth = 0;
+                        continue;
+                    }
+
+                    let block = if (y as f64) < surface_bottom {
+                        strata.rock_at(x, y, z)
+                    } else if depth <= biome.subsurface_depth() && is_shore {
+                        biome.shore()
+                    } else if depth == 0 {
+                        biome.surface(y)
+                    } else if depth <= biome.subsurface_depth() {
+                        biome.subsurface()
+                    } else {
+                        strata.rock_at(x, y, z)
+                    };
+
+                    chunk.set(x, y, z, Some(block));
+                    depth += 1;
+                    covered = true;
+                }
+            }
+        }
+
+        self.ores.place(&mut chunk, position);
+        self.columns.insert(
+            position,
+            ChunkColumns {
+                heights: ColumnGrid::from_fn(min, max.map(|n| n - 1), |x, z| {
+                    columns.get(x, z).0 as f32
+                }),
+                biomes: Some(biomes),
+            },
+        );
+
+        chunk
+    }
+
+    fn decorate(&self, chunk: &Chunk, position: [isize; 3]) -> Vec<BlockWrite> {
+        let mut writes = Vec::new();
+        let biomes = self.columns(position).and_then(|columns| columns.biomes.clone());
+
+        for x in 0..Chunk::WIDTH {
+            for z in 0..Chunk::WIDTH {
+                let (world_x, world_z) = (position[0] + x, position[2] + z);
+                let mut random = Random::at(self.seed, 30, &[world_x, world_z]);
+                let biome = biomes.as_ref().map_or_else(
+                    || self.biome_at(world_x, world_z),
+                    |biomes| *biomes.get(world_x, world_z),
+                );
+                let rules = biome.decorations();
+
+                let Some(feature) = Feature::pick(rules, random.next_f64()) else {
+                    continue;
+                };
+                let Some(y) = (0..Chunk::HEIGHT)
+                    .rev()
+                    .find(|&y| chunk.get([x, y, z]).is_some())
+                else {
+                    continue;
+                };
+
+                if y + Feature::MAX_HEIGHT >= Chunk::HEIGHT
+                    || !chunk
+                        .get([x, y, z])
+                        .is_some_and(|ground| feature.grows_on(ground))
+                {
+                    continue;
+                }
+
+                feature.place([world_x, y + 1, world_z], &mut random, &mut writes);
+            }
+        }
+
+        writes
+    }
+
+    fn biome_at(&self, x: isize, z: isize) -> Option<Biome> {
+        Some(self.biome_at(x, z))
+    }
+
+    fn rivers_near(&self, min: [isize; 2], max: [isize; 2]) -> Vec<River> {
+        self.rivers_near(min, max)
+    }
+
+    fn columns(&self, position: [isize; 3]) -> Option<Arc<ChunkColumns>> {
+        self.columns.get(position)
+    }
+}
+
+/// Height shapes of an area, averaged over the biomes around each point so there are
+/// no cliffs at biome borders.
+///
+/// Shapes are blended on a coarse grid aligned to the world origin and interpolated in
+/// between, which keeps neighbouring chunks seamless.
+struct ShapeGrid {
+    /// Cell coordinates of the first corner.
+    origin: [isize; 2],
+    width: usize,
+    corners: Vec<HeightShape>,
+}
+
+impl ShapeGrid {
+    /// Distance in blocks between the corners of the grid.
+    const CELL: isize = 4;
+    /// Radius in cells of the area biome shapes are averaged over.
+    const BLEND_RADIUS: isize = 3;
+
+    /// Covers all columns from `min` to `max`, inclusive.
+    fn new(biomes: &BiomeSource, min: [isize; 2], max: [isize; 2]) -> Self {
+        let origin = min.map(|n| n.div_euclid(Self::CELL));
+        let end = max.map(|n| n.div_euclid(Self::CELL) + 1);
+        let [width, depth] = [0, 1].map(|axis| (end[axis] - origin[axis] + 1).to_usize());
+
+        let radius = Self::BLEND_RADIUS;
+        let kernel = (2 * radius + 1).to_usize();
+        let samples_width = width + kernel - 1;
+        let samples: Vec<HeightShape> = (0..depth + kernel - 1)
+            .flat_map(|z| (0..samples_width).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                let x = (origin[0] - radius + x as isize) * Self::CELL;
+                let z = (origin[1] - radius + z as isize) * Self::CELL;
+                biomes.biome_at(x, z).shape()
+            })
+            .collect();
+
+        let weight = 1.0 / (kernel * kernel) as f64;
+        let corners = (0..depth)
+            .flat_map(|z| (0..width).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                (0..kernel)
+                    .flat_map(|dz| (0..kernel).map(move |dx| (dx, dz)))
+                    .map(|(dx, dz)| samples[(z + dz) * samples_width + x + dx])
+                    .fold(HeightShape::default(), |sum, shape| sum + shape * weight)
+            })
+            .collect();
+
+        Self {
+            origin,
+            width,
+            corners,
+        }
+    }
+
+    fn shape_at(&self, x: isize, z: isize) -> HeightShape {
+        let cell_x = (x.div_euclid(Self::CELL) - self.origin[0]).to_usize();
+        let cell_z = (z.div_euclid(Self::CELL) - self.origin[1]).to_usize();
+        let tx = x.rem_euclid(Self::CELL) as f64 / Self::CELL as f64;
+        let tz = z.rem_euclid(Self::CELL) as f64 / Self::CELL as f64;
+
+        let corner = |dx: usize, dz: usize| self.corners[(cell_z + dz) * self.width + cell_x + dx];
+
+        let near = corner(0, 0) * (1.0 - tx) + corner(1, 0) * tx;
+        let far = corner(0, 1) * (1.0 - tx) + corner(1, 1) * tx;
+
+        near * (1.0 - tz) + far * tz
+    }
+}
@@ -0,0 +1,54 @@
+use std::ops::Range;
+
+/// Small deterministic random number generator (SplitMix64), so generated worlds don't
+/// depend on the platform or on the versions of other crates.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Seeded from the world seed and a position, e.g. of a chunk or region.
+    /// The salt separates the different things generated at the same position.
+    pub fn at(seed: u32, salt: u32, position: &[isize]) -> Self {
+        let mut random = Self::new(u64::from(seed) << 32 | u64::from(salt));
+
+        for &n in position {
+            random.state ^= random.next_u64() ^ n as u64;
+        }
+
+        random
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0.0..1.0`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn chance(&mut self, probability: f64) -> bool {
+        self.next_f64() < probability
+    }
+
+    pub fn range(&mut self, range: Range<isize>) -> isize {
+        let length = (range.end - range.start).max(1) as u64;
+
+        range.start + (self.next_u64() % length) as isize
+    }
+
+    pub fn range_f64(&mut self, range: Range<f64>) -> f64 {
+        range.start + self.next_f64() * (range.end - range.start)
+    }
+}
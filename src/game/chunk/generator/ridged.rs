@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::{prelude::ReflectInspectorOptions, InspectorOptions};
+use noise::{MultiFractal, NoiseFn, OpenSimplex, RidgedMulti, ScaleBias, Seedable};
+
+use crate::{
+    game::chunk::{BlockType, Chunk},
+    utils::ToUsize,
+};
+
+use super::{erosion::Erosion, TerrainGenerator};
+
+/// Parameters of the noise the ridged terrain is shaped by.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, InspectorOptions)]
+#[reflect(InspectorOptions)]
+pub struct RidgedNoise {
+    /// Blocks per unit of noise, horizontally and vertically. Together with the bias, this
+    /// keeps the highest ridges below the top of the chunk.
+    #[inspector(min = 1, max = 200)]
+    pub scale: u32,
+    /// The noise supports up to 32 octaves.
+    #[inspector(min = 1, max = 32)]
+    pub octaves: usize,
+    #[inspector(min = 0.001)]
+    pub frequency: f64,
+    /// Added to the noise before it's scaled to heights, lifting the terrain.
+    #[inspector(min = -1.0, max = 1.0)]
+    pub bias: f64,
+}
+
+impl Default for RidgedNoise {
+    fn default() -> Self {
+        Self {
+            scale: 100,
+            octaves: 4,
+            frequency: 0.5,
+            bias: 1.0,
+        }
+    }
+}
+
+/// Rolling hills and sharp ridges of grass over stone, optionally worn down by erosion.
+pub struct RidgedTerrain {
+    terrain: Box<dyn NoiseFn<f64, 2> + Send + Sync>,
+    scale: u32,
+    erosion: Option<Erosion>,
+}
+
+impl RidgedTerrain {
+    pub fn new(seed: u32, erosion: bool, parameters: RidgedNoise) -> Self {
+        // the sources of `RidgedMulti::new` ignore the seed, they are only rebuilt by the setters
+        let noise = RidgedMulti::<OpenSimplex>::default()
+            .set_seed(seed)
+            .set_octaves(parameters.octaves)
+            .set_frequency(parameters.frequency);
+
+        let noise = ScaleBias::new(noise).set_bias(parameters.bias);
+
+        Self {
+            terrain: Box::new(noise),
+            scale: parameters.scale.max(1),
+            erosion: erosion.then(|| Erosion::new(seed)),
+        }
+    }
+
+    /// Height of a column before erosion.
+    fn height(&self, x: isize, z: isize, scale: f32) -> f32 {
+        let x_coord = x as f32 / scale;
+        let z_coord = z as f32 / scale;
+
+        let y = self.terrain.get([x_coord as f64, z_coord as f64]) as f32 * scale / 2.0;
+        y.abs()
+    }
+}
+
+impl TerrainGenerator for RidgedTerrain {
+    fn generate_chunk(&self, position: [isize; 3]) -> Chunk {
+        let mut chunk = Chunk::new(position.into());
+        let scale = self.scale;
+        let erosion = self.erosion.as_ref().map(|erosion| {
+            erosion.area(
+                scale,
+                [position[0], position[2]],
+                [
+                    position[0] + Chunk::WIDTH - 1,
+                    position[2] + Chunk::WIDTH - 1,
+                ],
+                |[min_x, min_z], size| {
+                    (0..size as isize)
+                        .flat_map(|z| (0..size as isize).map(move |x| (x, z)))
+                        .map(|(x, z)| self.height(min_x + x, min_z + z, scale as f32) as f64)
+                        .collect()
+                },
+            )
+        });
+        let scale = scale as f32;
+
+        for x in 0..Chunk::WIDTH.to_usize() {
+            for z in 0..Chunk::WIDTH.to_usize() {
+                let (world_x, world_z) = (x as isize + position[0], z as isize + position[2]);
+                let offset = erosion
+                    .as_ref()
+                    .map_or(0.0, |erosion| erosion.offset_at(world_x, world_z));
+
+                let y = self.height(world_x, world_z, scale) + offset as f32;
+                // erosion and extreme parameters can still reach past the top of the chunk
+                let y = y.round().clamp(0.0, (Chunk::HEIGHT - 1) as f32) as usize;
+
+                for y in 0..y {
+                    chunk.set(x, y, z, Some(BlockType::Stone));
+                }
+                chunk.set(x, y, z, Some(BlockType::Grass));
+            }
+        }
+
+        chunk
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use dashmap::DashMap;
+
+use super::random::Random;
+
+/// A point on the path of a river.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RiverPoint {
+    pub position: [isize; 2],
+    /// Height of the topmost water block, never rising downstream.
+    pub level: usize,
+    /// Half the width of the channel.
+    pub width: f64,
+}
+
+/// A river flowing from high ground to the sea.
+#[derive(Debug, Clone, PartialEq)]
+pub struct River {
+    /// Points from the source to the mouth.
+    pub path: Vec<RiverPoint>,
+    /// Corners of the area the river affects, inclusive.
+    min: [isize; 2],
+    max: [isize; 2],
+}
+
+/// The part of a river closest to a column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RiverSection {
+    /// Distance of the column from the middle of the river.
+    pub distance: f64,
+    pub level: usize,
+    pub width: f64,
+}
+
+impl River {
+    /// Maximum depth of the channel below the water level.
+    const DEPTH: f64 = 4.0;
+    /// Width of the valley the terrain is lowered in beside the channel.
+    pub const VALLEY: f64 = 12.0;
+
+    fn new(path: Vec<RiverPoint>) -> Self {
+        let reach = path
+            .iter()
+            .map(|point| (point.width + Self::VALLEY).ceil() as isize)
+            .max()
+            .unwrap_or_default();
+        let corner = |pick: fn(isize, isize) -> isize, offset: isize| {
+            [0, 1].map(|axis| {
+                path.iter()
+                    .map(|point| point.position[axis])
+                    .reduce(pick)
+                    .unwrap_or_default()
+                    + offset
+            })
+        };
+        let (min, max) = (corner(isize::min, -reach), corner(isize::max, reach));
+
+        Self { path, min, max }
+    }
+
+    /// Whether the river may affect any column in the area from `min` to `max`, inclusive.
+    pub fn overlaps(&self, min: [isize; 2], max: [isize; 2]) -> bool {
+        (0..2).all(|axis| self.min[axis] <= max[axis] && min[axis] <= self.max[axis])
+    }
+
+    /// Section of the river closest to a column, or `None` if the column lies outside of
+    /// its valley.
+    pub fn section_at(&self, x: isize, z: isize) -> Option<RiverSection> {
+        if !self.overlaps([x, z], [x, z]) {
+            return None;
+        }
+
+        let point = [x as f64, z as f64];
+
+        self.path
+            .windows(2)
+            .map(|segment| {
+                let [start, end] = [segment[0], segment[1]].map(|point| point.position);
+                let [start_x, start_z] = start.map(|n| n as f64);
+                let direction = [(end[0] - start[0]) as f64, (end[1] - start[1]) as f64];
+                let length = direction[0] * direction[0] + direction[1] * direction[1];
+                let t = if length > 0.0 {
+                    (((point[0] - start_x) * direction[0] + (point[1] - start_z) * direction[1])
+                        / length)
+                        .clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let closest = [start_x + direction[0] * t, start_z + direction[1] * t];
+                let distance = (point[0] - closest[0]).hypot(point[1] - closest[1]);
+                let level = segment[0].level as f64 * (1.0 - t) + segment[1].level as f64 * t;
+
+                RiverSection {
+                    distance,
+                    level: level.floor() as usize,
+                    width: segment[0].width * (1.0 - t) + segment[1].width * t,
+                }
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+            .filter(|section| section.distance < section.width + Self::VALLEY)
+    }
+
+    /// Whether a column lies within the channel of the river.
+    pub fn contains(&self, x: isize, z: isize) -> bool {
+        self.section_at(x, z)
+            .is_some_and(|section| section.in_channel())
+    }
+}
+
+impl RiverSection {
+    pub fn in_channel(&self) -> bool {
+        self.distance < self.width
+    }
+
+    /// Depth of the river bed below the water level, or `None` outside of the channel.
+    pub fn depth(&self) -> Option<f64> {
+        let distance = self.distance / self.width;
+
+        self.in_channel()
+            .then_some((1.0 - distance * distance) * River::DEPTH)
+    }
+
+    /// How much of the height above the banks a column beside the channel keeps, rising
+    /// smoothly from nothing at the channel to all of it at the edge of the valley.
+    pub fn valley_slope(&self) -> f64 {
+        let t = ((self.distance - self.width) / River::VALLEY).clamp(0.0, 1.0);
+
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+/// Plans rivers on a coarse grid across the whole world. Rivers start on high ground in
+/// each region and follow the lowest neighbouring cells until they reach the sea.
+///
+/// Rivers only depend on the seed and the heights of the grid cells along their path, so every
+/// chunk sees the same rivers no matter which chunks were generated before. Planned regions
+/// are cached, as rivers reach far beyond the region they start in.
+pub struct RiverPlanner {
+    seed: u32,
+    sea_level: usize,
+    regions: DashMap<[isize; 2], Arc<Vec<River>>>,
+    /// Cached regions, from the oldest to the newest.
+    order: Mutex<VecDeque<[isize; 2]>>,
+}
+
+impl RiverPlanner {
+    pub const REGION: isize = 512;
+    /// Distance between the cells of the grid rivers are planned on.
+    const CELL: isize = 16;
+    /// Number of places in a region a river may start at.
+    const SOURCES: usize = 3;
+    /// Sources have to lie this far above the sea level.
+    const SOURCE_HEIGHT: f64 = 24.0;
+    /// Rivers which don't reach the sea within this many cells are dropped.
+    const MAX_LENGTH: usize = 32;
+    /// How deep rivers may cut through ridges in their way.
+    const MAX_CUT: f64 = 20.0;
+    const MIN_WIDTH: f64 = 2.0;
+    const MAX_WIDTH: f64 = 7.0;
+    /// Number of regions kept in the cache.
+    const CACHED_REGIONS: usize = 64;
+
+    pub fn new(seed: u32, sea_level: usize) -> Self {
+        Self {
+            seed,
+            sea_level,
+            regions: DashMap::new(),
+            order: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Returns the rivers which may affect the area from `min` to `max`, inclusive.
+    ///
+    /// `height` returns the height of the terrain around a column. It's only sampled on
+    /// the coarse grid, so it doesn't need to be exact.
+    pub fn rivers_near(
+        &self,
+        min: [isize; 2],
+        max: [isize; 2],
+        height: impl Fn(isize, isize) -> f64,
+    ) -> Vec<River> {
+        // rivers wander at most diagonally away from their source
+        let margin = Self::MAX_LENGTH as isize * Self::CELL * 3 / 2
+            + (Self::MAX_WIDTH + River::VALLEY) as isize;
+        let [start_x, start_z] = min.map(|n| (n - margin).div_euclid(Self::REGION));
+        let [end_x, end_z] = max.map(|n| (n + margin).div_euclid(Self::REGION));
+
+        (start_x..=end_x)
+            .flat_map(|x| (start_z..=end_z).map(move |z| [x, z]))
+            .flat_map(|region| {
+                let rivers = match self.regions.get(&region) {
+                    Some(rivers) => rivers.clone(),
+                    None => self.cache(region, Arc::new(self.plan(region, &height))),
+                };
+
+                rivers
+                    .iter()
+                    .filter(|river| river.overlaps(min, max))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn cache(&self, region: [isize; 2], rivers: Arc<Vec<River>>) -> Arc<Vec<River>> {
+        self.regions.insert(region, rivers.clone());
+        let mut order = self.order.lock().unwrap();
+
+        if !order.contains(&region) {
+            order.push_back(region);
+        }
+
+        // evicted regions are simply planned again, the rivers are the same
+        while order.len() > Self::CACHED_REGIONS {
+            if let Some(oldest) = order.pop_front() {
+                self.regions.remove(&oldest);
+            }
+        }
+
+        rivers
+    }
+
+    fn plan(&self, region: [isize; 2], height: impl Fn(isize, isize) -> f64) -> Vec<River> {
+        let mut random = Random::at(self.seed, 14, &region);
+        let cells = Self::REGION / Self::CELL;
+
+        (0..Self::SOURCES)
+            .filter_map(|_| {
+                let source = region.map(|n| n * cells + random.range(0..cells));
+                self.trace(source, &height)
+            })
+            .collect()
+    }
+
+    /// Follows the lowest neighbouring cells from a source down to the sea.
+    fn trace(&self, source: [isize; 2], height: impl Fn(isize, isize) -> f64) -> Option<River> {
+        let sea_level = self.sea_level as f64;
+        let height_of = |cell: [isize; 2]| {
+            let [x, z] = self.point(cell);
+            height(x, z)
+        };
+
+        if height_of(source) < sea_level + Self::SOURCE_HEIGHT {
+            return None;
+        }
+
+        let mut cells = vec![source];
+        let mut level = height_of(source);
+
+        loop {
+            let cell = *cells.last()?;
+
+            if height_of(cell) < sea_level {
+                break;
+            }
+            if cells.len() >= Self::MAX_LENGTH {
+                return None;
+            }
+
+            let (next, next_height) = (-1..=1)
+                .flat_map(|dz| (-1..=1).map(move |dx| [cell[0] + dx, cell[1] + dz]))
+                .filter(|neighbour| !cells.contains(neighbour))
+                .map(|neighbour| (neighbour, height_of(neighbour)))
+                .min_by(|a, b| a.1.total_cmp(&b.1))?;
+
+            if next_height > level + Self::MAX_CUT {
+                return None;
+            }
+
+            level = level.min(next_height);
+            cells.push(next);
+        }
+
+        let mut level = f64::MAX;
+        let last = (cells.len() - 1).max(1) as f64;
+        let path = cells
+            .iter()
+            .enumerate()
+            .map(|(index, &cell)| {
+                let position = self.point(cell);
+                // the water stays below the terrain, so it's held by the banks
+                level = level.min(height(position[0], position[1]) - 2.0);
+                let grown = index as f64 / last;
+
+                RiverPoint {
+                    position,
+                    level: level.max(sea_level) as usize,
+                    width: Self::MIN_WIDTH + (Self::MAX_WIDTH - Self::MIN_WIDTH) * grown,
+                }
+            })
+            .collect();
+
+        Some(River::new(path))
+    }
+
+    /// Position of the river point in a cell, moved away from the centre so rivers don't follow
+    /// straight lines.
+    fn point(&self, cell: [isize; 2]) -> [isize; 2] {
+        let mut random = Random::at(self.seed, 15, &cell);
+        let jitter = Self::CELL / 3;
+
+        cell.map(|n| n * Self::CELL + Self::CELL / 2 + random.range(-jitter..jitter + 1))
+    }
+}
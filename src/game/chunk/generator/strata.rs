@@ -0,0 +1,71 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use crate::game::chunk::BlockType;
+
+use super::{density::DensityGrid, derive_seed};
+
+/// Rock layers underground: deepslate at the bottom of the world and bands of granite
+/// within the stone, both bent by 3D noise.
+pub struct Strata {
+    warp: Fbm<Perlin>,
+    bands: Perlin,
+}
+
+impl Strata {
+    /// Average height of the border between stone and deepslate.
+    const DEEPSLATE_LEVEL: f64 = 16.0;
+    /// How far the border between stone and deepslate is moved up and down.
+    const DEEPSLATE_WARP: f64 = 6.0;
+    /// Granite bands are where the band noise exceeds this.
+    const GRANITE_THRESHOLD: f64 = 0.3;
+
+    pub fn new(seed: u32) -> Self {
+        Self {
+            warp: Fbm::<Perlin>::new(derive_seed(seed, 11))
+                .set_octaves(2)
+                .set_frequency(1.0 / 32.0),
+            bands: Perlin::new(derive_seed(seed, 12)),
+        }
+    }
+
+    /// Samples the strata of a chunk. Like the terrain, they are interpolated from
+    /// coarse samples, so they are cheap to look up for every block.
+    pub fn sample(&self, position: [isize; 3]) -> StrataSamples {
+        let deepslate = DensityGrid::new(position, |x, z, samples| {
+            for (index, sample) in samples.iter_mut().enumerate() {
+                let y = DensityGrid::sample_height(index);
+                let warp = self.warp.get([x, y, z].map(|n| n as f64));
+                *sample = Self::DEEPSLATE_LEVEL + warp * Self::DEEPSLATE_WARP - y as f64;
+            }
+        });
+
+        // stretched horizontally, so the bands are flat and wide
+        let granite = DensityGrid::new(position, |x, z, samples| {
+            for (index, sample) in samples.iter_mut().enumerate() {
+                let y = DensityGrid::sample_height(index);
+                let point = [x as f64 / 160.0, y as f64 / 20.0, z as f64 / 160.0];
+                *sample = self.bands.get(point) - Self::GRANITE_THRESHOLD;
+            }
+        });
+
+        StrataSamples { deepslate, granite }
+    }
+}
+
+pub struct StrataSamples {
+    deepslate: DensityGrid,
+    granite: DensityGrid,
+}
+
+impl StrataSamples {
+    /// Rock at a position within the chunk.
+    pub fn rock_at(&self, x: usize, y: usize, z: usize) -> BlockType {
+        if self.deepslate.get(x, y, z) > 0.0 {
+            BlockType::Deepslate
+        } else if self.granite.get(x, y, z) > 0.0 {
+            BlockType::Granite
+        } else {
+            BlockType::Stone
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use crate::game::chunk::Chunk;
+
+use super::TerrainGenerator;
+
+/// Empty world, e.g. for building from scratch or testing without terrain.
+pub struct VoidTerrain;
+
+impl TerrainGenerator for VoidTerrain {
+    fn generate_chunk(&self, position: [isize; 3]) -> Chunk {
+        Chunk::new(position.into())
+    }
+}
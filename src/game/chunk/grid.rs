@@ -6,11 +6,14 @@ use std::{
 use bevy::prelude::*;
 use dashmap::DashMap;
 
-use crate::vec3;
+use crate::{utils::ToUsize, vec3};
 
 use super::{
-    mesh_builder::{MeshBuilder, MeshBuilderSettings},
-    Chunk,
+    decoration::{BlockWrite, Decorations},
+    generator::columns::ColumnGrid,
+    light::{ChunkLight, LightBlocks},
+    mesh_builder::{ChunkMesh, MeshBuilder, MeshBuilderSettings},
+    BlockType, Chunk,
 };
 
 #[derive(Resource, Clone, Deref, Default)]
@@ -19,50 +22,483 @@ pub struct ChunkGrid(Arc<ChunkGridInner>);
 #[derive(Default)]
 pub struct ChunkGridInner {
     chunks: DashMap<GridCoordinates, Option<Chunk>>,
+    decorations: Decorations,
 }
 
 impl ChunkGridInner {
+    /// Stores a generated chunk together with its features, adds the features its decorated
+    /// neighbours placed into it and builds its mesh.
     pub fn compute_mesh(
         &self,
         coordinates: GridCoordinates,
         chunk: Chunk,
+        features: Vec<BlockWrite>,
         mesh_builder_settings: MeshBuilderSettings,
-    ) -> Mesh {
+    ) -> ChunkMesh {
+        self.insert_generated(coordinates, chunk, features);
+
+        self.remesh(coordinates, mesh_builder_settings)
+            .unwrap_or_else(|| MeshBuilder::new(mesh_builder_settings).build_chunk_mesh())
+    }
+
+    /// Stores a generated chunk together with its features, which are placed once it's decorated,
+    /// and adds the features its decorated neighbours placed into it.
+    pub fn insert_generated(
+        &self,
+        coordinates: GridCoordinates,
+        chunk: Chunk,
+        features: Vec<BlockWrite>,
+    ) {
+        self.decorations.set_pending(coordinates, features);
+        self.decorations.set_terrain_heights(
+            coordinates,
+            ColumnGrid::from_fn(
+                [coordinates.x, coordinates.z],
+                [
+                    coordinates.x + Chunk::UPPER_BOUND,
+                    coordinates.z + Chunk::UPPER_BOUND,
+                ],
+                |x, z| Self::column_height(&chunk, [x, z].map(|n| n.rem_euclid(Chunk::WIDTH))),
+            ),
+        );
+
+        // the chunk has to be in the grid before the writes are read, otherwise writes
+        // of a neighbour decorated in between would end up neither in the chunk nor in the buffer
+        self.insert(coordinates, Some(chunk));
+
+        if let Some(mut entry) = self.get_mut(&coordinates) {
+            if let Some(chunk) = entry.value_mut() {
+                self.decorations.apply(coordinates, chunk);
+            }
+        }
+    }
+
+    /// Stores a chunk which has been unloaded before, e.g. from the [`ChunkCache`], and adds
+    /// the features and structures which have been placed into it while it was unloaded.
+    ///
+    /// [`ChunkCache`]: super::cache::ChunkCache
+    pub fn restore(&self, coordinates: GridCoordinates, chunk: Chunk) {
+        // see `insert_generated`
+        self.insert(coordinates, Some(chunk));
+
+        if let Some(mut entry) = self.get_mut(&coordinates) {
+            if let Some(chunk) = entry.value_mut() {
+                self.decorations.apply(coordinates, chunk);
+            }
+        }
+    }
+
+    /// Places the features of a chunk, e.g. trees, which may reach into its neighbours.
+    /// This waits until all loaded neighbours have their terrain, so features can't be
+    /// overwritten by it. Blocks for neighbours which are not loaded are kept until they are.
+    ///
+    /// Returns the loaded chunks which have been changed, or `None` if the chunk
+    /// has already been decorated or isn't ready yet.
+    pub fn decorate(&self, coordinates: GridCoordinates) -> Option<Vec<GridCoordinates>> {
+        if self.decorations.is_decorated(coordinates) || !self.has_terrain(coordinates) {
+            return None;
+        }
+
+        let neighbours_ready = (-1..=1)
+            .flat_map(|x| (-1..=1).map(move |z| [x * Chunk::WIDTH, 0, z * Chunk::WIDTH]))
+            .all(|offset| {
+                self.get(&(coordinates + offset))
+                    .is_none_or(|entry| entry.value().is_some())
+            });
+
+        if !neighbours_ready || !self.decorations.mark_decorated(coordinates) {
+            return None;
+        }
+
+        let writes = self.decorations.take_pending(coordinates);
+        let mut changed = Vec::new();
+
+        for write in writes {
+            let Some((target, _)) = GridCoordinates::split_world(write.position) else {
+                continue;
+            };
+
+            // pushed before looking the chunk up, see `insert_generated`
+            self.decorations.push(target, write);
+
+            let Some(mut entry) = self.get_mut(&target) else {
+                continue;
+            };
+
+            if entry
+                .value_mut()
+                .as_mut()
+                .is_some_and(|chunk| self.decorations.apply_feature(target, write, chunk))
+                && !changed.contains(&target)
+            {
+                changed.push(target);
+            }
+        }
+
+        Some(changed)
+    }
+
+    /// The chunk and its loaded neighbours which have been decorated, and so are lit and meshed.
+    /// Their light reaches across the borders into the chunk, so they have to be lit again
+    /// once it's decorated or restored.
+    pub fn decorated_around(&self, coordinates: GridCoordinates) -> Vec<GridCoordinates> {
+        (-1..=1)
+            .flat_map(|x| {
+                (-1..=1).map(move |z| coordinates + [x * Chunk::WIDTH, 0, z * Chunk::WIDTH])
+            })
+            .filter(|neighbour| {
+                self.decorations.is_decorated(*neighbour) && self.has_terrain(*neighbour)
+            })
+            .collect()
+    }
+
+    pub fn is_structure_placed(&self, start: GridCoordinates, salt: u32) -> bool {
+        self.decorations.is_structure_placed(start, salt)
+    }
+
+    /// Writes the blocks of a structure, replacing terrain and features. Blocks for chunks
+    /// which are not loaded are kept until they are. A structure is only placed once,
+    /// identified by its start chunk and the salt of its structure set.
+    ///
+    /// Returns the loaded chunks which have been changed, or `None` if the structure
+    /// has already been placed.
+    pub fn place_structure(
+        &self,
+        start: GridCoordinates,
+        salt: u32,
+        blocks: impl IntoIterator<Item = ([isize; 3], Option<BlockType>)>,
+    ) -> Option<Vec<GridCoordinates>> {
+        if !self.decorations.mark_structure_placed(start, salt) {
+            return None;
+        }
+
+        let mut changed = Vec::new();
+
+        for (position, block) in blocks {
+            let Some((target, local)) = GridCoordinates::split_world(position) else {
+                continue;
+            };
+
+            let block = self
+                .decorations
+                .push_structure(start, target, position, block);
+
+            let Some(mut entry) = self.get_mut(&target) else {
+                continue;
+            };
+            let Some(chunk) = entry.value_mut() else {
+                continue;
+            };
+
+            if chunk.get(local) != block {
+                let [x, y, z] = local.map(|n| n.to_usize());
+                chunk.set(x, y, z, block);
+
+                if !changed.contains(&target) {
+                    changed.push(target);
+                }
+            }
+        }
+
+        Some(changed)
+    }
+
+    /// Whether the terrain of a chunk has been generated.
+    pub fn has_terrain(&self, coordinates: GridCoordinates) -> bool {
+        self.get(&coordinates)
+            .is_some_and(|entry| entry.value().is_some())
+    }
+
+    /// Forgets the features and structures recorded for chunks which are far from all chunks
+    /// in the grid and from those which `is_kept`, e.g. because they're cached, so the records
+    /// don't pile up as the world is explored. The blocks are written again once the chunks
+    /// are generated again.
+    pub fn prune_decorations(&self, is_kept: impl Fn(GridCoordinates) -> bool) {
+        let reach = self.decorations.reach();
+        let is_used = |coordinates| self.contains_key(&coordinates) || is_kept(coordinates);
+
+        for coordinates in self.decorations.recorded_chunks() {
+            let is_near_used = (-reach..=reach)
+                .flat_map(|x| {
+                    (-reach..=reach).map(move |z| [x * Chunk::WIDTH, 0, z * Chunk::WIDTH])
+                })
+                .any(|offset| is_used(coordinates + offset));
+
+            if !is_near_used {
+                self.decorations.forget(coordinates);
+            }
+        }
+    }
+
+    /// Forgets all placed features and structures, e.g. when the world is generated anew.
+    pub fn clear_decorations(&self) {
+        self.decorations.clear();
+    }
+
+    /// Rebuilds light and mesh of a chunk which is already stored in the grid, e.g. after it has been edited.
+    pub fn remesh(
+        &self,
+        coordinates: GridCoordinates,
+        mesh_builder_settings: MeshBuilderSettings,
+    ) -> Option<ChunkMesh> {
+        let light = self.light(coordinates)?;
+
+        self.build_mesh(coordinates, &light, mesh_builder_settings)
+    }
+
+    /// Computes the sky light of a chunk stored in the grid, including the light reaching it
+    /// from its neighbours. Neighbours which aren't generated yet are taken as open sky; chunks
+    /// are only lit once they're decorated, when all their loaded neighbours have their terrain,
+    /// and are lit again when another neighbour is decorated, see [`Self::decorated_around`].
+    pub fn light(&self, coordinates: GridCoordinates) -> Option<ChunkLight> {
+        if !self.has_terrain(coordinates) {
+            return None;
+        }
+
+        let mut blocks = LightBlocks::default();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                // one chunk after another, so the entries aren't locked at the same time
+                let Some(entry) =
+                    self.get(&(coordinates + [x * Chunk::WIDTH, 0, z * Chunk::WIDTH]))
+                else {
+                    continue;
+                };
+
+                if let Some(chunk) = entry.value() {
+                    blocks.add([x, z], chunk);
+                }
+            }
+        }
+
+        Some(ChunkLight::compute(&blocks))
+    }
+
+    /// Builds the mesh of a chunk stored in the grid, shaded by its light.
+    pub fn build_mesh(
+        &self,
+        coordinates: GridCoordinates,
+        light: &ChunkLight,
+        mesh_builder_settings: MeshBuilderSettings,
+    ) -> Option<ChunkMesh> {
+        self.get(&coordinates).and_then(|entry| {
+            entry
+                .value()
+                .as_ref()
+                .map(|chunk| Self::build_lit_mesh(chunk, light, mesh_builder_settings))
+        })
+    }
+
+    fn build_lit_mesh(
+        chunk: &Chunk,
+        light: &ChunkLight,
+        mesh_builder_settings: MeshBuilderSettings,
+    ) -> ChunkMesh {
         let mut builder = MeshBuilder::new(mesh_builder_settings);
 
         for x in 0..Chunk::WIDTH {
             for y in 0..Chunk::HEIGHT {
                 for z in 0..Chunk::WIDTH {
+                    let Some(block) = chunk.get([x, y, z]) else {
+                        continue;
+                    };
+
                     builder.move_to(vec3!(x, y, z));
-                    builder.set_block_type(chunk.get([x, y, z]));
-
-                    if chunk.is_solid([x, y, z]) {
-                        if y == Chunk::HEIGHT - 1 || chunk.is_air([x, y + 1, z]) {
-                            builder.face_top();
-                        }
-                        if y == Chunk::LOWER_BOUND || chunk.is_air([x, y - 1, z]) {
-                            builder.face_bottom();
-                        }
-                        if x == Chunk::UPPER_BOUND || chunk.is_air([x + 1, y, z]) {
-                            builder.face_left();
-                        }
-                        if x == Chunk::LOWER_BOUND || chunk.is_air([x - 1, y, z]) {
-                            builder.face_right();
-                        }
-                        if z == Chunk::UPPER_BOUND || chunk.is_air([x, y, z + 1]) {
-                            builder.face_back();
-                        }
-                        if z == Chunk::LOWER_BOUND || chunk.is_air([x, y, z - 1]) {
-                            builder.face_front();
-                        }
+                    builder.set_block_type(Some(block));
+
+                    // faces are lit by the block they face
+                    if y == Chunk::HEIGHT - 1 || block.shows_face_to(chunk.get([x, y + 1, z])) {
+                        builder.set_brightness(light.brightness([x, y + 1, z]));
+                        builder.face_top();
+                    }
+                    if y == Chunk::LOWER_BOUND || block.shows_face_to(chunk.get([x, y - 1, z])) {
+                        builder.set_brightness(light.brightness([x, y - 1, z]));
+                        builder.face_bottom();
+                    }
+                    if x == Chunk::UPPER_BOUND || block.shows_face_to(chunk.get([x + 1, y, z])) {
+                        builder.set_brightness(light.brightness([x + 1, y, z]));
+                        builder.face_left();
+                    }
+                    if x == Chunk::LOWER_BOUND || block.shows_face_to(chunk.get([x - 1, y, z])) {
+                        builder.set_brightness(light.brightness([x - 1, y, z]));
+                        builder.face_right();
+                    }
+                    if z == Chunk::UPPER_BOUND || block.shows_face_to(chunk.get([x, y, z + 1])) {
+                        builder.set_brightness(light.brightness([x, y, z + 1]));
+                        builder.face_back();
+                    }
+                    if z == Chunk::LOWER_BOUND || block.shows_face_to(chunk.get([x, y, z - 1])) {
+                        builder.set_brightness(light.brightness([x, y, z - 1]));
+                        builder.face_front();
                     }
                 }
             }
         }
 
-        self.insert(coordinates, Some(chunk));
+        builder.build_chunk_mesh()
+    }
+
+    /// Returns the block at the given world position, or `None` if it is air or its chunk is not loaded.
+    pub fn get_block(&self, position: [isize; 3]) -> Option<BlockType> {
+        let (coordinates, local) = GridCoordinates::split_world(position)?;
 
-        builder.build()
+        self.get(&coordinates)
+            .and_then(|entry| entry.value().as_ref().and_then(|chunk| chunk.get(local)))
+    }
+
+    /// Sets the block at the given world position.
+    /// Returns the coordinates of the modified chunk, or `None` if its chunk is not loaded.
+    pub fn set_block(
+        &self,
+        position: [isize; 3],
+        value: Option<BlockType>,
+    ) -> Option<GridCoordinates> {
+        let (coordinates, [x, y, z]) = GridCoordinates::split_world(position)?;
+        let mut entry = self.get_mut(&coordinates)?;
+        let chunk = entry.value_mut().as_mut()?;
+
+        chunk.set(x.to_usize(), y.to_usize(), z.to_usize(), value);
+
+        Some(coordinates)
+    }
+
+    /// The loaded chunks whose light depends on the block at the given world position: its own
+    /// chunk, and the neighbours close enough for light to reach across the border.
+    pub fn lit_by(&self, position: [isize; 3]) -> Vec<GridCoordinates> {
+        let Some((coordinates, [x, _, z])) = GridCoordinates::split_world(position) else {
+            return Vec::new();
+        };
+
+        let neighbours = |n| {
+            let min = if n < LightBlocks::MARGIN { -1 } else { 0 };
+            let max = if n >= Chunk::WIDTH - LightBlocks::MARGIN {
+                1
+            } else {
+                0
+            };
+            min..=max
+        };
+
+        neighbours(x)
+            .flat_map(|x| neighbours(z).map(move |z| [x * Chunk::WIDTH, 0, z * Chunk::WIDTH]))
+            .map(|offset| coordinates + offset)
+            .filter(|coordinates| self.contains_key(coordinates))
+            .collect()
+    }
+
+    /// Returns the y coordinate of the highest solid block in the given world column.
+    pub fn surface_height(&self, x: isize, z: isize) -> Option<isize> {
+        (Chunk::LOWER_BOUND..Chunk::HEIGHT)
+            .rev()
+            .find(|&y| self.get_block([x, y, z]).is_some_and(BlockType::is_solid))
+    }
+
+    /// Returns the y coordinate of the highest solid block of the terrain in the given world column,
+    /// ignoring features like trees.
+    pub fn terrain_height(&self, x: isize, z: isize) -> Option<isize> {
+        let (coordinates, [x, _, z]) = GridCoordinates::split_world([x, 0, z])?;
+        let entry = self.get(&coordinates)?;
+        let chunk = entry.value().as_ref()?;
+
+        Self::column_height(chunk, [x, z])
+    }
+
+    /// Like [`Self::terrain_height`], but of the terrain as it was generated, before features,
+    /// structures and edits were added. Structures are placed on it, so they don't depend on
+    /// each other or on the order they are placed in.
+    pub fn generated_height(&self, x: isize, z: isize) -> Option<isize> {
+        let (coordinates, _) = GridCoordinates::split_world([x, 0, z])?;
+
+        // chunks which have been restored without being generated in this grid aren't recorded
+        self.decorations
+            .terrain_height(coordinates, x, z)
+            .unwrap_or_else(|| self.terrain_height(x, z))
+    }
+
+    fn column_height(chunk: &Chunk, [x, z]: [isize; 2]) -> Option<isize> {
+        (Chunk::LOWER_BOUND..Chunk::HEIGHT).rev().find(|&y| {
+            chunk
+                .get([x, y, z])
+                .is_some_and(|block| block.is_solid() && !block.is_feature())
+        })
+    }
+
+    /// Walks the voxels along the ray using the algorithm by Amanatides & Woo
+    /// and returns the first solid block which is hit.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RaycastHit> {
+        let direction = direction.try_normalize()?;
+        let mut position = origin.floor();
+        let step = direction.signum();
+        // boundaries of axes the ray runs parallel to are never reached, set explicitly
+        // since `0 * inf` is NaN when the origin lies exactly on one of them
+        let is_parallel = direction.cmpeq(Vec3::ZERO);
+        let delta = Vec3::select(is_parallel, Vec3::INFINITY, (Vec3::ONE / direction).abs());
+        let mut distance_to_boundary = Vec3::select(
+            is_parallel,
+            Vec3::INFINITY,
+            Vec3::select(
+                direction.cmpgt(Vec3::ZERO),
+                (position + Vec3::ONE - origin) * delta,
+                (origin - position) * delta,
+            ),
+        );
+        let mut normal = Vec3::ZERO;
+        let mut distance = 0.0;
+
+        while distance <= max_distance {
+            let block = [position.x, position.y, position.z].map(|n| n as isize);
+
+            if self.get_block(block).is_some_and(BlockType::is_solid) {
+                return Some(RaycastHit {
+                    position: block,
+                    normal,
+                    distance,
+                });
+            }
+
+            let axis = if distance_to_boundary.x < distance_to_boundary.y {
+                if distance_to_boundary.x < distance_to_boundary.z {
+                    0
+                } else {
+                    2
+                }
+            } else if distance_to_boundary.y < distance_to_boundary.z {
+                1
+            } else {
+                2
+            };
+
+            distance = distance_to_boundary[axis];
+            distance_to_boundary[axis] += delta[axis];
+            position[axis] += step[axis];
+            normal = Vec3::ZERO;
+            normal[axis] = -step[axis];
+        }
+
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit {
+    /// World position of the block which was hit.
+    pub position: [isize; 3],
+    /// Normal of the face through which the ray entered the block.
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl RaycastHit {
+    /// World position of the air block in front of the face which was hit.
+    pub fn adjacent_position(&self) -> [isize; 3] {
+        let [x, y, z] = self.position;
+        let normal = self.normal.as_ivec3();
+
+        [
+            x + normal.x as isize,
+            y + normal.y as isize,
+            z + normal.z as isize,
+        ]
     }
 }
 
@@ -100,6 +536,33 @@ impl GridCoordinates {
         self
     }
 
+    /// Splits a world block position into the coordinates of the chunk containing it
+    /// and the position of the block inside that chunk.
+    /// Returns `None` if the position is above or below the world.
+    pub fn split_world([x, y, z]: [isize; 3]) -> Option<(Self, [isize; 3])> {
+        if !(Chunk::LOWER_BOUND..Chunk::HEIGHT).contains(&y) {
+            return None;
+        }
+
+        let coordinates = Self::new(
+            x.div_euclid(Chunk::WIDTH) * Chunk::WIDTH,
+            0,
+            z.div_euclid(Chunk::WIDTH) * Chunk::WIDTH,
+        );
+        let local = [x.rem_euclid(Chunk::WIDTH), y, z.rem_euclid(Chunk::WIDTH)];
+
+        Some((coordinates, local))
+    }
+
+    /// Whether the chunk lies within a circle around another chunk, with the radius
+    /// given in chunks.
+    pub fn is_within_radius(self, center: Self, radius: isize) -> bool {
+        let x = (self.x - center.x) / Chunk::WIDTH;
+        let z = (self.z - center.z) / Chunk::WIDTH;
+
+        x * x + z * z <= radius * radius
+    }
+
     pub fn length(self) -> f32 {
         Vec3::new(self.x as f32, self.y as f32, self.z as f32).length()
     }
@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+
+use crate::utils::ToUsize;
+
+use super::{BlockType, Chunk};
+
+/// Sky light of the blocks of a chunk and of the blocks right next to it, from 0 in closed
+/// caves to [`ChunkLight::MAX`] under the open sky. Light spreads from the sky into overhangs
+/// and caves, losing a level with every block, also across chunk borders, so faces on both
+/// sides of a border are lit alike.
+pub struct ChunkLight {
+    levels: Vec<u8>,
+}
+
+/// Which blocks let light through, in a chunk and as far around it as light can reach it from.
+/// Blocks of neighbours which haven't been added, e.g. because they aren't generated yet,
+/// let all light through.
+pub struct LightBlocks {
+    transparent: Vec<bool>,
+}
+
+impl ChunkLight {
+    pub const MAX: u8 = 15;
+    /// Brightness of faces in complete darkness, so caves aren't pitch black.
+    const MIN_BRIGHTNESS: f32 = 0.25;
+    const WIDTH: isize = Chunk::WIDTH + 2;
+
+    pub fn compute(blocks: &LightBlocks) -> Self {
+        let mut levels = vec![0; blocks.transparent.len()];
+        let mut queue = VecDeque::new();
+
+        for x in 0..LightBlocks::WIDTH {
+            for z in 0..LightBlocks::WIDTH {
+                for y in (Chunk::LOWER_BOUND..Chunk::HEIGHT).rev() {
+                    let index = LightBlocks::index([x, y, z]);
+                    if !blocks.transparent[index] {
+                        break;
+                    }
+
+                    levels[index] = Self::MAX;
+                    queue.push_back([x, y, z]);
+                }
+            }
+        }
+
+        while let Some(position @ [x, y, z]) = queue.pop_front() {
+            let level = levels[LightBlocks::index(position)].saturating_sub(1);
+            if level == 0 {
+                continue;
+            }
+
+            let neighbours = [
+                [x - 1, y, z],
+                [x + 1, y, z],
+                [x, y - 1, z],
+                [x, y + 1, z],
+                [x, y, z - 1],
+                [x, y, z + 1],
+            ];
+
+            for neighbour in neighbours {
+                if !LightBlocks::contains(neighbour) {
+                    continue;
+                }
+
+                let index = LightBlocks::index(neighbour);
+                if blocks.transparent[index] && levels[index] < level {
+                    levels[index] = level;
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+
+        // only the chunk and the blocks its faces can face are kept
+        let mut light = Self {
+            levels: vec![0; (Self::WIDTH * Chunk::HEIGHT * Self::WIDTH).to_usize()],
+        };
+        for x in -1..=Chunk::WIDTH {
+            for y in Chunk::LOWER_BOUND..Chunk::HEIGHT {
+                for z in -1..=Chunk::WIDTH {
+                    light.levels[Self::index([x, y, z])] = levels
+                        [LightBlocks::index([x + LightBlocks::MARGIN, y, z + LightBlocks::MARGIN])];
+                }
+            }
+        }
+
+        light
+    }
+
+    /// Light level at a position inside the chunk or right next to it, `None` further away.
+    pub fn level(&self, position: [isize; 3]) -> Option<u8> {
+        Self::contains(position).then(|| self.levels[Self::index(position)])
+    }
+
+    /// How much faces facing the position are lit. Positions above and below the world
+    /// are treated as fully lit.
+    pub fn brightness(&self, position: [isize; 3]) -> f32 {
+        self.level(position).map_or(1.0, |level| {
+            Self::MIN_BRIGHTNESS
+                + (1.0 - Self::MIN_BRIGHTNESS) * f32::from(level) / f32::from(Self::MAX)
+        })
+    }
+
+    fn contains([x, y, z]: [isize; 3]) -> bool {
+        (-1..=Chunk::WIDTH).contains(&x)
+            && (Chunk::LOWER_BOUND..Chunk::HEIGHT).contains(&y)
+            && (-1..=Chunk::WIDTH).contains(&z)
+    }
+
+    fn index([x, y, z]: [isize; 3]) -> usize {
+        (((x + 1) * Chunk::HEIGHT + y) * Self::WIDTH + z + 1).to_usize()
+    }
+}
+
+impl LightBlocks {
+    /// Light from further away fades out before it reaches the blocks next to the chunk.
+    pub const MARGIN: isize = ChunkLight::MAX as isize;
+    const WIDTH: isize = Chunk::WIDTH + 2 * Self::MARGIN;
+
+    /// Adds the blocks of the lit chunk, with an offset of `[0, 0]`, or of one of its
+    /// neighbours, offset by a chunk along x and z.
+    pub fn add(&mut self, [offset_x, offset_z]: [isize; 2], chunk: &Chunk) {
+        for x in 0..Chunk::WIDTH {
+            let padded_x = x + offset_x * Chunk::WIDTH + Self::MARGIN;
+            if !(0..Self::WIDTH).contains(&padded_x) {
+                continue;
+            }
+
+            for z in 0..Chunk::WIDTH {
+                let padded_z = z + offset_z * Chunk::WIDTH + Self::MARGIN;
+                if !(0..Self::WIDTH).contains(&padded_z) {
+                    continue;
+                }
+
+                for y in Chunk::LOWER_BOUND..Chunk::HEIGHT {
+                    self.transparent[Self::index([padded_x, y, padded_z])] =
+                        chunk.get([x, y, z]).is_none_or(BlockType::is_transparent);
+                }
+            }
+        }
+    }
+
+    fn contains([x, y, z]: [isize; 3]) -> bool {
+        (0..Self::WIDTH).contains(&x)
+            && (Chunk::LOWER_BOUND..Chunk::HEIGHT).contains(&y)
+            && (0..Self::WIDTH).contains(&z)
+    }
+
+    fn index([x, y, z]: [isize; 3]) -> usize {
+        ((x * Chunk::HEIGHT + y) * Self::WIDTH + z).to_usize()
+    }
+}
+
+impl Default for LightBlocks {
+    fn default() -> Self {
+        Self {
+            transparent: vec![true; (Self::WIDTH * Chunk::HEIGHT * Self::WIDTH).to_usize()],
+        }
+    }
+}
@@ -3,6 +3,7 @@ use bevy::{
     render::{mesh::Indices, render_resource::PrimitiveTopology},
 };
 use bevy_inspector_egui::{prelude::ReflectInspectorOptions, InspectorOptions};
+use bevy_rapier3d::prelude::Collider;
 
 use crate::{array_texture::ATTRIBUTE_TEXTURE_INDEX, vec3};
 
@@ -20,16 +21,46 @@ impl Default for MeshBuilderSettings {
     }
 }
 
+/// Mesh of a chunk together with the triangles of its solid blocks, which make up its collider.
+pub struct ChunkMesh {
+    pub mesh: Mesh,
+    /// Triangles indexing into the vertex positions of the mesh.
+    pub collider_indices: Vec<[u32; 3]>,
+}
+
+impl ChunkMesh {
+    /// Builds the collider of the solid blocks, or `None` if there are none to collide with.
+    pub fn collider(&self) -> Option<Collider> {
+        // only solid blocks are collided with, so the collider can't be built from the whole mesh
+        (!self.collider_indices.is_empty()).then(|| {
+            let vertices = self
+                .mesh
+                .attribute(Mesh::ATTRIBUTE_POSITION)
+                .and_then(|positions| positions.as_float3())
+                .expect("chunk meshes should have positions")
+                .iter()
+                .map(|&position| Vec3::from(position))
+                .collect();
+
+            Collider::trimesh(vertices, self.collider_indices.clone())
+        })
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct MeshBuilder {
     vertices: Vec<Vec3>,
     indices: Vec<u32>,
+    collider_indices: Vec<[u32; 3]>,
     vertex_count: u32,
     normals: Vec<Vec3>,
     uvs: Vec<[f32; 2]>,
     texture_indices: Vec<u32>,
+    colors: Vec<[f32; 4]>,
     position: Vec3,
     block_type: Option<BlockType>,
+    /// Shade of the next faces, meshes only get vertex colors once it has been set.
+    brightness: Option<f32>,
     settings: MeshBuilderSettings,
 }
 
@@ -39,16 +70,23 @@ impl MeshBuilder {
             settings,
             vertices: Default::default(),
             indices: Default::default(),
+            collider_indices: Default::default(),
             vertex_count: Default::default(),
             normals: Default::default(),
             uvs: Default::default(),
             texture_indices: Default::default(),
+            colors: Default::default(),
             position: Default::default(),
             block_type: Default::default(),
+            brightness: Default::default(),
         }
     }
 
     pub fn build(self) -> Mesh {
+        self.build_chunk_mesh().mesh
+    }
+
+    pub fn build_chunk_mesh(self) -> ChunkMesh {
         let vertices: Vec<_> = self
             .vertices
             .into_iter()
@@ -67,12 +105,20 @@ impl MeshBuilder {
             mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
         }
 
+        if !self.colors.is_empty() {
+            assert_eq!(self.colors.len(), vertices.len());
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        }
+
         mesh.insert_attribute(ATTRIBUTE_TEXTURE_INDEX, self.texture_indices);
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
         mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
         mesh.set_indices(Some(Indices::U32(self.indices)));
 
-        mesh
+        ChunkMesh {
+            mesh,
+            collider_indices: self.collider_indices,
+        }
     }
 
     pub fn move_to(&mut self, position: Vec3) {
@@ -83,12 +129,27 @@ impl MeshBuilder {
         self.block_type = block_type;
     }
 
+    /// Shades the next faces, from 0 for black to 1 for their full color.
+    /// Has to be set before the first face if it's used at all.
+    pub fn set_brightness(&mut self, brightness: f32) {
+        self.brightness = Some(brightness);
+    }
+
     fn add_face(&mut self, unit_vertices: [Vec3; 4], unit_indices: [u32; 6], normal: Vec3) {
         self.vertices
             .extend(unit_vertices.map(|v| v * self.settings.voxel_size + self.position));
-        self.indices
-            .extend(unit_indices.map(|i| i + self.vertex_count));
+        let indices = unit_indices.map(|i| i + self.vertex_count);
+        self.indices.extend(indices);
+        if self.block_type.is_some_and(BlockType::is_solid) {
+            self.collider_indices.extend([
+                [indices[0], indices[1], indices[2]],
+                [indices[3], indices[4], indices[5]],
+            ]);
+        }
         self.normals.extend([normal; 4]);
+        if let Some(brightness) = self.brightness {
+            self.colors.extend([[brightness, brightness, brightness, 1.0]; 4]);
+        }
         self.vertex_count += 4;
         self.uvs
             .extend([[1.0, 1.0], [1.0, 0.0], [0.0, 0.0], [0.0, 1.0]]);
@@ -1,30 +1,49 @@
 use std::sync::Arc;
 
 use bevy::{
+    diagnostic::RegisterDiagnostic,
+    ecs::system::SystemParam,
     prelude::*,
     reflect::TypeUuid,
-    tasks::{AsyncComputeTaskPool, Task},
+    render::primitives::Frustum,
+    utils::HashSet,
 };
 
 use bevy_rapier3d::prelude::*;
 
-use futures_lite::future;
+use serde::{Deserialize, Serialize};
 
 use crate::{settings::Settings, utils::ToUsize, vec3, AppState, VoxelConfig};
 
 use self::{
-    chunk_data_generation_future::ChunkDataGenerationFuture,
-    generator::ChunkGenerator,
+    anvil::AnvilWorld,
+    budget::IntegrationBudget,
+    cache::ChunkCache,
+    generation_queue::GenerationQueue,
+    generator::{
+        graph::{NoiseGraph, NoiseGraphLoader},
+        heightmap::HeightmapImages,
+        void::VoidTerrain,
+        ChunkGenerator, WorldPreset,
+    },
     grid::{ChunkGrid, ChunkGridInner, GridCoordinates},
     mesh_builder::{MeshBuilder, MeshBuilderSettings},
+    pipeline::{ChunkPipeline, ChunkRequest, ChunkTask},
 };
 
 use super::camera_controller::CameraController;
 
-mod chunk_data_generation_future;
+pub mod anvil;
+pub mod block_mapping;
+pub mod budget;
+pub mod cache;
+pub mod decoration;
+mod generation_queue;
 pub mod generator;
 pub mod grid;
+pub mod light;
 pub mod mesh_builder;
+pub mod pipeline;
 
 pub struct ChunkPlugin;
 
@@ -32,19 +51,40 @@ impl Plugin for ChunkPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ChunkGrid>()
             .init_resource::<ChunkGenerator>()
+            .init_resource::<LoadedNoiseGraph>()
+            .init_resource::<LoadedHeightmap>()
+            .init_resource::<GenerationQueue>()
+            .init_resource::<ChunkPipeline>()
+            .init_resource::<IntegrationBudget>()
+            .init_resource::<ChunkCache>()
             .init_asset::<GeneratedChunkData>()
+            .init_asset::<NoiseGraph>()
+            .init_asset_loader::<NoiseGraphLoader>()
+            .add_event::<ChunkEdited>()
             .add_systems(Startup, setup_voxel_material)
+            .add_systems(PreUpdate, IntegrationBudget::begin_frame)
             .add_systems(
                 Update,
                 (
+                    Chunk::apply_noise_settings,
+                    Chunk::reload_world_assets,
+                    Chunk::apply_pipeline_settings,
+                    Chunk::apply_cache_settings,
                     Chunk::trigger_generation,
-                    Chunk::poll_generation_tasks,
+                    Chunk::start_generation_tasks,
+                    Chunk::receive_finished_chunks,
                     Chunk::insert_meshes_and_colliders,
+                    Chunk::decorate_chunks,
+                    Chunk::remesh_edited_chunks,
                     unload_chunks,
                     despawn_chunks,
                 )
                     .run_if(in_state(AppState::InGame)),
             );
+
+        for diagnostic in IntegrationBudget::diagnostics() {
+            app.register_diagnostic(diagnostic);
+        }
     }
 }
 
@@ -63,13 +103,102 @@ fn setup_voxel_material(mut commands: Commands, mut materials: ResMut<Assets<Sta
     });
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Reflect, Serialize, Deserialize)]
 pub enum BlockType {
     Grass,
     Stone,
+    Dirt,
+    Sand,
+    Snow,
+    Gravel,
+    Water,
+    Deepslate,
+    Granite,
+    CoalOre,
+    IronOre,
+    GoldOre,
+    DiamondOre,
+    Log,
+    Leaves,
+    Flower,
+    MossyStone,
 }
 
 impl BlockType {
+    pub const ALL: [BlockType; 17] = [
+        BlockType::Grass,
+        BlockType::Stone,
+        BlockType::Dirt,
+        BlockType::Sand,
+        BlockType::Snow,
+        BlockType::Gravel,
+        BlockType::Water,
+        BlockType::Deepslate,
+        BlockType::Granite,
+        BlockType::CoalOre,
+        BlockType::IronOre,
+        BlockType::GoldOre,
+        BlockType::DiamondOre,
+        BlockType::Log,
+        BlockType::Leaves,
+        BlockType::Flower,
+        BlockType::MossyStone,
+    ];
+
+    /// Name under which the block is stored in files.
+    pub fn name(self) -> &'static str {
+        use BlockType::*;
+
+        match self {
+            Grass => "grass",
+            Stone => "stone",
+            Dirt => "dirt",
+            Sand => "sand",
+            Snow => "snow",
+            Gravel => "gravel",
+            Water => "water",
+            Deepslate => "deepslate",
+            Granite => "granite",
+            CoalOre => "coal_ore",
+            IronOre => "iron_ore",
+            GoldOre => "gold_ore",
+            DiamondOre => "diamond_ore",
+            Log => "log",
+            Leaves => "leaves",
+            Flower => "flower",
+            MossyStone => "mossy_stone",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|block| block.name() == name)
+    }
+
+    /// Average color of the block, used where blocks are shown without textures.
+    pub fn color(self) -> [u8; 3] {
+        use BlockType::*;
+
+        match self {
+            Grass => [95, 159, 53],
+            Stone => [125, 125, 125],
+            Dirt => [134, 96, 67],
+            Sand => [219, 207, 163],
+            Snow => [240, 245, 250],
+            Gravel => [136, 126, 126],
+            Water => [48, 92, 200],
+            Deepslate => [72, 72, 80],
+            Granite => [154, 104, 86],
+            CoalOre => [105, 105, 105],
+            IronOre => [136, 130, 127],
+            GoldOre => [143, 140, 125],
+            DiamondOre => [121, 141, 140],
+            Log => [102, 81, 51],
+            Leaves => [60, 120, 40],
+            Flower => [95, 159, 53],
+            MossyStone => [110, 120, 100],
+        }
+    }
+
     pub fn texture_indices(self) -> TextureIndices {
         use BlockType::*;
 
@@ -82,16 +211,51 @@ impl BlockType {
                 pos_z: 1,
                 neg_z: 1,
             },
-            Stone => TextureIndices {
-                pos_x: 3,
-                neg_x: 3,
-                pos_y: 3,
-                neg_y: 3,
-                pos_z: 3,
-                neg_z: 3,
+            Stone => TextureIndices::all(3),
+            Dirt => TextureIndices::all(2),
+            Sand => TextureIndices::all(4),
+            Snow => TextureIndices::all(5),
+            Gravel => TextureIndices::all(6),
+            Water => TextureIndices::all(7),
+            Deepslate => TextureIndices::all(8),
+            Granite => TextureIndices::all(9),
+            CoalOre => TextureIndices::all(10),
+            IronOre => TextureIndices::all(11),
+            GoldOre => TextureIndices::all(12),
+            DiamondOre => TextureIndices::all(13),
+            Log => TextureIndices {
+                pos_y: 15,
+                neg_y: 15,
+                ..TextureIndices::all(14)
             },
+            Leaves => TextureIndices::all(16),
+            Flower => TextureIndices::all(17),
+            MossyStone => TextureIndices::all(18),
         }
     }
+
+    /// Whether the block can be collided with.
+    pub fn is_solid(self) -> bool {
+        !matches!(self, BlockType::Water | BlockType::Flower)
+    }
+
+    /// Whether blocks behind this one can be seen through it.
+    pub fn is_transparent(self) -> bool {
+        matches!(self, BlockType::Water | BlockType::Flower)
+    }
+
+    /// Whether the block is placed by features like trees, rather than being part of the terrain.
+    pub fn is_feature(self) -> bool {
+        use BlockType::*;
+
+        matches!(self, Log | Leaves | Flower | MossyStone)
+    }
+
+    /// Whether the face of this block towards the given neighbour has to be drawn.
+    /// Faces between blocks of the same transparent type, e.g. within water, are hidden.
+    pub fn shows_face_to(self, neighbour: Option<BlockType>) -> bool {
+        neighbour.is_none_or(|neighbour| neighbour.is_transparent() && neighbour != self)
+    }
 }
 
 pub struct TextureIndices {
@@ -104,6 +268,18 @@ pub struct TextureIndices {
 }
 
 impl TextureIndices {
+    /// Uses the same layer on every side.
+    pub const fn all(index: u32) -> Self {
+        Self {
+            pos_x: index,
+            neg_x: index,
+            pos_y: index,
+            neg_y: index,
+            pos_z: index,
+            neg_z: index,
+        }
+    }
+
     pub fn index_by_normal(self, normal: Vec3) -> u32 {
         if normal == Vec3::X {
             self.pos_x
@@ -127,7 +303,8 @@ impl TextureIndices {
 #[uuid = "d4d4e3e8-a3ea-4d73-95ed-95ed85bf85e5"]
 pub struct GeneratedChunkData {
     pub mesh: Mesh,
-    pub collider: Collider,
+    /// `None` for chunks without solid blocks, which have nothing to collide with.
+    pub collider: Option<Collider>,
 }
 
 #[derive(Component)]
@@ -152,27 +329,25 @@ impl Chunk {
         }
     }
 
+    /// Whether there is a block at the position which can be collided with.
     pub fn is_solid(&self, position: [isize; 3]) -> bool {
-        self.get(position).is_some()
+        self.get(position).is_some_and(BlockType::is_solid)
     }
 
-    pub fn is_air(&self, [x, y, z]: [isize; 3]) -> bool {
-        !self.is_solid([x, y, z])
+    pub fn is_air(&self, position: [isize; 3]) -> bool {
+        self.get(position).is_none()
     }
 
-    fn check_adjacent_chunk(
+    /// Returns the block at a position of an adjacent chunk,
+    /// or `None` if the adjacent chunk is not loaded.
+    fn get_adjacent_chunk(
         &self,
         grid: &Arc<ChunkGridInner>,
         grid_offset: [isize; 3],
         chunk_coordinates: [isize; 3],
-    ) -> bool {
+    ) -> Option<Option<BlockType>> {
         grid.get(&(self.coordinates + grid_offset))
-            .and_then(|r| {
-                r.value()
-                    .as_ref()
-                    .map(|chunk| chunk.is_solid(chunk_coordinates))
-            })
-            .unwrap_or(true)
+            .and_then(|r| r.value().as_ref().map(|chunk| chunk.get(chunk_coordinates)))
     }
 
     pub fn set(&mut self, x: usize, y: usize, z: usize, value: Option<BlockType>) {
@@ -184,6 +359,19 @@ impl Chunk {
         self.data[x][y][z]
     }
 
+    /// FNV-1a hash of the blocks, which stays the same across runs and platforms.
+    /// Used to check that generation is deterministic.
+    pub fn fingerprint(&self) -> u64 {
+        self.data
+            .iter()
+            .flatten()
+            .flatten()
+            .fold(0xcbf29ce484222325, |hash, block| {
+                let byte = block.map_or(0, |block| block as u8 + 1);
+                (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+            })
+    }
+
     /// http://ilkinulas.github.io/development/unity/2016/04/30/cube-mesh-in-unity3d.html
     pub fn compute_mesh(&self, settings: MeshBuilderSettings, grid: Arc<ChunkGridInner>) -> Mesh {
         let mut builder = MeshBuilder::new(settings);
@@ -194,25 +382,27 @@ impl Chunk {
                     builder.move_to(vec3!(x, y, z));
                     builder.set_block_type(self.get([x, y, z]));
 
-                    if self.is_solid([x, y, z]) {
-                        if y == Chunk::HEIGHT - 1 || self.is_air([x, y + 1, z]) {
-                            builder.face_top();
-                        }
-                        if y == Chunk::LOWER_BOUND || self.is_air([x, y - 1, z]) {
-                            builder.face_bottom();
-                        }
-                        if self.adjacent_is_air([x - 1, y, z], &grid) {
-                            builder.face_right();
-                        }
-                        if self.adjacent_is_air([x + 1, y, z], &grid) {
-                            builder.face_left();
-                        }
-                        if self.adjacent_is_air([x, y, z - 1], &grid) {
-                            builder.face_front();
-                        }
-                        if self.adjacent_is_air([x, y, z + 1], &grid) {
-                            builder.face_back();
-                        }
+                    let Some(block) = self.get([x, y, z]) else {
+                        continue;
+                    };
+
+                    if y == Chunk::HEIGHT - 1 || block.shows_face_to(self.get([x, y + 1, z])) {
+                        builder.face_top();
+                    }
+                    if y == Chunk::LOWER_BOUND || block.shows_face_to(self.get([x, y - 1, z])) {
+                        builder.face_bottom();
+                    }
+                    if self.adjacent_shows_face(block, [x - 1, y, z], &grid) {
+                        builder.face_right();
+                    }
+                    if self.adjacent_shows_face(block, [x + 1, y, z], &grid) {
+                        builder.face_left();
+                    }
+                    if self.adjacent_shows_face(block, [x, y, z - 1], &grid) {
+                        builder.face_front();
+                    }
+                    if self.adjacent_shows_face(block, [x, y, z + 1], &grid) {
+                        builder.face_back();
                     }
                 }
             }
@@ -223,50 +413,114 @@ impl Chunk {
         builder.build()
     }
 
-    fn adjacent_is_solid(&self, [x, y, z]: [isize; 3], grid: &Arc<ChunkGridInner>) -> bool {
+    /// Faces towards chunks which are not loaded are hidden.
+    fn adjacent_shows_face(
+        &self,
+        block: BlockType,
+        [x, y, z]: [isize; 3],
+        grid: &Arc<ChunkGridInner>,
+    ) -> bool {
+        let shows_face = |neighbour: Option<Option<BlockType>>| {
+            neighbour.is_some_and(|neighbour| block.shows_face_to(neighbour))
+        };
+
         if x < Chunk::LOWER_BOUND {
-            return self.check_adjacent_chunk(
+            return shows_face(self.get_adjacent_chunk(
                 grid,
                 [-Chunk::WIDTH, 0, 0],
                 [Chunk::UPPER_BOUND, y, z],
-            );
+            ));
         }
         if x > Chunk::UPPER_BOUND {
-            return self.check_adjacent_chunk(
+            return shows_face(self.get_adjacent_chunk(
                 grid,
                 [Chunk::WIDTH, 0, 0],
                 [Chunk::LOWER_BOUND, y, z],
-            );
+            ));
         }
         if z < Chunk::LOWER_BOUND {
-            return self.check_adjacent_chunk(
+            return shows_face(self.get_adjacent_chunk(
                 grid,
                 [0, 0, -Chunk::WIDTH],
                 [x, y, Chunk::UPPER_BOUND],
-            );
+            ));
         }
         if z > Chunk::UPPER_BOUND {
-            return self.check_adjacent_chunk(
+            return shows_face(self.get_adjacent_chunk(
                 grid,
                 [0, 0, Chunk::WIDTH],
                 [x, y, Chunk::LOWER_BOUND],
-            );
+            ));
         }
 
-        self.data[x.to_usize()][y.to_usize()][z.to_usize()].is_some()
-    }
-
-    fn adjacent_is_air(&self, [x, y, z]: [isize; 3], grid: &Arc<ChunkGridInner>) -> bool {
-        !self.adjacent_is_solid([x, y, z], grid)
+        block.shows_face_to(self.data[x.to_usize()][y.to_usize()][z.to_usize()])
     }
 }
 
 impl Chunk {
+    /// Rebuilds the generator when the noise settings change and regenerates the loaded
+    /// chunks in place. Noise graphs and heightmaps are loaded if the preset uses one.
+    fn apply_noise_settings(
+        mut settings: ResMut<Settings>,
+        mut generator: ResMut<ChunkGenerator>,
+        mut assets: WorldAssets,
+        mut loaded: LoadedChunks,
+    ) {
+        if !settings.is_changed() || !settings.detect_changes() {
+            return;
+        }
+
+        assets.load(&settings.noise.preset);
+        *generator = assets.generator(&settings);
+
+        loaded.regenerate(&generator, settings.mesh_builder);
+    }
+
+    /// Rebuilds the generator once the noise graph or heightmap of the world has been loaded,
+    /// and again whenever one of its files changes.
+    fn reload_world_assets(
+        mut graph_events: EventReader<AssetEvent<NoiseGraph>>,
+        mut image_events: EventReader<AssetEvent<Image>>,
+        assets: WorldAssets,
+        settings: Res<Settings>,
+        mut generator: ResMut<ChunkGenerator>,
+        mut loaded: LoadedChunks,
+    ) {
+        // all events are read, so they aren't seen again in the next frame
+        let graph_changed = graph_events
+            .read()
+            .filter(|event| {
+                assets.graph.0.as_ref().is_some_and(|handle| {
+                    event.is_loaded_with_dependencies(handle) || event.is_modified(handle)
+                })
+            })
+            .count()
+            > 0;
+        let heightmap_changed = image_events
+            .read()
+            .filter(|event| match (&assets.heightmap.0, event) {
+                (
+                    Some(images),
+                    AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id },
+                ) => images.contains(*id),
+                _ => false,
+            })
+            .count()
+            > 0;
+
+        if graph_changed || heightmap_changed {
+            *generator = assets.generator(&settings);
+            loaded.regenerate(&generator, settings.mesh_builder);
+        }
+    }
+
+    /// Queues the missing chunks within the render distance for generation. Chunks which are
+    /// still cached are put back into the grid right away, and only need a new mesh.
     fn trigger_generation(
-        mut commands: Commands,
+        mut queue: ResMut<GenerationQueue>,
+        mut cache: ResMut<ChunkCache>,
         player: Query<&Transform, With<CameraController>>,
         grid: Res<ChunkGrid>,
-        generator: Res<ChunkGenerator>,
         settings: Res<Settings>,
     ) {
         if settings.update_chunks {
@@ -277,52 +531,106 @@ impl Chunk {
                 translation.z.round() as isize,
             );
             let player_grid_coordinates = player_xz_coordinates.to_grid();
-            let task_pool = AsyncComputeTaskPool::get();
 
             for x in -settings.render_distance..=settings.render_distance {
                 for z in -settings.render_distance..=settings.render_distance {
                     let chunk_coordinates = player_grid_coordinates
                         + [x * Chunk::WIDTH as isize, 0, z * Chunk::WIDTH as isize];
+                    let is_within_render_distance = chunk_coordinates
+                        .is_within_radius(player_grid_coordinates, settings.render_distance);
+
+                    if is_within_render_distance && !grid.contains_key(&chunk_coordinates) {
+                        match cache.take(chunk_coordinates) {
+                            Some(chunk) => grid.restore(chunk_coordinates, chunk),
+                            None => {
+                                grid.insert(chunk_coordinates, None);
+                            }
+                        }
+                        queue.push(chunk_coordinates);
+                    }
+                }
+            }
+        }
+    }
 
-                    if !grid.contains_key(&chunk_coordinates) {
-                        let generator = generator.clone();
-                        let grid = grid.clone();
+    /// Starts tasks for the most urgent queued chunks, as long as fewer than
+    /// `max_generation_tasks` are running. The queue is sorted again whenever the player moves.
+    fn start_generation_tasks(
+        mut commands: Commands,
+        mut queue: ResMut<GenerationQueue>,
+        player: Query<(Ref<Transform>, Option<&Frustum>), With<CameraController>>,
+        tasks: Query<(), With<GenerateChunk>>,
+        mut edited: EventWriter<ChunkEdited>,
+        sources: ChunkSources,
+        settings: Res<Settings>,
+    ) {
+        if queue.is_empty() {
+            return;
+        }
 
-                        let task = task_pool.spawn(ChunkDataGenerationFuture::new(
-                            chunk_coordinates,
-                            generator,
-                            grid.clone(),
-                            settings.mesh_builder,
-                        ));
+        let (transform, frustum) = player.single();
+        if transform.is_changed() || !queue.is_sorted() {
+            queue.sort(transform.translation, frustum);
+        }
 
-                        commands.spawn((chunk_coordinates, GenerateChunk(task)));
+        let running = tasks.iter().count();
 
-                        grid.insert(chunk_coordinates, None);
-                    }
-                }
+        for _ in running..settings.max_generation_tasks {
+            let Some(coordinates) = queue.pop() else {
+                break;
+            };
+
+            let entity = commands.spawn(coordinates).id();
+
+            // chunks restored from the cache already have their terrain, and are meshed together
+            // with their neighbours if they've been decorated, otherwise once they are
+            if sources.grid.has_terrain(coordinates) {
+                let decorated = sources.grid.decorated_around(coordinates);
+                edited.send_batch(decorated.into_iter().map(ChunkEdited));
+                continue;
             }
+
+            let request = sources.request(entity, coordinates, settings.mesh_builder);
+            let task = sources.pipeline.generate(request);
+
+            commands.entity(entity).insert(GenerateChunk(task));
+        }
+    }
+
+    fn apply_pipeline_settings(settings: Res<Settings>, pipeline: Res<ChunkPipeline>) {
+        if settings.is_changed() {
+            pipeline.set_workers(settings.pipeline);
+        }
+    }
+
+    fn apply_cache_settings(settings: Res<Settings>, mut cache: ResMut<ChunkCache>) {
+        if settings.is_changed() {
+            cache.set_capacity(settings.cached_chunks);
         }
     }
 
-    fn poll_generation_tasks(
+    /// Picks up all chunks the pipeline has finished since the last frame.
+    fn receive_finished_chunks(
         mut commands: Commands,
-        mut generation_tasks: Query<(Entity, &mut GenerateChunk)>,
-        settings: Res<Settings>,
+        tasks: Query<&GenerateChunk>,
+        pipeline: Res<ChunkPipeline>,
         mut chunk_data_assets: ResMut<Assets<GeneratedChunkData>>,
     ) {
-        for (entity, mut task) in generation_tasks
-            .iter_mut()
-            .take(settings.task_polls_per_frame)
-        {
-            let data = future::block_on(future::poll_once(&mut task.0));
-            //info!("polled task");
-            if let Some(data) = data {
-                let handle = chunk_data_assets.add(data);
-
-                commands
-                    .entity(entity)
-                    .insert(handle)
-                    .remove::<GenerateChunk>();
+        for chunk in pipeline.finished() {
+            // chunks which have been despawned or regenerated since are dropped
+            let Ok(GenerateChunk(task)) = tasks.get(chunk.entity) else {
+                continue;
+            };
+            if !task.produced(&chunk) {
+                continue;
+            }
+
+            let mut entity = commands.entity(chunk.entity);
+            entity.remove::<GenerateChunk>();
+
+            // generated chunks get their mesh once they're decorated
+            if let Some(data) = chunk.data {
+                entity.insert(chunk_data_assets.add(data));
             }
         }
     }
@@ -333,36 +641,257 @@ impl Chunk {
         mut chunk_data_assets: ResMut<Assets<GeneratedChunkData>>,
         mut meshes: ResMut<Assets<Mesh>>,
         config: Res<VoxelConfig>,
-        settings: Res<Settings>,
+        mut budget: ResMut<IntegrationBudget>,
         voxel_material: Res<VoxelMaterial>,
     ) {
-        for (entity, handle, coordinates) in query.iter().take(settings.mesh_updates_per_frame) {
+        let integrated = budget.run(&query, |(entity, handle, coordinates)| {
             let GeneratedChunkData { mesh, collider } = chunk_data_assets.remove(handle).unwrap();
 
-            commands
-                .entity(entity)
-                .remove::<Handle<GeneratedChunkData>>()
-                .insert((
-                    MaterialMeshBundle {
-                        mesh: meshes.add(mesh),
-                        //material: config.material.clone(),
-                        material: voxel_material.handle.clone(),
-                        transform: Transform::from_translation((*coordinates).into()),
-                        ..Default::default()
-                    },
-                    collider,
-                    RigidBody::Fixed,
-                ));
+            let mut entity = commands.entity(entity);
+
+            entity.remove::<Handle<GeneratedChunkData>>().insert((
+                MaterialMeshBundle {
+                    mesh: meshes.add(mesh),
+                    //material: config.material.clone(),
+                    material: voxel_material.handle.clone(),
+                    transform: Transform::from_translation((*coordinates).into()),
+                    ..Default::default()
+                },
+                RigidBody::Fixed,
+            ));
+
+            match collider {
+                Some(collider) => entity.insert(collider),
+                None => entity.remove::<Collider>(),
+            };
+        });
+
+        budget.report_backlog(
+            IntegrationBudget::MESH_BACKLOG,
+            query.iter().len() - integrated,
+        );
+    }
+
+    /// Places trees and other features once the terrain around a chunk has been generated.
+    /// Only then is the chunk lit and meshed, together with its decorated neighbours, whose
+    /// light reaches into it.
+    fn decorate_chunks(
+        chunks: Query<&GridCoordinates, Without<DespawnChunk>>,
+        mut edited: EventWriter<ChunkEdited>,
+        grid: Res<ChunkGrid>,
+        mut budget: ResMut<IntegrationBudget>,
+    ) {
+        let decorated = chunks.iter().filter_map(|coordinates| {
+            grid.decorate(*coordinates)
+                .map(|changed| (*coordinates, changed))
+        });
+
+        budget.run(decorated, |(coordinates, changed)| {
+            edited.send_batch(changed.into_iter().map(ChunkEdited));
+            edited.send_batch(
+                grid.decorated_around(coordinates)
+                    .into_iter()
+                    .map(ChunkEdited),
+            );
+        });
+    }
+
+    fn remesh_edited_chunks(
+        mut commands: Commands,
+        mut events: EventReader<ChunkEdited>,
+        mut pending: Local<HashSet<GridCoordinates>>,
+        chunks: Query<(Entity, &GridCoordinates, Has<GenerateChunk>), Without<DespawnChunk>>,
+        sources: ChunkSources,
+        settings: Res<Settings>,
+    ) {
+        pending.extend(events.read().map(|ChunkEdited(coordinates)| *coordinates));
+
+        if pending.is_empty() {
+            return;
+        }
+
+        for (entity, coordinates, is_generating) in &chunks {
+            // chunks which are still being generated keep their edit pending until the task is done
+            if is_generating || !pending.contains(coordinates) {
+                continue;
+            }
+
+            let task = sources.pipeline.remesh(sources.request(
+                entity,
+                *coordinates,
+                settings.mesh_builder,
+            ));
+
+            commands.entity(entity).insert(GenerateChunk(task));
+            pending.remove(coordinates);
         }
+
+        pending.retain(|coordinates| sources.grid.contains_key(coordinates));
     }
 }
 
+/// Dropping the component, e.g. by despawning the chunk, cancels its task.
 #[derive(Component)]
-struct GenerateChunk(Task<GeneratedChunkData>);
+struct GenerateChunk(ChunkTask);
+
+/// Everything chunks are generated from.
+#[derive(SystemParam)]
+struct ChunkSources<'w> {
+    pipeline: Res<'w, ChunkPipeline>,
+    grid: Res<'w, ChunkGrid>,
+    generator: Res<'w, ChunkGenerator>,
+    world: Option<Res<'w, AnvilWorld>>,
+}
 
+impl ChunkSources<'_> {
+    fn request(
+        &self,
+        entity: Entity,
+        coordinates: GridCoordinates,
+        mesh_builder: MeshBuilderSettings,
+    ) -> ChunkRequest {
+        ChunkRequest {
+            entity,
+            coordinates,
+            generator: self.generator.clone(),
+            world: self.world.as_deref().cloned(),
+            grid: self.grid.clone(),
+            mesh_builder,
+        }
+    }
+}
+
+/// Noise graph the terrain of the world is generated from, if its preset uses one.
+#[derive(Resource, Default)]
+struct LoadedNoiseGraph(Option<Handle<NoiseGraph>>);
+
+/// Images the terrain of the world is generated from, if its preset uses a heightmap.
+#[derive(Resource, Default)]
+struct LoadedHeightmap(Option<HeightmapImages>);
+
+/// Assets the terrain of the world may be generated from.
+#[derive(SystemParam)]
+struct WorldAssets<'w> {
+    asset_server: Res<'w, AssetServer>,
+    graph: ResMut<'w, LoadedNoiseGraph>,
+    graphs: Res<'w, Assets<NoiseGraph>>,
+    heightmap: ResMut<'w, LoadedHeightmap>,
+    images: Res<'w, Assets<Image>>,
+}
+
+impl WorldAssets<'_> {
+    /// Starts loading the assets the preset uses, and drops the ones it doesn't.
+    fn load(&mut self, preset: &WorldPreset) {
+        self.graph.0 = match preset {
+            WorldPreset::Graph { path } => Some(self.asset_server.load(path.clone())),
+            _ => None,
+        };
+        self.heightmap.0 = match preset {
+            WorldPreset::Heightmap(settings) => {
+                Some(HeightmapImages::load(settings.clone(), &self.asset_server))
+            }
+            _ => None,
+        };
+    }
+
+    /// Generator of the world, which stays empty while its assets are loading.
+    fn generator(&self, settings: &Settings) -> ChunkGenerator {
+        let seed = settings.noise.seed;
+
+        if let Some(graph) = self
+            .graph
+            .0
+            .as_ref()
+            .and_then(|handle| self.graphs.get(handle))
+        {
+            return ChunkGenerator::from_graph(seed, graph);
+        }
+
+        let terrain = self
+            .heightmap
+            .0
+            .as_ref()
+            .and_then(|images| Some((images, images.terrain(&self.images)?)));
+
+        match terrain {
+            Some((_, Ok(terrain))) => ChunkGenerator::with_terrain(seed, terrain),
+            Some((images, Err(error))) => {
+                error!(
+                    "failed to load heightmap {}: {error}",
+                    images.settings().path
+                );
+                ChunkGenerator::with_terrain(seed, VoidTerrain)
+            }
+            None => settings.noise.generator(),
+        }
+    }
+}
+
+/// The loaded chunks, for regenerating them when the generator changes.
+#[derive(SystemParam)]
+struct LoadedChunks<'w, 's> {
+    commands: Commands<'w, 's>,
+    chunks: Query<'w, 's, (Entity, &'static GridCoordinates), Without<DespawnChunk>>,
+    player: Query<'w, 's, &'static Transform, With<CameraController>>,
+    pipeline: Res<'w, ChunkPipeline>,
+    grid: Res<'w, ChunkGrid>,
+    cache: ResMut<'w, ChunkCache>,
+    world: Option<Res<'w, AnvilWorld>>,
+}
+
+impl LoadedChunks<'_, '_> {
+    /// Regenerates all loaded chunks in place. Chunks keep their old mesh until the new one
+    /// is ready, and the chunks nearest to the player are regenerated first.
+    fn regenerate(&mut self, generator: &ChunkGenerator, mesh_builder: MeshBuilderSettings) {
+        self.grid.clear_decorations();
+        self.cache.clear();
+
+        let player = self
+            .player
+            .get_single()
+            .map_or(Vec3::ZERO, |transform| transform.translation);
+        let mut chunks: Vec<_> = self.chunks.iter().collect();
+        chunks.sort_by_key(|(_, coordinates)| {
+            let x = coordinates.x + Chunk::WIDTH / 2 - player.x.round() as isize;
+            let z = coordinates.z + Chunk::WIDTH / 2 - player.z.round() as isize;
+            x * x + z * z
+        });
+
+        // every chunk waits for its new terrain first, so none is decorated next to old terrain
+        for (_, coordinates) in &chunks {
+            self.grid.insert(**coordinates, None);
+        }
+
+        for (entity, coordinates) in chunks {
+            let task = self.pipeline.generate(ChunkRequest {
+                entity,
+                coordinates: *coordinates,
+                generator: generator.clone(),
+                world: self.world.as_deref().cloned(),
+                grid: self.grid.clone(),
+                mesh_builder,
+            });
+
+            // replaces the task of a chunk which is still being generated with the old generator
+            self.commands.entity(entity).insert(GenerateChunk(task));
+        }
+    }
+}
+
+/// Sent after blocks of a loaded chunk or next to it have been modified, or once it's been
+/// decorated, so its light, mesh and collider get rebuilt.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ChunkEdited(pub GridCoordinates);
+
+/// Marks chunks beyond the render distance and the unload margin for despawning, and discards
+/// queued chunks which have left it before their generation starts. The terrain of unloaded
+/// chunks is kept in the cache, and the decorations of chunks far from the loaded and cached
+/// ones are forgotten.
 fn unload_chunks(
     mut commands: Commands,
     mut chunks: Query<(Entity, &GridCoordinates)>,
+    mut queue: ResMut<GenerationQueue>,
+    mut cache: ResMut<ChunkCache>,
     player: Query<&Transform, With<CameraController>>,
     grid: Res<ChunkGrid>,
     settings: Res<Settings>,
@@ -375,33 +904,58 @@ fn unload_chunks(
             translation.z.round() as isize,
         );
         let player_grid_coordinates = player_xz_coordinates.to_grid();
-        let bounds_distance = Chunk::WIDTH as isize * settings.render_distance;
+        let unload_distance = settings.render_distance + settings.unload_margin.max(0);
+        let is_outside_unload_distance = |coordinates: &GridCoordinates| {
+            !coordinates.is_within_radius(player_grid_coordinates, unload_distance)
+        };
+        let mut unloaded = false;
+        let mut unload = |coordinates: &GridCoordinates| {
+            unloaded = true;
+
+            // chunks which are still waiting for their terrain have nothing worth keeping
+            if let Some((_, Some(chunk))) = grid.remove(coordinates) {
+                cache.insert(*coordinates, chunk);
+            }
+        };
 
         for (entity, coordinates) in &mut chunks {
-            let is_outside_pos_x = player_grid_coordinates.x + bounds_distance < coordinates.x;
-            let is_outside_neg_x = player_grid_coordinates.x - bounds_distance > coordinates.x;
-            let is_outside_pos_z = player_grid_coordinates.z + bounds_distance < coordinates.z;
-            let is_outside_neg_z = player_grid_coordinates.z - bounds_distance > coordinates.z;
-
-            let is_outside_render_distance =
-                is_outside_pos_x || is_outside_neg_x || is_outside_pos_z || is_outside_neg_z;
-
-            if is_outside_render_distance && grid.contains_key(coordinates) {
-                grid.remove(coordinates);
+            if is_outside_unload_distance(coordinates) && grid.contains_key(coordinates) {
+                // a running generation task notices the missing entry and stops
+                unload(coordinates);
                 commands.entity(entity).insert(DespawnChunk);
             }
         }
+
+        queue.retain(|coordinates| {
+            let is_outside = is_outside_unload_distance(&coordinates);
+            if is_outside {
+                unload(&coordinates);
+            }
+
+            !is_outside
+        });
+
+        if unloaded {
+            grid.prune_decorations(|coordinates| cache.contains(coordinates));
+        }
     }
 }
 
 #[derive(Component)]
 pub struct DespawnChunk;
 
+/// Chunks which are still being generated are despawned as well, which cancels their task.
 fn despawn_chunks(
     mut commands: Commands,
-    chunks: Query<Entity, (With<DespawnChunk>, Without<GenerateChunk>)>,
+    chunks: Query<Entity, With<DespawnChunk>>,
+    mut budget: ResMut<IntegrationBudget>,
 ) {
-    for entity in chunks.iter().take(20) {
+    let despawned = budget.run(&chunks, |entity| {
         commands.entity(entity).despawn();
-    }
+    });
+
+    budget.report_backlog(
+        IntegrationBudget::DESPAWN_BACKLOG,
+        chunks.iter().len() - despawned,
+    );
 }
@@ -0,0 +1,372 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+use bevy::{prelude::*, tasks::AsyncComputeTaskPool};
+use bevy_inspector_egui::{prelude::ReflectInspectorOptions, InspectorOptions};
+
+use super::{
+    anvil::AnvilWorld,
+    generator::ChunkGenerator,
+    grid::{ChunkGrid, GridCoordinates},
+    light::ChunkLight,
+    mesh_builder::{ChunkMesh, MeshBuilderSettings},
+    Chunk, GeneratedChunkData,
+};
+
+/// Workers per stage of the chunk pipeline. Stages run on the async compute task pool,
+/// so more workers than threads only make stages compete with each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect, InspectorOptions)]
+#[reflect(InspectorOptions)]
+pub struct PipelineWorkers {
+    #[inspector(min = 1)]
+    pub generate: usize,
+    #[inspector(min = 1)]
+    pub decorate: usize,
+    #[inspector(min = 1)]
+    pub light: usize,
+    #[inspector(min = 1)]
+    pub mesh: usize,
+    #[inspector(min = 1)]
+    pub collider: usize,
+}
+
+impl Default for PipelineWorkers {
+    fn default() -> Self {
+        Self {
+            generate: 4,
+            decorate: 2,
+            light: 2,
+            mesh: 4,
+            collider: 2,
+        }
+    }
+}
+
+/// A chunk to be generated or remeshed, together with everything the stages need for it.
+pub struct ChunkRequest {
+    /// Entity the finished chunk belongs to.
+    pub entity: Entity,
+    pub coordinates: GridCoordinates,
+    pub generator: ChunkGenerator,
+    pub world: Option<AnvilWorld>,
+    pub grid: ChunkGrid,
+    pub mesh_builder: MeshBuilderSettings,
+}
+
+/// Handle of a chunk in the pipeline. Dropping it cancels the chunk before its next stage.
+pub struct ChunkTask {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ChunkTask {
+    /// Whether the finished chunk has been produced for this task, rather than for
+    /// an earlier one of the same entity which it replaced.
+    pub fn produced(&self, chunk: &FinishedChunk) -> bool {
+        Arc::ptr_eq(&self.cancelled, &chunk.cancelled)
+    }
+}
+
+impl Drop for ChunkTask {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+}
+
+/// A chunk which has passed all its stages.
+pub struct FinishedChunk {
+    pub entity: Entity,
+    /// Mesh and collider of the chunk, or `None` if only its terrain has been generated.
+    /// Generated chunks are lit and meshed after they've been decorated on the main thread,
+    /// which waits for the terrain of their neighbours.
+    pub data: Option<GeneratedChunkData>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Generates chunks in stages: generate → decorate, which plans the features of the chunk and
+/// stores it in the grid, and light → mesh → collider once the chunk has been decorated and is
+/// remeshed. Every stage has its own queue and workers, and finished chunks are sent back
+/// over a channel.
+///
+/// Chunks are dropped between stages once their task is dropped, or once they've been
+/// removed from the grid, since nobody will see them.
+#[derive(Resource, Clone)]
+pub struct ChunkPipeline(Arc<PipelineInner>);
+
+struct PipelineInner {
+    stages: [StageQueue; 5],
+    finished: Sender<FinishedChunk>,
+    receiver: Mutex<Receiver<FinishedChunk>>,
+}
+
+#[derive(Default)]
+struct StageQueue {
+    jobs: Mutex<VecDeque<Job>>,
+    running: AtomicUsize,
+    workers: AtomicUsize,
+}
+
+#[derive(Clone, Copy)]
+enum Stage {
+    Generate,
+    Decorate,
+    Light,
+    Mesh,
+    Collider,
+}
+
+struct Job {
+    request: ChunkRequest,
+    cancelled: Arc<AtomicBool>,
+    input: StageInput,
+}
+
+enum StageInput {
+    None,
+    Chunk(Chunk),
+    Light(ChunkLight),
+    Mesh(ChunkMesh),
+}
+
+impl Default for ChunkPipeline {
+    fn default() -> Self {
+        Self::new(PipelineWorkers::default())
+    }
+}
+
+impl ChunkPipeline {
+    pub fn new(workers: PipelineWorkers) -> Self {
+        let (finished, receiver) = mpsc::channel();
+        let pipeline = Self(Arc::new(PipelineInner {
+            stages: Default::default(),
+            finished,
+            receiver: Mutex::new(receiver),
+        }));
+        pipeline.set_workers(workers);
+
+        pipeline
+    }
+
+    /// Changes the number of workers. Stages with more workers than allowed finish their
+    /// current jobs first.
+    pub fn set_workers(&self, workers: PipelineWorkers) {
+        let counts = [
+            workers.generate,
+            workers.decorate,
+            workers.light,
+            workers.mesh,
+            workers.collider,
+        ];
+
+        for (stage, count) in [
+            Stage::Generate,
+            Stage::Decorate,
+            Stage::Light,
+            Stage::Mesh,
+            Stage::Collider,
+        ]
+        .into_iter()
+        .zip(counts)
+        {
+            let queue = self.queue(stage);
+            queue.workers.store(count.max(1), Ordering::Release);
+
+            if !queue.jobs.lock().unwrap().is_empty() {
+                self.start_worker(stage);
+            }
+        }
+    }
+
+    /// Generates a chunk, or imports it from the world, and stores it in the grid. It's finished
+    /// without a mesh, which is built by [`Self::remesh`] once it's been decorated.
+    pub fn generate(&self, request: ChunkRequest) -> ChunkTask {
+        self.push(Stage::Generate, request, StageInput::None)
+    }
+
+    /// Rebuilds light, mesh and collider of a chunk already stored in the grid.
+    pub fn remesh(&self, request: ChunkRequest) -> ChunkTask {
+        self.push(Stage::Light, request, StageInput::None)
+    }
+
+    /// Takes the chunks which have been finished since the last call.
+    pub fn finished(&self) -> Vec<FinishedChunk> {
+        self.0.receiver.lock().unwrap().try_iter().collect()
+    }
+
+    fn push(&self, stage: Stage, request: ChunkRequest, input: StageInput) -> ChunkTask {
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        self.enqueue(
+            stage,
+            Job {
+                request,
+                cancelled: cancelled.clone(),
+                input,
+            },
+        );
+
+        ChunkTask { cancelled }
+    }
+
+    fn queue(&self, stage: Stage) -> &StageQueue {
+        &self.0.stages[stage as usize]
+    }
+
+    fn enqueue(&self, stage: Stage, job: Job) {
+        self.queue(stage).jobs.lock().unwrap().push_back(job);
+        self.start_worker(stage);
+    }
+
+    /// Starts another worker for the stage, unless it has all its workers already.
+    fn start_worker(&self, stage: Stage) {
+        let queue = self.queue(stage);
+
+        let started = queue
+            .running
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |running| {
+                (running < queue.workers.load(Ordering::Acquire)).then_some(running + 1)
+            })
+            .is_ok();
+
+        if started {
+            let pipeline = self.clone();
+            AsyncComputeTaskPool::get()
+                .spawn(async move { pipeline.work(stage) })
+                .detach();
+        }
+    }
+
+    /// Runs jobs of the stage until its queue is empty.
+    fn work(&self, stage: Stage) {
+        let queue = self.queue(stage);
+        let _worker = RunningWorker {
+            pipeline: self,
+            stage,
+        };
+
+        loop {
+            if queue.running.load(Ordering::Acquire) > queue.workers.load(Ordering::Acquire) {
+                break;
+            }
+
+            let Some(job) = queue.jobs.lock().unwrap().pop_front() else {
+                break;
+            };
+
+            self.run(stage, job);
+        }
+    }
+
+    fn run(&self, stage: Stage, job: Job) {
+        let Job {
+            request,
+            cancelled,
+            input,
+        } = job;
+        let coordinates = request.coordinates;
+
+        if cancelled.load(Ordering::Acquire) || !request.grid.contains_key(&coordinates) {
+            return;
+        }
+
+        let (next, input) = match (stage, input) {
+            (Stage::Generate, _) => {
+                let position = coordinates.into();
+                let imported = request.world.as_ref().and_then(|world| {
+                    world
+                        .load_chunk(position)
+                        .map_err(|error| error!("failed to import chunk at {position:?}: {error}"))
+                        .ok()
+                        .flatten()
+                });
+
+                match imported {
+                    // imported worlds already have their features
+                    Some(chunk) => {
+                        request
+                            .grid
+                            .insert_generated(coordinates, chunk, Vec::new());
+                        self.finish(request.entity, cancelled, None);
+                        return;
+                    }
+                    None => (
+                        Stage::Decorate,
+                        StageInput::Chunk(request.generator.generate_chunk(position)),
+                    ),
+                }
+            }
+            (Stage::Decorate, StageInput::Chunk(chunk)) => {
+                let features = request.generator.decorate(&chunk, coordinates.into());
+                request.grid.insert_generated(coordinates, chunk, features);
+                self.finish(request.entity, cancelled, None);
+                return;
+            }
+            (Stage::Light, _) => match request.grid.light(coordinates) {
+                Some(light) => (Stage::Mesh, StageInput::Light(light)),
+                None => return,
+            },
+            (Stage::Mesh, StageInput::Light(light)) => {
+                match request
+                    .grid
+                    .build_mesh(coordinates, &light, request.mesh_builder)
+                {
+                    Some(mesh) => (Stage::Collider, StageInput::Mesh(mesh)),
+                    None => return,
+                }
+            }
+            (Stage::Collider, StageInput::Mesh(mesh)) => {
+                let collider = mesh.collider();
+                let data = GeneratedChunkData {
+                    mesh: mesh.mesh,
+                    collider,
+                };
+                self.finish(request.entity, cancelled, Some(data));
+                return;
+            }
+            _ => unreachable!("stages should get the output of the stage before them"),
+        };
+
+        self.enqueue(
+            next,
+            Job {
+                request,
+                cancelled,
+                input,
+            },
+        );
+    }
+
+    fn finish(&self, entity: Entity, cancelled: Arc<AtomicBool>, data: Option<GeneratedChunkData>) {
+        // the receiver lives as long as the pipeline
+        let _ = self.0.finished.send(FinishedChunk {
+            entity,
+            data,
+            cancelled,
+        });
+    }
+}
+
+/// Counts a worker of a stage as running until it's dropped, also when one of its jobs
+/// panics, so the stage doesn't run out of workers.
+struct RunningWorker<'a> {
+    pipeline: &'a ChunkPipeline,
+    stage: Stage,
+}
+
+impl Drop for RunningWorker<'_> {
+    fn drop(&mut self) {
+        let queue = self.pipeline.queue(self.stage);
+        queue.running.fetch_sub(1, Ordering::AcqRel);
+
+        // a job may have been queued after the queue was found empty, but before this
+        // worker was done, in which case no new worker has been started for it
+        if !queue.jobs.lock().unwrap().is_empty() {
+            self.pipeline.start_worker(self.stage);
+        }
+    }
+}
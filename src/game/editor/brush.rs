@@ -0,0 +1,168 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_inspector_egui::{prelude::ReflectInspectorOptions, InspectorOptions};
+
+use crate::game::chunk::{grid::ChunkGridInner, BlockType, Chunk};
+
+use super::history::EditBatch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum BrushShape {
+    Sphere,
+    Cube,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum BrushMode {
+    Raise,
+    Lower,
+    Smooth,
+    Flatten,
+    Paint,
+}
+
+/// Freeform terrain brush. All modes operate on the terrain surface of the columns
+/// inside the brush footprint.
+#[derive(Debug, Clone, Resource, Reflect, InspectorOptions)]
+#[reflect(InspectorOptions)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub mode: BrushMode,
+    #[inspector(min = 1, max = 32)]
+    pub radius: isize,
+    /// Fraction of the radius by which terrain is raised or lowered,
+    /// or how far smoothing and flattening move the surface towards their target.
+    #[inspector(min = 0.0, max = 1.0)]
+    pub strength: f32,
+    #[inspector(min = 0, max = 255)]
+    pub flatten_height: isize,
+    pub paint_block: BlockType,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            shape: BrushShape::Sphere,
+            mode: BrushMode::Raise,
+            radius: 4,
+            strength: 0.5,
+            flatten_height: 64,
+            paint_block: BlockType::Stone,
+        }
+    }
+}
+
+impl Brush {
+    pub fn apply(&self, grid: &ChunkGridInner, center: [isize; 3]) -> EditBatch {
+        let [center_x, center_y, center_z] = center;
+        let mut batch = EditBatch::default();
+
+        // the footprint is extended by one column so smoothing can look at the surrounding surface
+        let heights: HashMap<(isize, isize), isize> = self
+            .columns(1)
+            .filter_map(|(dx, dz)| {
+                let (x, z) = (center_x + dx, center_z + dz);
+                self.surface_near(grid, x, z, center_y)
+                    .map(|height| ((x, z), height))
+            })
+            .collect();
+
+        for (dx, dz) in self.columns(0) {
+            let weight = self.weight(dx, dz);
+            let (x, z) = (center_x + dx, center_z + dz);
+
+            let Some(&height) = heights.get(&(x, z)) else {
+                continue;
+            };
+
+            if weight <= 0.0 {
+                continue;
+            }
+
+            let target = match self.mode {
+                BrushMode::Raise => height + self.amount(weight),
+                BrushMode::Lower => height - self.amount(weight),
+                BrushMode::Smooth => {
+                    let surrounding: Vec<isize> = (-1..=1)
+                        .flat_map(|ox| (-1..=1).map(move |oz| (x + ox, z + oz)))
+                        .filter_map(|column| heights.get(&column).copied())
+                        .collect();
+                    let average =
+                        surrounding.iter().sum::<isize>() as f32 / surrounding.len() as f32;
+                    self.towards(height, average, weight)
+                }
+                BrushMode::Flatten => self.towards(height, self.flatten_height as f32, weight),
+                BrushMode::Paint => {
+                    batch.set(grid, [x, height, z], Some(self.paint_block));
+                    continue;
+                }
+            };
+
+            Self::set_column_height(&mut batch, grid, x, z, height, target);
+        }
+
+        batch
+    }
+
+    fn columns(&self, margin: isize) -> impl Iterator<Item = (isize, isize)> {
+        let extent = self.radius + margin;
+        (-extent..=extent).flat_map(move |dx| (-extent..=extent).map(move |dz| (dx, dz)))
+    }
+
+    /// Influence of the brush on a column, `1.0` at the center and `0.0` outside of the brush.
+    fn weight(&self, dx: isize, dz: isize) -> f32 {
+        match self.shape {
+            BrushShape::Sphere => {
+                let distance =
+                    Vec2::new(dx as f32, dz as f32).length() / (self.radius as f32 + 0.5);
+                (1.0 - distance * distance).max(0.0).sqrt()
+            }
+            BrushShape::Cube => 1.0,
+        }
+    }
+
+    fn amount(&self, weight: f32) -> isize {
+        (self.strength * self.radius as f32 * weight).round() as isize
+    }
+
+    fn towards(&self, height: isize, target: f32, weight: f32) -> isize {
+        height + ((target - height as f32) * self.strength * weight).round() as isize
+    }
+
+    /// Finds the highest solid block of a column, starting the search above the brush center
+    /// so that terrain above an overhang is not affected.
+    fn surface_near(&self, grid: &ChunkGridInner, x: isize, z: isize, y: isize) -> Option<isize> {
+        let top = (y + self.radius).min(Chunk::HEIGHT - 1);
+        let bottom = (y - 2 * self.radius).max(Chunk::LOWER_BOUND);
+
+        (bottom..=top)
+            .rev()
+            .find(|&y| grid.get_block([x, y, z]).is_some())
+    }
+
+    /// Moves the surface of a column to the target height, keeping its top block on top.
+    fn set_column_height(
+        batch: &mut EditBatch,
+        grid: &ChunkGridInner,
+        x: isize,
+        z: isize,
+        height: isize,
+        target: isize,
+    ) {
+        let target = target.clamp(Chunk::LOWER_BOUND, Chunk::HEIGHT - 1);
+        let surface = grid.get_block([x, height, z]);
+
+        if target > height {
+            let subsurface = grid.get_block([x, height - 1, z]).or(surface);
+
+            for y in height..target {
+                batch.set(grid, [x, y, z], subsurface);
+            }
+        } else if target < height {
+            for y in target + 1..=height {
+                batch.set(grid, [x, y, z], None);
+            }
+        }
+
+        batch.set(grid, [x, target, z], surface);
+    }
+}
@@ -0,0 +1,230 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::{prelude::ReflectInspectorOptions, InspectorOptions};
+
+use crate::game::{
+    chunk::{
+        block_mapping::BlockMapping,
+        grid::{ChunkGrid, GridCoordinates},
+        Chunk, ChunkEdited,
+    },
+    export::heightmap::HeightmapExport,
+    schematic::{vox::VoxModel, Schematic},
+};
+
+use super::{
+    history::{EditBatch, EditHistory},
+    Editor,
+};
+
+/// Box spanned by two corner blocks, both inclusive.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Selection {
+    pub first: Option<[isize; 3]>,
+    pub second: Option<[isize; 3]>,
+}
+
+impl Selection {
+    pub fn corners(&self) -> Option<([isize; 3], [isize; 3])> {
+        self.first.zip(self.second)
+    }
+}
+
+/// Schematic which has been copied from the selection or loaded from a file, ready to be pasted.
+#[derive(Resource, Default)]
+pub struct Clipboard {
+    pub selection: Selection,
+    pub schematic: Option<Schematic>,
+    mapping: BlockMapping,
+    /// MagicaVoxel model the clipboard follows, until something else is copied or loaded.
+    prop: Option<Handle<VoxModel>>,
+}
+
+#[derive(Debug, Clone, Resource, Reflect, InspectorOptions)]
+#[reflect(InspectorOptions)]
+pub struct SchematicSettings {
+    /// File the clipboard is saved to and loaded from. The extension selects the format,
+    /// `.schem` for Sponge schematics, `.vox` for MagicaVoxel models or `.vschem` for the native format.
+    pub path: String,
+    /// MagicaVoxel model in `assets/` which is loaded into the clipboard. It's loaded again
+    /// whenever the file changes, so props can be touched up while placing them.
+    pub prop_path: String,
+    /// Whether air inside the schematic replaces blocks when pasting.
+    pub paste_air: bool,
+    /// PNG file the heightmap of the selection is exported to, the colour map of its
+    /// top blocks is saved next to it. Heightmaps in `assets/` can be used by the heightmap preset.
+    pub heightmap_path: String,
+}
+
+impl Default for SchematicSettings {
+    fn default() -> Self {
+        Self {
+            path: "schematics/clipboard.schem".to_string(),
+            prop_path: "props/boulder.vox".to_string(),
+            paste_air: false,
+            heightmap_path: "assets/heightmaps/selection.png".to_string(),
+        }
+    }
+}
+
+impl Clipboard {
+    pub(super) fn select_corners(
+        editor: Res<Editor>,
+        mut clipboard: ResMut<Clipboard>,
+        input: Res<Input<KeyCode>>,
+    ) {
+        if let Some(target) = editor.target {
+            if input.just_pressed(KeyCode::Key1) {
+                clipboard.selection.first = Some(target.position);
+            }
+            if input.just_pressed(KeyCode::Key2) {
+                clipboard.selection.second = Some(target.position);
+            }
+            if input.just_pressed(KeyCode::Key3) {
+                // selects the whole chunk under the cursor
+                if let Some((coordinates, _)) = GridCoordinates::split_world(target.position) {
+                    let [x, _, z]: [isize; 3] = coordinates.into();
+                    clipboard.selection.first = Some([x, Chunk::LOWER_BOUND, z]);
+                    clipboard.selection.second = Some([
+                        x + Chunk::UPPER_BOUND,
+                        Chunk::HEIGHT - 1,
+                        z + Chunk::UPPER_BOUND,
+                    ]);
+                }
+            }
+        }
+    }
+
+    pub(super) fn copy_and_paste(
+        editor: Res<Editor>,
+        mut clipboard: ResMut<Clipboard>,
+        mut history: ResMut<EditHistory>,
+        mut chunk_edited: EventWriter<ChunkEdited>,
+        settings: Res<SchematicSettings>,
+        grid: Res<ChunkGrid>,
+        input: Res<Input<KeyCode>>,
+    ) {
+        if !input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+            return;
+        }
+
+        if input.just_pressed(KeyCode::C) {
+            if let Some((first, second)) = clipboard.selection.corners() {
+                clipboard.schematic = Some(Schematic::from_grid(&grid, first, second));
+                clipboard.prop = None;
+            }
+        }
+
+        if input.just_pressed(KeyCode::V) {
+            if let Some((target, schematic)) = editor.target.zip(clipboard.schematic.as_ref()) {
+                let mut batch = EditBatch::default();
+
+                schematic.paste(
+                    target.adjacent_position(),
+                    settings.paste_air,
+                    |position, block| batch.set(&grid, position, block),
+                );
+
+                for coordinates in batch.modified_chunks() {
+                    chunk_edited.send(ChunkEdited(coordinates));
+                }
+
+                history.record(batch);
+            }
+        }
+    }
+
+    pub(super) fn save_and_load(
+        mut clipboard: ResMut<Clipboard>,
+        settings: Res<SchematicSettings>,
+        input: Res<Input<KeyCode>>,
+    ) {
+        if input.just_pressed(KeyCode::F5) {
+            if let Some(schematic) = &clipboard.schematic {
+                match schematic.save(&settings.path, &clipboard.mapping) {
+                    Ok(()) => info!("saved schematic to {}", settings.path),
+                    Err(error) => error!("failed to save schematic to {}: {error}", settings.path),
+                }
+            }
+        }
+
+        if input.just_pressed(KeyCode::F9) {
+            match Schematic::load(&settings.path, &clipboard.mapping) {
+                Ok(schematic) => {
+                    clipboard.schematic = Some(schematic);
+                    clipboard.prop = None;
+                }
+                Err(error) => error!("failed to load schematic from {}: {error}", settings.path),
+            }
+        }
+    }
+
+    pub(super) fn load_prop(
+        mut clipboard: ResMut<Clipboard>,
+        settings: Res<SchematicSettings>,
+        asset_server: Res<AssetServer>,
+        input: Res<Input<KeyCode>>,
+    ) {
+        if input.just_pressed(KeyCode::F8) {
+            clipboard.prop = Some(asset_server.load(settings.prop_path.clone()));
+        }
+    }
+
+    /// Puts the first model of the prop into the clipboard once it's loaded, and again
+    /// whenever it's modified.
+    pub(super) fn receive_prop(
+        mut events: EventReader<AssetEvent<VoxModel>>,
+        mut clipboard: ResMut<Clipboard>,
+        models: Res<Assets<VoxModel>>,
+    ) {
+        let Some(handle) = clipboard.prop.clone() else {
+            events.clear();
+            return;
+        };
+
+        let changed = events.read().fold(false, |changed, event| {
+            changed || event.is_loaded_with_dependencies(&handle) || event.is_modified(&handle)
+        });
+
+        if let Some(model) = models.get(&handle).filter(|_| changed) {
+            match model.models.first() {
+                Some(schematic) => clipboard.schematic = Some(schematic.clone()),
+                None => warn!("prop {:?} contains no models", handle.path()),
+            }
+        }
+    }
+
+    pub(super) fn export_heightmap(
+        clipboard: Res<Clipboard>,
+        settings: Res<SchematicSettings>,
+        grid: Res<ChunkGrid>,
+        input: Res<Input<KeyCode>>,
+    ) {
+        if !input.just_pressed(KeyCode::F6) {
+            return;
+        }
+
+        if let Some((first, second)) = clipboard.selection.corners() {
+            let min = [first[0].min(second[0]), first[2].min(second[2])];
+            let max = [first[0].max(second[0]), first[2].max(second[2])];
+            let path = &settings.heightmap_path;
+
+            match HeightmapExport::from_grid(&grid, min, max).save(path) {
+                Ok(()) => info!("exported heightmap to {path}"),
+                Err(error) => error!("failed to export heightmap to {path}: {error}"),
+            }
+        }
+    }
+
+    pub(super) fn draw_selection(clipboard: Res<Clipboard>, mut gizmos: Gizmos) {
+        if let Some((first, second)) = clipboard.selection.corners() {
+            let [min, max] =
+                [first, second].map(|[x, y, z]| Vec3::new(x as f32, y as f32, z as f32));
+            let (min, max) = (min.min(max), min.max(max) + Vec3::ONE);
+
+            gizmos.cuboid(
+                Transform::from_translation((min + max) / 2.0).with_scale(max - min),
+                Color::YELLOW,
+            );
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::game::chunk::{
+    grid::{ChunkGridInner, GridCoordinates},
+    BlockType,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct BlockEdit {
+    pub position: [isize; 3],
+    pub before: Option<BlockType>,
+    pub after: Option<BlockType>,
+}
+
+/// Block modifications which are applied to the grid and undone as a single step.
+#[derive(Debug, Default)]
+pub struct EditBatch {
+    edits: Vec<BlockEdit>,
+    /// Chunks which have been modified, and their neighbours lit by the modified blocks.
+    modified_chunks: HashSet<GridCoordinates>,
+}
+
+impl EditBatch {
+    pub fn set(&mut self, grid: &ChunkGridInner, position: [isize; 3], value: Option<BlockType>) {
+        let before = grid.get_block(position);
+
+        if before == value {
+            return;
+        }
+
+        if grid.set_block(position, value).is_some() {
+            self.edits.push(BlockEdit {
+                position,
+                before,
+                after: value,
+            });
+            self.modified_chunks.extend(grid.lit_by(position));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    pub fn modified_chunks(&self) -> impl Iterator<Item = GridCoordinates> + '_ {
+        self.modified_chunks.iter().copied()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct EditHistory {
+    undo: Vec<Vec<BlockEdit>>,
+    redo: Vec<Vec<BlockEdit>>,
+}
+
+impl EditHistory {
+    pub const MAX_STEPS: usize = 100;
+
+    pub fn record(&mut self, batch: EditBatch) {
+        if batch.is_empty() {
+            return;
+        }
+
+        self.undo.push(batch.edits);
+        self.redo.clear();
+
+        if self.undo.len() > Self::MAX_STEPS {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Reverts the most recent step and returns the chunks which have been modified by it,
+    /// together with the neighbours whose light it changed.
+    pub fn undo(&mut self, grid: &ChunkGridInner) -> Option<HashSet<GridCoordinates>> {
+        let edits = self.undo.pop()?;
+        let modified_chunks = Self::apply(
+            grid,
+            edits.iter().rev().map(|edit| (edit.position, edit.before)),
+        );
+        self.redo.push(edits);
+
+        Some(modified_chunks)
+    }
+
+    /// Re-applies the most recently undone step and returns the chunks which have been modified
+    /// by it, together with the neighbours whose light it changed.
+    pub fn redo(&mut self, grid: &ChunkGridInner) -> Option<HashSet<GridCoordinates>> {
+        let edits = self.redo.pop()?;
+        let modified_chunks =
+            Self::apply(grid, edits.iter().map(|edit| (edit.position, edit.after)));
+        self.undo.push(edits);
+
+        Some(modified_chunks)
+    }
+
+    fn apply(
+        grid: &ChunkGridInner,
+        blocks: impl Iterator<Item = ([isize; 3], Option<BlockType>)>,
+    ) -> HashSet<GridCoordinates> {
+        blocks
+            .filter(|&(position, value)| grid.set_block(position, value).is_some())
+            .flat_map(|(position, _)| grid.lit_by(position))
+            .collect()
+    }
+}
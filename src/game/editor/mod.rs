@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::quick::ResourceInspectorPlugin;
+
+use crate::AppState;
+
+use self::{
+    brush::{Brush, BrushShape},
+    clipboard::{Clipboard, SchematicSettings},
+    history::EditHistory,
+};
+
+use super::{
+    camera_controller::CameraController,
+    chunk::{
+        grid::{ChunkGrid, RaycastHit},
+        ChunkEdited,
+    },
+};
+
+pub mod brush;
+pub mod clipboard;
+pub mod history;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Editor>()
+            .init_resource::<Brush>()
+            .init_resource::<EditHistory>()
+            .init_resource::<Clipboard>()
+            .init_resource::<SchematicSettings>()
+            .add_plugins((
+                ResourceInspectorPlugin::<Brush>::default(),
+                ResourceInspectorPlugin::<SchematicSettings>::default(),
+            ))
+            .add_systems(
+                Update,
+                (
+                    Editor::toggle,
+                    // props finish loading whether or not the editor is still open
+                    Clipboard::receive_prop,
+                    (
+                        Editor::update_target,
+                        Editor::apply_brush,
+                        Editor::pick_flatten_height,
+                        Editor::undo_redo,
+                        Editor::draw_brush,
+                        Clipboard::select_corners,
+                        Clipboard::copy_and_paste,
+                        Clipboard::save_and_load,
+                        Clipboard::load_prop,
+                        Clipboard::export_heightmap,
+                        Clipboard::draw_selection,
+                    )
+                        .chain()
+                        .run_if(Editor::is_enabled),
+                )
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Editor {
+    pub enabled: bool,
+    /// Block currently under the crosshair.
+    pub target: Option<RaycastHit>,
+}
+
+impl Editor {
+    pub const REACH: f32 = 128.0;
+
+    fn is_enabled(editor: Res<Editor>) -> bool {
+        editor.enabled
+    }
+
+    fn toggle(mut editor: ResMut<Editor>, input: Res<Input<KeyCode>>) {
+        if input.just_pressed(KeyCode::E) {
+            editor.enabled = !editor.enabled;
+            editor.target = None;
+        }
+    }
+
+    fn update_target(
+        mut editor: ResMut<Editor>,
+        player: Query<&GlobalTransform, With<CameraController>>,
+        grid: Res<ChunkGrid>,
+    ) {
+        let transform = player.single();
+
+        editor.target = grid.raycast(transform.translation(), transform.forward(), Self::REACH);
+    }
+
+    fn apply_brush(
+        editor: Res<Editor>,
+        brush: Res<Brush>,
+        mut history: ResMut<EditHistory>,
+        mut chunk_edited: EventWriter<ChunkEdited>,
+        grid: Res<ChunkGrid>,
+        input: Res<Input<MouseButton>>,
+    ) {
+        if let Some(target) = editor
+            .target
+            .filter(|_| input.just_pressed(MouseButton::Left))
+        {
+            let batch = brush.apply(&grid, target.position);
+
+            for coordinates in batch.modified_chunks() {
+                chunk_edited.send(ChunkEdited(coordinates));
+            }
+
+            history.record(batch);
+        }
+    }
+
+    fn pick_flatten_height(
+        editor: Res<Editor>,
+        mut brush: ResMut<Brush>,
+        input: Res<Input<MouseButton>>,
+    ) {
+        if let Some(target) = editor
+            .target
+            .filter(|_| input.just_pressed(MouseButton::Middle))
+        {
+            brush.flatten_height = target.position[1];
+        }
+    }
+
+    fn undo_redo(
+        mut history: ResMut<EditHistory>,
+        mut chunk_edited: EventWriter<ChunkEdited>,
+        grid: Res<ChunkGrid>,
+        input: Res<Input<KeyCode>>,
+    ) {
+        if !input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+            return;
+        }
+
+        let modified_chunks = if input.just_pressed(KeyCode::Z) {
+            history.undo(&grid)
+        } else if input.just_pressed(KeyCode::Y) {
+            history.redo(&grid)
+        } else {
+            None
+        };
+
+        for coordinates in modified_chunks.into_iter().flatten() {
+            chunk_edited.send(ChunkEdited(coordinates));
+        }
+    }
+
+    fn draw_brush(editor: Res<Editor>, brush: Res<Brush>, mut gizmos: Gizmos) {
+        if let Some(target) = editor.target {
+            let [x, y, z] = target.position;
+            let center = Vec3::new(x as f32, y as f32, z as f32) + Vec3::splat(0.5);
+            let size = brush.radius as f32 * 2.0 + 1.0;
+
+            match brush.shape {
+                BrushShape::Sphere => {
+                    gizmos.sphere(center, Quat::IDENTITY, size / 2.0, Color::WHITE);
+                }
+                BrushShape::Cube => {
+                    gizmos.cuboid(
+                        Transform::from_translation(center).with_scale(Vec3::splat(size)),
+                        Color::WHITE,
+                    );
+                }
+            }
+        }
+    }
+}
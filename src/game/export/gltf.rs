@@ -0,0 +1,109 @@
+//! glTF 2.0 with an external binary buffer.
+//! https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html
+
+use std::{fs, path::Path};
+
+use serde_json::json;
+
+use super::{file_name, ExportError, ExportMesh};
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const NEAREST: u32 = 9728;
+
+pub fn write(mesh: &ExportMesh, path: &Path, texture_path: &Path) -> Result<(), ExportError> {
+    let buffer_path = path.with_extension("bin");
+    let mut buffer = Vec::new();
+    let mut views = Vec::new();
+
+    let mut add_view = |bytes: Vec<u8>, target| {
+        views.push(json!({
+            "buffer": 0,
+            "byteOffset": buffer.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        buffer.extend(bytes);
+        views.len() - 1
+    };
+
+    let positions = add_view(to_bytes(mesh.positions.iter().flatten()), ARRAY_BUFFER);
+    let normals = add_view(to_bytes(mesh.normals.iter().flatten()), ARRAY_BUFFER);
+    let uvs = add_view(to_bytes(mesh.uvs.iter().flatten()), ARRAY_BUFFER);
+    let indices = add_view(
+        mesh.indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect(),
+        ELEMENT_ARRAY_BUFFER,
+    );
+
+    let (min, max) =
+        mesh.positions
+            .iter()
+            .fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), position| {
+                (
+                    [0, 1, 2].map(|axis| min[axis].min(position[axis])),
+                    [0, 1, 2].map(|axis| max[axis].max(position[axis])),
+                )
+            });
+    let vertex_count = mesh.positions.len();
+
+    let document = json!({
+        "asset": { "version": "2.0", "generator": "bevy-3d" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0, "name": "terrain" }],
+        "meshes": [{
+            "primitives": [{
+                "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 },
+                "indices": 3,
+                "material": 0,
+            }],
+        }],
+        "materials": [{
+            "pbrMetallicRoughness": {
+                "baseColorTexture": { "index": 0 },
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            },
+        }],
+        "textures": [{ "source": 0, "sampler": 0 }],
+        "samplers": [{ "magFilter": NEAREST, "minFilter": NEAREST }],
+        "images": [{ "uri": file_name(texture_path) }],
+        "buffers": [{ "uri": file_name(&buffer_path), "byteLength": buffer.len() }],
+        "bufferViews": views,
+        "accessors": [
+            {
+                "bufferView": positions,
+                "componentType": FLOAT,
+                "count": vertex_count,
+                "type": "VEC3",
+                "min": min,
+                "max": max,
+            },
+            { "bufferView": normals, "componentType": FLOAT, "count": vertex_count, "type": "VEC3" },
+            { "bufferView": uvs, "componentType": FLOAT, "count": vertex_count, "type": "VEC2" },
+            {
+                "bufferView": indices,
+                "componentType": UNSIGNED_INT,
+                "count": mesh.indices.len(),
+                "type": "SCALAR",
+            },
+        ],
+    });
+
+    fs::write(&buffer_path, buffer)?;
+    fs::write(
+        path,
+        serde_json::to_string_pretty(&document).map_err(std::io::Error::from)?,
+    )?;
+
+    Ok(())
+}
+
+fn to_bytes<'a>(values: impl Iterator<Item = &'a f32>) -> Vec<u8> {
+    values.flat_map(|value| value.to_le_bytes()).collect()
+}
@@ -0,0 +1,59 @@
+use std::{fs, path::Path};
+
+use image::{ImageBuffer, Luma, RgbImage};
+
+use crate::game::chunk::{grid::ChunkGridInner, BlockType, Chunk};
+
+use super::ExportError;
+
+/// Heights and top block colours of an area of the world, which can be saved as PNG images.
+///
+/// The heightmap uses the full range of 16 bit grayscale for the height of the world, so it can be
+/// imported again with a minimum height of 0 and a maximum height one below the world height.
+pub struct HeightmapExport {
+    pub heights: ImageBuffer<Luma<u16>, Vec<u16>>,
+    pub colors: RgbImage,
+}
+
+impl HeightmapExport {
+    /// Reads the columns from `min` to `max`, inclusive. Heights ignore water and features like
+    /// trees, while colours show the topmost block. Columns which are not loaded are black.
+    pub fn from_grid(grid: &ChunkGridInner, min: [isize; 2], max: [isize; 2]) -> Self {
+        let [width, depth] = [0, 1].map(|axis| (max[axis] - min[axis] + 1).max(0) as u32);
+        let top = (Chunk::HEIGHT - 1) as f64;
+
+        let heights = ImageBuffer::from_fn(width, depth, |x, z| {
+            let [x, z] = [min[0] + x as isize, min[1] + z as isize];
+            let height = grid.terrain_height(x, z).unwrap_or_default();
+
+            Luma([(height as f64 / top * f64::from(u16::MAX)).round() as u16])
+        });
+        let colors = RgbImage::from_fn(width, depth, |x, z| {
+            let [x, z] = [min[0] + x as isize, min[1] + z as isize];
+            let color = (Chunk::LOWER_BOUND..Chunk::HEIGHT)
+                .rev()
+                .find_map(|y| grid.get_block([x, y, z]))
+                .map_or([0; 3], BlockType::color);
+
+            image::Rgb(color)
+        });
+
+        Self { heights, colors }
+    }
+
+    /// Saves the heightmap, and the colour map next to it with `_colors` appended to its name.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ExportError> {
+        let path = path.as_ref();
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let colors_path = path.with_file_name(format!("{stem}_colors.png"));
+
+        self.heights.save(path)?;
+        self.colors.save(colors_path)?;
+
+        Ok(())
+    }
+}
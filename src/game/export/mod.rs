@@ -0,0 +1,234 @@
+use std::{fmt, fs, io, path::Path};
+
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, VertexAttributeValues},
+};
+use image::{imageops, RgbaImage};
+
+use crate::array_texture::ATTRIBUTE_TEXTURE_INDEX;
+
+use super::chunk::{
+    grid::{ChunkGridInner, GridCoordinates},
+    mesh_builder::MeshBuilderSettings,
+};
+
+pub mod gltf;
+pub mod heightmap;
+pub mod obj;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Gltf,
+    Obj,
+}
+
+impl ExportFormat {
+    /// Chooses the format by file extension, `.gltf` or `.obj`.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "gltf" => Some(ExportFormat::Gltf),
+            "obj" => Some(ExportFormat::Obj),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(io::Error),
+    Image(image::ImageError),
+    UnsupportedFormat,
+    EmptyMesh,
+    InvalidTexture,
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Io(error) => write!(f, "{error}"),
+            ExportError::Image(error) => write!(f, "{error}"),
+            ExportError::UnsupportedFormat => {
+                write!(f, "unsupported format, expected .gltf or .obj")
+            }
+            ExportError::EmptyMesh => write!(f, "the exported chunks do not contain any faces"),
+            ExportError::InvalidTexture => {
+                write!(f, "texture height is not a multiple of its width")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<io::Error> for ExportError {
+    fn from(error: io::Error) -> Self {
+        ExportError::Io(error)
+    }
+}
+
+impl From<image::ImageError> for ExportError {
+    fn from(error: image::ImageError) -> Self {
+        ExportError::Image(error)
+    }
+}
+
+/// The layers of the block array texture, arranged in a grid so they can be used by tools
+/// which do not support array textures.
+pub struct TextureAtlas {
+    image: RgbaImage,
+    columns: u32,
+    rows: u32,
+}
+
+impl TextureAtlas {
+    /// Loads square layers stacked on top of each other, the layout used for the array texture.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ExportError> {
+        Self::bake(&image::open(path)?.to_rgba8())
+    }
+
+    pub fn bake(stacked: &RgbaImage) -> Result<Self, ExportError> {
+        let resolution = stacked.width();
+
+        if resolution == 0 || !stacked.height().is_multiple_of(resolution) {
+            return Err(ExportError::InvalidTexture);
+        }
+
+        let layers = stacked.height() / resolution;
+        let columns = (layers as f32).sqrt().ceil() as u32;
+        let rows = layers.div_ceil(columns);
+        let mut image = RgbaImage::new(columns * resolution, rows * resolution);
+
+        for layer in 0..layers {
+            let tile = imageops::crop_imm(stacked, 0, layer * resolution, resolution, resolution);
+            imageops::replace(
+                &mut image,
+                &*tile,
+                ((layer % columns) * resolution).into(),
+                ((layer / columns) * resolution).into(),
+            );
+        }
+
+        Ok(Self {
+            image,
+            columns,
+            rows,
+        })
+    }
+
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// Maps texture coordinates of an array texture layer to the atlas.
+    fn uv(&self, layer: u32, [u, v]: [f32; 2]) -> [f32; 2] {
+        let column = (layer % self.columns) as f32;
+        let row = (layer / self.columns) as f32;
+
+        [
+            (column + u) / self.columns as f32,
+            (row + v) / self.rows as f32,
+        ]
+    }
+}
+
+/// Combined world space geometry of several chunks.
+#[derive(Debug, Default)]
+pub struct ExportMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// Texture coordinates into the baked atlas, with the origin in the top left corner.
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+impl ExportMesh {
+    /// Meshes all loaded chunks out of `coordinates`, the same way they are meshed for rendering.
+    pub fn from_chunks(
+        grid: &ChunkGridInner,
+        coordinates: impl IntoIterator<Item = GridCoordinates>,
+        settings: MeshBuilderSettings,
+        atlas: &TextureAtlas,
+    ) -> Self {
+        let mut export = Self::default();
+
+        for coordinates in coordinates {
+            if let Some(chunk_mesh) = grid.remesh(coordinates, settings) {
+                export.append(&chunk_mesh.mesh, coordinates.into(), atlas);
+            }
+        }
+
+        export
+    }
+
+    fn append(&mut self, mesh: &Mesh, offset: Vec3, atlas: &TextureAtlas) {
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(VertexAttributeValues::Float32x2(uvs)),
+            Some(VertexAttributeValues::Uint32(layers)),
+            Some(Indices::U32(indices)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0),
+            mesh.attribute(ATTRIBUTE_TEXTURE_INDEX),
+            mesh.indices(),
+        )
+        else {
+            return;
+        };
+
+        let first_vertex = self.positions.len() as u32;
+
+        self.positions.extend(
+            positions
+                .iter()
+                .map(|position| (Vec3::from(*position) + offset).to_array()),
+        );
+        self.normals.extend_from_slice(normals);
+        self.uvs.extend(
+            uvs.iter()
+                .zip(layers)
+                .map(|(uv, layer)| atlas.uv(*layer, *uv)),
+        );
+        self.indices
+            .extend(indices.iter().map(|index| index + first_vertex));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Writes the mesh next to its atlas texture, which is stored as PNG with the same file name.
+    pub fn export(
+        &self,
+        path: impl AsRef<Path>,
+        format: ExportFormat,
+        atlas: &TextureAtlas,
+    ) -> Result<(), ExportError> {
+        if self.is_empty() {
+            return Err(ExportError::EmptyMesh);
+        }
+
+        let path = path.as_ref();
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let texture_path = path.with_extension("png");
+        atlas.image.save(&texture_path)?;
+
+        match format {
+            ExportFormat::Gltf => gltf::write(self, path, &texture_path),
+            ExportFormat::Obj => obj::write(self, path, &texture_path),
+        }
+    }
+}
+
+/// File name of a path written next to the exported mesh, referenced by a relative URI.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
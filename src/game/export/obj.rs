@@ -0,0 +1,45 @@
+//! Wavefront OBJ with a material library referencing the atlas texture.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::{file_name, ExportError, ExportMesh};
+
+pub fn write(mesh: &ExportMesh, path: &Path, texture_path: &Path) -> Result<(), ExportError> {
+    let material_path = path.with_extension("mtl");
+
+    let mut material = BufWriter::new(File::create(&material_path)?);
+    writeln!(material, "newmtl terrain")?;
+    writeln!(material, "Kd 1.0 1.0 1.0")?;
+    writeln!(material, "map_Kd {}", file_name(texture_path))?;
+    material.flush()?;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "mtllib {}", file_name(&material_path))?;
+    writeln!(writer, "o terrain")?;
+
+    for [x, y, z] in &mesh.positions {
+        writeln!(writer, "v {x} {y} {z}")?;
+    }
+    for [x, y, z] in &mesh.normals {
+        writeln!(writer, "vn {x} {y} {z}")?;
+    }
+    // OBJ texture coordinates start in the bottom left corner
+    for [u, v] in &mesh.uvs {
+        writeln!(writer, "vt {u} {}", 1.0 - v)?;
+    }
+
+    writeln!(writer, "usemtl terrain")?;
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| index + 1);
+        writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
@@ -1,3 +1,7 @@
 pub mod camera_controller;
 pub mod chunk;
 pub mod debug_info;
+pub mod editor;
+pub mod export;
+pub mod schematic;
+pub mod structure;
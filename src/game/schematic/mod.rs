@@ -0,0 +1,226 @@
+use std::{
+    fmt, fs,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+
+use bevy::prelude::*;
+
+use crate::nbt::NbtError;
+
+use self::vox::{PaletteMapping, VoxLoader, VoxModel};
+
+use super::chunk::{block_mapping::BlockMapping, grid::ChunkGridInner, BlockType, Chunk};
+
+pub mod native;
+pub mod sponge;
+pub mod vox;
+
+pub struct SchematicPlugin;
+
+impl Plugin for SchematicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<VoxModel>()
+            .init_asset_loader::<VoxLoader>();
+    }
+}
+
+/// A box of blocks which can be copied out of and pasted into the world.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schematic {
+    size: [usize; 3],
+    /// Blocks in YZX order, which is the order used by Sponge schematics.
+    blocks: Vec<Option<BlockType>>,
+}
+
+#[derive(Debug)]
+pub enum SchematicError {
+    Io(io::Error),
+    Nbt(NbtError),
+    UnsupportedFormat(String),
+    UnknownBlock(String),
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SchematicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchematicError::Io(error) => write!(f, "{error}"),
+            SchematicError::Nbt(error) => write!(f, "{error}"),
+            SchematicError::UnsupportedFormat(extension) => {
+                write!(f, "unsupported schematic format '{extension}'")
+            }
+            SchematicError::UnknownBlock(name) => write!(f, "unknown block '{name}'"),
+            SchematicError::Invalid(reason) => write!(f, "invalid schematic: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for SchematicError {}
+
+impl From<io::Error> for SchematicError {
+    fn from(error: io::Error) -> Self {
+        SchematicError::Io(error)
+    }
+}
+
+impl From<NbtError> for SchematicError {
+    fn from(error: NbtError) -> Self {
+        SchematicError::Nbt(error)
+    }
+}
+
+impl Schematic {
+    /// Schematics read from files are limited to this many blocks, so broken files
+    /// can't exhaust the memory.
+    pub const MAX_VOLUME: usize = 1 << 28;
+
+    pub fn new(size: [usize; 3]) -> Self {
+        Self {
+            size,
+            blocks: vec![None; size[0] * size[1] * size[2]],
+        }
+    }
+
+    /// Creates an empty schematic with a size read from a file, rejecting sizes
+    /// above [`Self::MAX_VOLUME`].
+    pub fn with_size(size: [usize; 3]) -> Result<Self, SchematicError> {
+        Self::volume(size)?;
+
+        Ok(Self::new(size))
+    }
+
+    /// Number of blocks in a schematic of a size read from a file, rejecting sizes
+    /// above [`Self::MAX_VOLUME`].
+    pub fn volume(size: [usize; 3]) -> Result<usize, SchematicError> {
+        size.into_iter()
+            .try_fold(1_usize, |volume, length| volume.checked_mul(length))
+            .filter(|volume| *volume <= Self::MAX_VOLUME)
+            .ok_or(SchematicError::Invalid("too large"))
+    }
+
+    /// Copies the blocks between two corners, both inclusive.
+    pub fn from_grid(grid: &ChunkGridInner, corner: [isize; 3], opposite: [isize; 3]) -> Self {
+        let min = [0, 1, 2].map(|axis| corner[axis].min(opposite[axis]));
+        let max = [0, 1, 2].map(|axis| corner[axis].max(opposite[axis]));
+        let mut schematic = Self::new([0, 1, 2].map(|axis| (max[axis] - min[axis] + 1) as usize));
+
+        for position in schematic.positions() {
+            let world = [0, 1, 2].map(|axis| min[axis] + position[axis] as isize);
+            schematic.set(position, grid.get_block(world));
+        }
+
+        schematic
+    }
+
+    pub fn from_chunk(chunk: &Chunk) -> Self {
+        let mut schematic =
+            Self::new([Chunk::WIDTH, Chunk::HEIGHT, Chunk::WIDTH].map(|n| n as usize));
+
+        for position in schematic.positions() {
+            schematic.set(position, chunk.get(position.map(|n| n as isize)));
+        }
+
+        schematic
+    }
+
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    pub fn get(&self, position: [usize; 3]) -> Option<BlockType> {
+        self.blocks[self.index(position)]
+    }
+
+    pub fn set(&mut self, position: [usize; 3], value: Option<BlockType>) {
+        let index = self.index(position);
+        self.blocks[index] = value;
+    }
+
+    /// All positions inside the schematic, in storage order.
+    pub fn positions(&self) -> impl Iterator<Item = [usize; 3]> {
+        let [size_x, size_y, size_z] = self.size;
+
+        (0..size_y)
+            .flat_map(move |y| (0..size_z).flat_map(move |z| (0..size_x).map(move |x| [x, y, z])))
+    }
+
+    pub fn blocks(&self) -> &[Option<BlockType>] {
+        &self.blocks
+    }
+
+    /// Writes the schematic with its minimum corner at `origin` by calling `set` for every block.
+    /// Air is only written if `include_air` is set, so pasted structures blend into the terrain.
+    pub fn paste(
+        &self,
+        origin: [isize; 3],
+        include_air: bool,
+        mut set: impl FnMut([isize; 3], Option<BlockType>),
+    ) {
+        for (position, block) in self.positions().zip(self.blocks.iter().copied()) {
+            if block.is_some() || include_air {
+                set(
+                    [0, 1, 2].map(|axis| origin[axis] + position[axis] as isize),
+                    block,
+                );
+            }
+        }
+    }
+
+    /// Loads a schematic, choosing the format by file extension:
+    /// `.schem` for Sponge schematics, `.vox` for MagicaVoxel models and `.vschem` for the native format.
+    /// Only the first model of a `.vox` file is loaded.
+    pub fn load(path: impl AsRef<Path>, mapping: &BlockMapping) -> Result<Self, SchematicError> {
+        let path = path.as_ref();
+        let mut reader = BufReader::new(fs::File::open(path)?);
+
+        match extension(path) {
+            "schem" => sponge::read(&mut reader, mapping),
+            "vschem" => native::read(&mut reader),
+            "vox" => vox::read(&mut reader, &PaletteMapping::default())?
+                .into_iter()
+                .next()
+                .ok_or(SchematicError::Invalid("no models")),
+            extension => Err(SchematicError::UnsupportedFormat(extension.to_string())),
+        }
+    }
+
+    /// Saves the schematic, choosing the format by file extension like [`Self::load`].
+    /// Missing directories are created.
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        mapping: &BlockMapping,
+    ) -> Result<(), SchematicError> {
+        let path = path.as_ref();
+
+        // check the extension before the file gets created
+        let format = match extension(path) {
+            format @ ("schem" | "vschem" | "vox") => format,
+            extension => return Err(SchematicError::UnsupportedFormat(extension.to_string())),
+        };
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+
+        match format {
+            "schem" => sponge::write(self, &mut writer, mapping),
+            "vox" => vox::write(self, &mut writer),
+            _ => native::write(self, &mut writer),
+        }
+    }
+
+    fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+        let [size_x, _, size_z] = self.size;
+        x + z * size_x + y * size_x * size_z
+    }
+}
+
+fn extension(path: &Path) -> &str {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+}
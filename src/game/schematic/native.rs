@@ -0,0 +1,213 @@
+//! Compact native schematic format: a gzip compressed stream of
+//! `VSCH`, version byte, size as three little endian `u32`, palette of block names
+//! and one little endian `u16` palette index per block, where `0` is air.
+
+use std::io::{Read, Write};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::game::chunk::BlockType;
+
+use super::{Schematic, SchematicError};
+
+const MAGIC: &[u8; 4] = b"VSCH";
+const VERSION: u8 = 1;
+
+pub fn write(schematic: &Schematic, writer: &mut impl Write) -> Result<(), SchematicError> {
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+
+    encoder.write_all(MAGIC)?;
+    encoder.write_all(&[VERSION])?;
+
+    for length in schematic.size() {
+        let length = u32::try_from(length).map_err(|_| SchematicError::Invalid("too large"))?;
+        encoder.write_all(&length.to_le_bytes())?;
+    }
+
+    encoder.write_all(&(BlockType::ALL.len() as u16).to_le_bytes())?;
+
+    for block in BlockType::ALL {
+        let name = block.name();
+        encoder.write_all(&[name.len() as u8])?;
+        encoder.write_all(name.as_bytes())?;
+    }
+
+    for block in schematic.blocks() {
+        let index = block.map_or(0, |block| {
+            BlockType::ALL
+                .iter()
+                .position(|other| *other == block)
+                .expect("all block types should be part of the palette")
+                + 1
+        });
+        encoder.write_all(&(index as u16).to_le_bytes())?;
+    }
+
+    encoder.finish()?;
+
+    Ok(())
+}
+
+pub fn read(reader: &mut impl Read) -> Result<Schematic, SchematicError> {
+    let mut decoder = GzDecoder::new(reader);
+
+    let mut header = [0; 5];
+    decoder.read_exact(&mut header)?;
+
+    if &header[..4] != MAGIC {
+        return Err(SchematicError::Invalid("missing header"));
+    }
+    if header[4] != VERSION {
+        return Err(SchematicError::Invalid("unsupported version"));
+    }
+
+    let mut size = [0; 3];
+    for length in &mut size {
+        *length = u32::from_le_bytes(read_array(&mut decoder)?) as usize;
+    }
+
+    let palette_length = u16::from_le_bytes(read_array(&mut decoder)?);
+    let mut palette = vec![None];
+
+    for _ in 0..palette_length {
+        let [name_length] = read_array(&mut decoder)?;
+        let mut name = vec![0; name_length.into()];
+        decoder.read_exact(&mut name)?;
+
+        let name = String::from_utf8_lossy(&name);
+        let block =
+            BlockType::from_name(&name).ok_or_else(|| SchematicError::UnknownBlock(name.into()))?;
+        palette.push(Some(block));
+    }
+
+    // the data only grows as it's read, so the size alone can't make the reader allocate much
+    let length = Schematic::volume(size)? * 2;
+    let mut data = Vec::new();
+    decoder.take(length as u64).read_to_end(&mut data)?;
+
+    if data.len() < length {
+        return Err(SchematicError::Invalid("block data too short"));
+    }
+
+    let mut schematic = Schematic::with_size(size)?;
+
+    for (block, index) in schematic.blocks.iter_mut().zip(data.chunks_exact(2)) {
+        let index = u16::from_le_bytes([index[0], index[1]]);
+        *block = *palette
+            .get(usize::from(index))
+            .ok_or(SchematicError::Invalid("palette index out of range"))?;
+    }
+
+    Ok(schematic)
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], SchematicError> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compresses a file written by hand, for content the writer doesn't produce.
+    fn compress(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Header up to the block data, with a palette of the given names.
+    fn header(size: [u32; 3], names: &[&str]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend(size.iter().flat_map(|length| length.to_le_bytes()));
+        bytes.extend((names.len() as u16).to_le_bytes());
+
+        for name in names {
+            bytes.push(name.len() as u8);
+            bytes.extend(name.as_bytes());
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn round_trips_all_blocks() {
+        let mut schematic = Schematic::new([BlockType::ALL.len() + 1, 2, 3]);
+        for (x, block) in BlockType::ALL.into_iter().enumerate() {
+            schematic.set([x, 1, 2], Some(block));
+        }
+
+        let mut bytes = Vec::new();
+        write(&schematic, &mut bytes).unwrap();
+
+        assert_eq!(read(&mut bytes.as_slice()).unwrap(), schematic);
+    }
+
+    #[test]
+    fn maps_palette_names_to_blocks() {
+        let mut bytes = header([3, 1, 1], &["sand", "stone"]);
+        bytes.extend([2_u16, 0, 1].iter().flat_map(|index| index.to_le_bytes()));
+
+        let schematic = read(&mut compress(&bytes).as_slice()).unwrap();
+
+        assert_eq!(
+            schematic.blocks(),
+            [Some(BlockType::Stone), None, Some(BlockType::Sand)]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_block_names() {
+        let mut bytes = header([1, 1, 1], &["stone", "unobtainium"]);
+        bytes.extend(1_u16.to_le_bytes());
+
+        let error = read(&mut compress(&bytes).as_slice()).unwrap_err();
+
+        assert!(matches!(error, SchematicError::UnknownBlock(name) if name == "unobtainium"));
+    }
+
+    #[test]
+    fn rejects_palette_indices_out_of_range() {
+        let mut bytes = header([1, 1, 1], &["stone"]);
+        bytes.extend(2_u16.to_le_bytes());
+
+        assert!(read(&mut compress(&bytes).as_slice()).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let mut schematic = Schematic::new([4, 4, 4]);
+        schematic.set([1, 2, 3], Some(BlockType::Granite));
+        let mut bytes = Vec::new();
+        write(&schematic, &mut bytes).unwrap();
+        let mut decompressed = Vec::new();
+        GzDecoder::new(bytes.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+
+        for length in [3, 10, decompressed.len() - 1] {
+            let truncated = compress(&decompressed[..length]);
+            assert!(read(&mut truncated.as_slice()).is_err(), "{length} bytes");
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_files_before_allocating() {
+        let too_large = header([1 << 12, 1 << 12, 1 << 12], &[]);
+        assert!(matches!(
+            read(&mut compress(&too_large).as_slice()),
+            Err(SchematicError::Invalid("too large"))
+        ));
+
+        // as large as allowed, but without the data to back it
+        let mut short = header([1 << 14, 1 << 14, 1], &[]);
+        short.extend([0; 16]);
+        assert!(matches!(
+            read(&mut compress(&short).as_slice()),
+            Err(SchematicError::Invalid("block data too short"))
+        ));
+    }
+}
@@ -0,0 +1,311 @@
+//! Sponge schematics (`.schem`), as written by WorldEdit and most other Minecraft tools.
+//! Versions 1 to 3 can be read, files are written as version 2.
+//! https://github.com/SpongePowered/Schematic-Specification
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use bevy::utils::HashMap;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::{
+    game::chunk::{block_mapping::BlockMapping, BlockType},
+    nbt::{self, Tag},
+};
+
+use super::{Schematic, SchematicError};
+
+/// Data version of Minecraft 1.16.5, the last release supported by most tools reading version 2.
+const DATA_VERSION: i32 = 2586;
+
+pub fn write(
+    schematic: &Schematic,
+    writer: &mut impl Write,
+    mapping: &BlockMapping,
+) -> Result<(), SchematicError> {
+    let mut palette = BTreeMap::new();
+    let mut palette_indices = HashMap::<Option<BlockType>, i32>::default();
+    let mut block_data = Vec::with_capacity(schematic.blocks().len());
+
+    for block in schematic.blocks() {
+        let index = *palette_indices.entry(*block).or_insert_with(|| {
+            let index = palette.len() as i32;
+            palette.insert(mapping.to_name(*block), Tag::Int(index));
+            index
+        });
+
+        write_varint(&mut block_data, index);
+    }
+
+    let [width, height, length] = schematic
+        .size()
+        .map(|length| u16::try_from(length).map(|length| Tag::Short(length as i16)));
+
+    let root = Tag::Compound(BTreeMap::from([
+        ("Version".to_string(), Tag::Int(2)),
+        ("DataVersion".to_string(), Tag::Int(DATA_VERSION)),
+        ("Width".to_string(), width.map_err(|_| too_large())?),
+        ("Height".to_string(), height.map_err(|_| too_large())?),
+        ("Length".to_string(), length.map_err(|_| too_large())?),
+        ("Offset".to_string(), Tag::IntArray(vec![0, 0, 0])),
+        ("PaletteMax".to_string(), Tag::Int(palette.len() as i32)),
+        ("Palette".to_string(), Tag::Compound(palette)),
+        ("BlockData".to_string(), Tag::ByteArray(block_data)),
+    ]));
+
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    nbt::write(&mut encoder, "Schematic", &root)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+pub fn read(reader: &mut impl Read, mapping: &BlockMapping) -> Result<Schematic, SchematicError> {
+    let (_, root) = nbt::read(&mut GzDecoder::new(reader))?;
+
+    // version 3 wraps everything in another compound
+    let root = root
+        .get("Schematic")
+        .filter(|tag| tag.as_compound().is_some())
+        .unwrap_or(&root);
+    let version = root.get("Version").and_then(Tag::as_i64).unwrap_or(1);
+
+    let dimension = |key| {
+        root.get(key)
+            .and_then(Tag::as_i64)
+            .map(|length| length as u16 as usize)
+            .ok_or(SchematicError::Invalid("missing dimensions"))
+    };
+    let size = [
+        dimension("Width")?,
+        dimension("Height")?,
+        dimension("Length")?,
+    ];
+
+    let (palette, data) = if version >= 3 {
+        let blocks = root
+            .get("Blocks")
+            .ok_or(SchematicError::Invalid("missing blocks"))?;
+        (blocks.get("Palette"), blocks.get("Data"))
+    } else {
+        (root.get("Palette"), root.get("BlockData"))
+    };
+
+    let palette = palette
+        .and_then(Tag::as_compound)
+        .ok_or(SchematicError::Invalid("missing palette"))?;
+    let data = data
+        .and_then(Tag::as_byte_array)
+        .ok_or(SchematicError::Invalid("missing block data"))?;
+
+    let mut blocks = HashMap::<i32, Option<BlockType>>::default();
+    for (name, index) in palette {
+        let index = index
+            .as_i64()
+            .ok_or(SchematicError::Invalid("invalid palette index"))?;
+        blocks.insert(index as i32, mapping.to_block(name));
+    }
+
+    // every block takes at least one byte, so shorter data can be rejected before allocating
+    if size.iter().map(|&length| length as u64).product::<u64>() > data.len() as u64 {
+        return Err(SchematicError::Invalid("block data too short"));
+    }
+
+    let mut schematic = Schematic::with_size(size)?;
+    let mut data = data.iter().copied();
+
+    for block in &mut schematic.blocks {
+        let index =
+            read_varint(&mut data).ok_or(SchematicError::Invalid("block data too short"))?;
+        *block = *blocks
+            .get(&index)
+            .ok_or(SchematicError::Invalid("palette index out of range"))?;
+    }
+
+    Ok(schematic)
+}
+
+fn too_large() -> SchematicError {
+    SchematicError::Invalid("too large for a Sponge schematic")
+}
+
+fn write_varint(bytes: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+
+    while value >= 0x80 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Option<i32> {
+    let mut value = 0_u32;
+
+    for shift in (0..35).step_by(7) {
+        let byte = bytes.next()?;
+        value |= u32::from(byte & 0x7f) << shift;
+
+        if byte & 0x80 == 0 {
+            return Some(value as i32);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compress(root: &Tag) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        nbt::write(&mut encoder, "Schematic", root).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// A version 2 schematic with the given palette and one byte per block.
+    fn version_2(size: [i16; 3], palette: &[(&str, i32)], data: Vec<u8>) -> Tag {
+        let palette = palette
+            .iter()
+            .map(|(name, index)| (name.to_string(), Tag::Int(*index)))
+            .collect();
+
+        Tag::Compound(BTreeMap::from([
+            ("Version".to_string(), Tag::Int(2)),
+            ("Width".to_string(), Tag::Short(size[0])),
+            ("Height".to_string(), Tag::Short(size[1])),
+            ("Length".to_string(), Tag::Short(size[2])),
+            ("Palette".to_string(), Tag::Compound(palette)),
+            ("BlockData".to_string(), Tag::ByteArray(data)),
+        ]))
+    }
+
+    #[test]
+    fn round_trips_all_blocks() {
+        let mapping = BlockMapping::minecraft();
+        let mut schematic = Schematic::new([BlockType::ALL.len() + 1, 3, 2]);
+        for (x, block) in BlockType::ALL.into_iter().enumerate() {
+            schematic.set([x, 2, 1], Some(block));
+        }
+
+        let mut bytes = Vec::new();
+        write(&schematic, &mut bytes, &mapping).unwrap();
+
+        assert_eq!(read(&mut bytes.as_slice(), &mapping).unwrap(), schematic);
+    }
+
+    #[test]
+    fn round_trips_varints() {
+        let mut bytes = Vec::new();
+        for value in [0, 127, 128, 300, i32::MAX] {
+            write_varint(&mut bytes, value);
+        }
+
+        let mut data = bytes.iter().copied();
+        for value in [0, 127, 128, 300, i32::MAX] {
+            assert_eq!(read_varint(&mut data), Some(value));
+        }
+        assert_eq!(read_varint(&mut data), None);
+    }
+
+    #[test]
+    fn maps_names_and_falls_back_for_unknown_ones() {
+        let mapping = BlockMapping::from_ron(
+            r#"(
+                blocks: [("minecraft:air", None), ("minecraft:stone", Some(Stone))],
+                fallback: Some(Dirt),
+            )"#,
+        )
+        .unwrap();
+        let root = version_2(
+            [4, 1, 1],
+            &[
+                ("minecraft:air", 0),
+                ("minecraft:stone", 1),
+                ("minecraft:stone[smooth=true]", 2),
+                ("somemod:unobtainium", 3),
+            ],
+            vec![0, 1, 2, 3],
+        );
+
+        let schematic = read(&mut compress(&root).as_slice(), &mapping).unwrap();
+
+        assert_eq!(
+            schematic.blocks(),
+            [
+                None,
+                Some(BlockType::Stone),
+                Some(BlockType::Stone),
+                Some(BlockType::Dirt)
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_version_3() {
+        let palette = BTreeMap::from([
+            ("minecraft:air".to_string(), Tag::Int(0)),
+            ("minecraft:sand".to_string(), Tag::Int(1)),
+        ]);
+        let blocks = Tag::Compound(BTreeMap::from([
+            ("Palette".to_string(), Tag::Compound(palette)),
+            ("Data".to_string(), Tag::ByteArray(vec![1, 0])),
+        ]));
+        let schematic = Tag::Compound(BTreeMap::from([
+            ("Version".to_string(), Tag::Int(3)),
+            ("Width".to_string(), Tag::Short(2)),
+            ("Height".to_string(), Tag::Short(1)),
+            ("Length".to_string(), Tag::Short(1)),
+            ("Blocks".to_string(), blocks),
+        ]));
+        let root = Tag::Compound(BTreeMap::from([("Schematic".to_string(), schematic)]));
+
+        let schematic = read(&mut compress(&root).as_slice(), &BlockMapping::minecraft()).unwrap();
+
+        assert_eq!(schematic.blocks(), [Some(BlockType::Sand), None]);
+    }
+
+    #[test]
+    fn rejects_short_block_data_and_unknown_indices() {
+        let mapping = BlockMapping::minecraft();
+        let palette = [("minecraft:air", 0), ("minecraft:stone", 1)];
+
+        let short = version_2([2, 2, 2], &palette, vec![1; 7]);
+        assert!(read(&mut compress(&short).as_slice(), &mapping).is_err());
+
+        // a varint which continues past the end of the data
+        let unfinished = version_2([1, 1, 1], &palette, vec![0x81]);
+        assert!(read(&mut compress(&unfinished).as_slice(), &mapping).is_err());
+
+        let out_of_range = version_2([1, 1, 1], &palette, vec![2]);
+        assert!(read(&mut compress(&out_of_range).as_slice(), &mapping).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_dimensions_before_allocating() {
+        // -1 is read as 65535 blocks along each axis
+        let root = version_2([-1, -1, -1], &[("minecraft:air", 0)], vec![0; 16]);
+
+        assert!(matches!(
+            read(&mut compress(&root).as_slice(), &BlockMapping::minecraft()),
+            Err(SchematicError::Invalid("block data too short"))
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let mut bytes = Vec::new();
+        write(
+            &Schematic::new([2, 2, 2]),
+            &mut bytes,
+            &BlockMapping::minecraft(),
+        )
+        .unwrap();
+
+        assert!(read(&mut &bytes[..bytes.len() / 2], &BlockMapping::minecraft()).is_err());
+    }
+}
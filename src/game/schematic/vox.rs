@@ -0,0 +1,351 @@
+//! MagicaVoxel models (`.vox`).
+//! https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox.txt
+//!
+//! MagicaVoxel uses a right-handed coordinate system with z pointing up,
+//! so models are rotated to y pointing up and mirrored along z when converted.
+
+use std::io::{self, Read, Write};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::game::chunk::BlockType;
+
+use super::{Schematic, SchematicError};
+
+const VERSION: i32 = 150;
+/// Files without a palette chunk use the default palette of MagicaVoxel,
+/// which is not shipped with the game. Its colors are approximated by a neutral grey.
+const DEFAULT_COLOR: [u8; 4] = [128, 128, 128, 255];
+/// Models are limited to 256 voxels along each axis since coordinates are stored as bytes.
+pub const MAX_SIZE: usize = 256;
+
+/// All models of a `.vox` file, converted to schematics which can be placed in the world.
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct VoxModel {
+    pub models: Vec<Schematic>,
+}
+
+/// Decides which block the colors of a `.vox` palette turn into. Also the settings of the
+/// [`VoxLoader`], so models in `assets/` can override blocks in their `.meta` file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PaletteMapping {
+    /// Explicit block for palette indices. All other indices use the block with the closest color.
+    pub overrides: HashMap<u8, Option<BlockType>>,
+}
+
+impl PaletteMapping {
+    pub fn to_block(&self, index: u8, color: [u8; 4]) -> Option<BlockType> {
+        self.overrides.get(&index).copied().unwrap_or_else(|| {
+            let [r, g, b, _] = color.map(i32::from);
+
+            BlockType::ALL.into_iter().min_by_key(|block| {
+                let [br, bg, bb] = block.color().map(i32::from);
+                (r - br).pow(2) + (g - bg).pow(2) + (b - bb).pow(2)
+            })
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct VoxLoader;
+
+impl AssetLoader for VoxLoader {
+    type Asset = VoxModel;
+    type Settings = PaletteMapping;
+    type Error = SchematicError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        use bevy::asset::AsyncReadExt;
+
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+
+            let models = read(&mut bytes.as_slice(), settings)?;
+
+            Ok(VoxModel { models })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vox"]
+    }
+}
+
+/// Reads all models of a `.vox` file.
+pub fn read(
+    reader: &mut impl Read,
+    mapping: &PaletteMapping,
+) -> Result<Vec<Schematic>, SchematicError> {
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+
+    if &header[..4] != b"VOX " {
+        return Err(SchematicError::Invalid("missing header"));
+    }
+
+    let (id, content, children) = read_chunk(reader)?;
+
+    if &id != b"MAIN" || !content.is_empty() {
+        return Err(SchematicError::Invalid("missing main chunk"));
+    }
+
+    let mut children = children.as_slice();
+    let mut sizes = Vec::new();
+    let mut voxels = Vec::new();
+    let mut palette = None;
+
+    while !children.is_empty() {
+        let (id, content, _) = read_chunk(&mut children)?;
+        let mut content = content.as_slice();
+
+        match &id {
+            b"SIZE" => {
+                let mut size = [0; 3];
+                for length in &mut size {
+                    *length = read_i32(&mut content)?.max(0) as usize;
+                }
+
+                if size.into_iter().any(|length| length > MAX_SIZE) {
+                    return Err(SchematicError::Invalid("model too large"));
+                }
+
+                sizes.push(size);
+            }
+            b"XYZI" => {
+                let count = read_i32(&mut content)?;
+                // the count is only trusted as far as the chunk actually contains voxels
+                let mut model = Vec::with_capacity((count.max(0) as usize).min(content.len() / 4));
+
+                for _ in 0..count {
+                    let mut voxel = [0; 4];
+                    content.read_exact(&mut voxel)?;
+                    model.push(voxel);
+                }
+
+                voxels.push(model);
+            }
+            b"RGBA" => {
+                let mut colors = [[0; 4]; 256];
+                for color in &mut colors {
+                    content.read_exact(color)?;
+                }
+                palette = Some(colors);
+            }
+            // scene graph, materials and layers do not affect the voxels
+            _ => {}
+        }
+    }
+
+    sizes
+        .into_iter()
+        .zip(voxels)
+        .map(|(size, voxels)| {
+            let [size_x, size_y, size_z] = size;
+            let mut schematic = Schematic::with_size([size_x, size_z, size_y])?;
+
+            for [x, y, z, index] in voxels {
+                let [x, y, z] = [x, y, z].map(usize::from);
+
+                if x >= size_x || y >= size_y || z >= size_z {
+                    return Err(SchematicError::Invalid("voxel outside of model"));
+                }
+
+                // palette entries are shifted by one, index 0 is never used by voxels
+                let block = match &palette {
+                    Some(colors) => {
+                        mapping.to_block(index, colors[usize::from(index.wrapping_sub(1))])
+                    }
+                    None => mapping.to_block(index, DEFAULT_COLOR),
+                };
+
+                schematic.set([x, z, size_y - 1 - y], block);
+            }
+
+            Ok(schematic)
+        })
+        .collect()
+}
+
+/// Writes a schematic as a single `.vox` model, using the block colors as palette.
+pub fn write(schematic: &Schematic, writer: &mut impl Write) -> Result<(), SchematicError> {
+    let [size_x, size_y, size_z] = schematic.size();
+
+    if schematic.size().into_iter().any(|length| length > MAX_SIZE) {
+        return Err(SchematicError::Invalid("too large for a .vox model"));
+    }
+
+    let mut size = Vec::new();
+    for length in [size_x, size_z, size_y] {
+        size.extend((length as i32).to_le_bytes());
+    }
+
+    let mut voxels = Vec::new();
+    let mut count = 0_i32;
+
+    for (position, block) in schematic.positions().zip(schematic.blocks()) {
+        if let Some(block) = block {
+            let [x, y, z] = position;
+            let index = BlockType::ALL
+                .iter()
+                .position(|other| other == block)
+                .expect("all block types should be part of the palette")
+                + 1;
+
+            voxels.extend([x, size_z - 1 - z, y].map(|n| n as u8));
+            voxels.push(index as u8);
+            count += 1;
+        }
+    }
+
+    let mut palette = vec![0; 256 * 4];
+    for (index, block) in BlockType::ALL.into_iter().enumerate() {
+        let [r, g, b] = block.color();
+        palette[index * 4..index * 4 + 4].copy_from_slice(&[r, g, b, 255]);
+    }
+
+    let mut children = Vec::new();
+    write_chunk(&mut children, b"SIZE", &size, &[])?;
+    write_chunk(
+        &mut children,
+        b"XYZI",
+        &[count.to_le_bytes().as_slice(), &voxels].concat(),
+        &[],
+    )?;
+    write_chunk(&mut children, b"RGBA", &palette, &[])?;
+
+    writer.write_all(b"VOX ")?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    write_chunk(writer, b"MAIN", &[], &children)?;
+
+    Ok(())
+}
+
+fn read_chunk(reader: &mut impl Read) -> io::Result<([u8; 4], Vec<u8>, Vec<u8>)> {
+    let mut id = [0; 4];
+    reader.read_exact(&mut id)?;
+
+    let content_length = read_i32(reader)?.max(0) as usize;
+    let children_length = read_i32(reader)?.max(0) as usize;
+
+    Ok((
+        id,
+        read_bytes(reader, content_length)?,
+        read_bytes(reader, children_length)?,
+    ))
+}
+
+/// Reads `length` bytes, growing the buffer only as the bytes arrive, so a broken length
+/// can't make the reader allocate more than the file contains.
+fn read_bytes(reader: &mut impl Read, length: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+
+    if bytes.len() < length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(bytes)
+}
+
+fn write_chunk(
+    writer: &mut impl Write,
+    id: &[u8; 4],
+    content: &[u8],
+    children: &[u8],
+) -> io::Result<()> {
+    writer.write_all(id)?;
+    writer.write_all(&(content.len() as i32).to_le_bytes())?;
+    writer.write_all(&(children.len() as i32).to_le_bytes())?;
+    writer.write_all(content)?;
+    writer.write_all(children)
+}
+
+fn read_i32(reader: &mut impl Read) -> io::Result<i32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORNER: &[u8] = include_bytes!("../../../tests/fixtures/corner.vox");
+
+    #[test]
+    fn reads_fixture_with_y_up() {
+        let models = read(&mut &CORNER[..], &PaletteMapping::default()).unwrap();
+        let [model] = models.as_slice() else {
+            panic!("expected one model, got {}", models.len());
+        };
+
+        // MagicaVoxel's 2 × 3 × 4 with z up
+        assert_eq!(model.size(), [2, 4, 3]);
+        assert_eq!(model.get([0, 0, 2]), Some(BlockType::Sand));
+        assert_eq!(model.get([1, 0, 2]), Some(BlockType::Water));
+        assert_eq!(model.get([0, 0, 0]), Some(BlockType::Log));
+        assert_eq!(model.get([0, 3, 2]), Some(BlockType::Stone));
+        assert_eq!(model.blocks().iter().flatten().count(), 4);
+    }
+
+    #[test]
+    fn palette_overrides_take_precedence() {
+        let mapping = PaletteMapping {
+            overrides: HashMap::from_iter([(1, Some(BlockType::Gravel)), (3, None)]),
+        };
+        let models = read(&mut &CORNER[..], &mapping).unwrap();
+
+        assert_eq!(models[0].get([0, 0, 2]), Some(BlockType::Gravel));
+        assert_eq!(models[0].get([0, 0, 0]), None);
+        assert_eq!(models[0].get([1, 0, 2]), Some(BlockType::Water));
+    }
+
+    #[test]
+    fn palette_overrides_are_read_from_loader_settings() {
+        // the settings part of a `.meta` file
+        let mapping: PaletteMapping =
+            ron::from_str("(overrides: {1: Some(Gravel), 3: None})").unwrap();
+        let models = read(&mut &CORNER[..], &mapping).unwrap();
+
+        assert_eq!(models[0].get([0, 0, 2]), Some(BlockType::Gravel));
+        assert_eq!(models[0].get([0, 0, 0]), None);
+    }
+
+    #[test]
+    fn round_trips_schematic() {
+        let mut schematic = Schematic::new([3, 5, 2]);
+        schematic.set([0, 0, 0], Some(BlockType::Stone));
+        schematic.set([2, 0, 1], Some(BlockType::Sand));
+        schematic.set([1, 4, 0], Some(BlockType::Leaves));
+        schematic.set([0, 2, 1], Some(BlockType::DiamondOre));
+
+        let mut bytes = Vec::new();
+        write(&schematic, &mut bytes).unwrap();
+        let models = read(&mut bytes.as_slice(), &PaletteMapping::default()).unwrap();
+
+        assert_eq!(models, [schematic]);
+    }
+
+    #[test]
+    fn rejects_truncated_and_oversized_models() {
+        assert!(read(&mut &CORNER[..CORNER.len() - 1], &PaletteMapping::default()).is_err());
+
+        let mut bytes = Vec::new();
+        write(&Schematic::new([1, 1, 1]), &mut bytes).unwrap();
+        // the SIZE chunk follows the header and the MAIN chunk header
+        bytes[32..36].copy_from_slice(&1000_i32.to_le_bytes());
+
+        assert!(read(&mut bytes.as_slice(), &PaletteMapping::default()).is_err());
+    }
+}
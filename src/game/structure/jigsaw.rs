@@ -0,0 +1,277 @@
+use std::collections::VecDeque;
+
+use crate::game::chunk::{generator::random::Random, BlockType, Chunk};
+
+use super::{
+    template::{Direction, Projection, StructureTemplate},
+    Placement, StructureSet,
+};
+
+/// A template placed in the world.
+#[derive(Debug, Clone)]
+pub struct Piece<'a> {
+    pub template: &'a StructureTemplate,
+    /// Clockwise quarter turns around the y axis.
+    pub turns: usize,
+    /// Minimum corner of the piece.
+    pub origin: [isize; 3],
+    /// Index of the connector the piece is attached to its parent with.
+    attached_by: Option<usize>,
+}
+
+impl Piece<'_> {
+    /// Size after turning the template.
+    pub fn size(&self) -> [isize; 3] {
+        let [x, y, z] = self.template.schematic.size().map(|n| n as isize);
+
+        if self.turns % 2 == 1 {
+            [z, y, x]
+        } else {
+            [x, y, z]
+        }
+    }
+
+    /// Position of a template block within the turned piece.
+    fn rotate(&self, [x, y, z]: [isize; 3]) -> [isize; 3] {
+        let [size_x, _, size_z] = self.template.schematic.size().map(|n| n as isize);
+
+        match self.turns % 4 {
+            0 => [x, y, z],
+            1 => [size_z - 1 - z, y, x],
+            2 => [size_x - 1 - x, y, size_z - 1 - z],
+            _ => [z, y, size_x - 1 - x],
+        }
+    }
+
+    fn to_world(&self, position: [usize; 3]) -> [isize; 3] {
+        let local = self.rotate(position.map(|n| n as isize));
+
+        [0, 1, 2].map(|axis| self.origin[axis] + local[axis])
+    }
+
+    /// Whether the footprints of the pieces overlap.
+    fn overlaps(&self, other: &Piece) -> bool {
+        let (size, other_size) = (self.size(), other.size());
+
+        [0, 2].into_iter().all(|axis| {
+            self.origin[axis] < other.origin[axis] + other_size[axis]
+                && other.origin[axis] < self.origin[axis] + size[axis]
+        })
+    }
+
+    /// Blocks of the piece at world positions. Air is included if the template clears it.
+    pub fn blocks(&self) -> impl Iterator<Item = ([isize; 3], Option<BlockType>)> + '_ {
+        let schematic = &self.template.schematic;
+
+        schematic
+            .positions()
+            .zip(schematic.blocks().iter().copied())
+            .filter(|(_, block)| block.is_some() || self.template.clear_air)
+            .map(|(position, block)| (self.to_world(position), block))
+    }
+}
+
+/// Assembles structures by attaching pieces from the pools of a structure set
+/// to the connectors of the pieces placed before, starting from a single piece.
+pub struct Assembler<'a, H> {
+    set: &'a StructureSet,
+    random: Random,
+    height_at: H,
+    /// Column of the start, pieces don't reach further than the maximum distance from it.
+    center: [isize; 2],
+    pieces: Vec<Piece<'a>>,
+}
+
+impl<'a, H: Fn(isize, isize) -> Option<isize>> Assembler<'a, H> {
+    /// `height_at` returns the height of the terrain in a column.
+    pub fn new(set: &'a StructureSet, random: Random, center: [isize; 2], height_at: H) -> Self {
+        Self {
+            set,
+            random,
+            height_at,
+            center,
+            pieces: Vec::new(),
+        }
+    }
+
+    /// Places the pieces of the structure, breadth first so the pieces close to the start are
+    /// placed before the space runs out. Returns nothing if the start doesn't fit.
+    pub fn assemble(mut self) -> Vec<Piece<'a>> {
+        let Some(start) = self.start() else {
+            return Vec::new();
+        };
+
+        self.pieces.push(start);
+        let mut queue = VecDeque::from([(0, 0)]);
+
+        while let Some((index, depth)) = queue.pop_front() {
+            if depth >= self.set.max_depth {
+                continue;
+            }
+
+            let piece = self.pieces[index].clone();
+
+            for (connector_index, connector) in piece.template.connectors.iter().enumerate() {
+                let Some(pool) = &connector.pool else {
+                    continue;
+                };
+
+                if piece.attached_by == Some(connector_index) {
+                    continue;
+                }
+
+                let position = piece.to_world(connector.position);
+                let facing = connector.facing.rotated(piece.turns);
+
+                if let Some(child) = self.attach(pool, position, facing) {
+                    self.pieces.push(child);
+                    queue.push_back((self.pieces.len() - 1, depth + 1));
+                }
+            }
+        }
+
+        self.pieces
+    }
+
+    fn start(&mut self) -> Option<Piece<'a>> {
+        let template = *self.shuffled_pool(&self.set.start_pool).first()?;
+        let mut piece = Piece {
+            template,
+            turns: self.random.range(0..4) as usize,
+            origin: [0; 3],
+            attached_by: None,
+        };
+        let size = piece.size();
+
+        piece.origin[0] = self.center[0] - size[0] / 2;
+        piece.origin[2] = self.center[1] - size[2] / 2;
+        piece.origin[1] = match self.set.placement {
+            Placement::Surface => self.ground_height(&piece)?,
+            Placement::Underground {
+                min_height,
+                max_height,
+            } => self.random.range(min_height..max_height + 1),
+        };
+
+        self.fits(&piece).then_some(piece)
+    }
+
+    /// Finds a piece of the pool which can be attached to a connector at `position`.
+    fn attach(&mut self, pool: &str, position: [isize; 3], facing: Direction) -> Option<Piece<'a>> {
+        let [offset_x, offset_z] = facing.offset();
+        let target = [position[0] + offset_x, position[1], position[2] + offset_z];
+
+        for template in self.shuffled_pool(pool) {
+            let first_turn = self.random.range(0..4) as usize;
+
+            for turns in (first_turn..first_turn + 4).map(|turns| turns % 4) {
+                for (index, connector) in template.connectors.iter().enumerate() {
+                    if connector.facing.rotated(turns) != facing.opposite() {
+                        continue;
+                    }
+
+                    let mut piece = Piece {
+                        template,
+                        turns,
+                        origin: [0; 3],
+                        attached_by: Some(index),
+                    };
+                    let local = piece.rotate(connector.position.map(|n| n as isize));
+
+                    piece.origin = [0, 1, 2].map(|axis| target[axis] - local[axis]);
+
+                    if template.projection == Projection::Terrain {
+                        let Some(height) = self.ground_height(&piece) else {
+                            continue;
+                        };
+                        piece.origin[1] = height;
+                    }
+
+                    if self.fits(&piece) {
+                        return Some(piece);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Height of the origin of a piece which puts its ground layer on top of the terrain.
+    fn ground_height(&self, piece: &Piece) -> Option<isize> {
+        let size = piece.size();
+        let terrain =
+            (self.height_at)(piece.origin[0] + size[0] / 2, piece.origin[2] + size[2] / 2)?;
+
+        Some(terrain + 1 - piece.template.ground as isize)
+    }
+
+    fn fits(&self, piece: &Piece) -> bool {
+        let size = piece.size();
+        let distance = self.set.max_distance;
+        let within_reach = [(0, 0), (2, 1)].into_iter().all(|(axis, center_axis)| {
+            piece.origin[axis] >= self.center[center_axis] - distance
+                && piece.origin[axis] + size[axis] <= self.center[center_axis] + distance
+        });
+        let within_world = piece.origin[1] >= 1 && piece.origin[1] + size[1] <= Chunk::HEIGHT;
+
+        within_reach && within_world && !self.pieces.iter().any(|other| other.overlaps(piece))
+    }
+
+    /// Templates of a pool in random order, where templates with more weight tend to come first.
+    fn shuffled_pool(&mut self, pool: &str) -> Vec<&'a StructureTemplate> {
+        let mut remaining: Vec<_> = self.set.pools.get(pool).into_iter().flatten().collect();
+        let mut shuffled = Vec::with_capacity(remaining.len());
+
+        while !remaining.is_empty() {
+            let total: u32 = remaining
+                .iter()
+                .map(|template| template.weight.max(1))
+                .sum();
+            let mut roll = self.random.range(0..total as isize) as u32;
+            let index = remaining
+                .iter()
+                .position(|template| {
+                    let weight = template.weight.max(1);
+                    let picked = roll < weight;
+                    roll = roll.saturating_sub(weight);
+                    picked
+                })
+                .unwrap_or(0);
+
+            shuffled.push(remaining.remove(index));
+        }
+
+        shuffled
+    }
+}
+
+/// Blocks of the assembled pieces, with foundations below the pieces which have them.
+pub fn blocks<'a>(
+    pieces: &'a [Piece],
+    height_at: impl Fn(isize, isize) -> Option<isize> + Copy + 'a,
+) -> impl Iterator<Item = ([isize; 3], Option<BlockType>)> + 'a {
+    /// Foundations don't reach deeper than this, pieces overhang steep slopes instead.
+    const MAX_FOUNDATION: isize = 8;
+
+    let foundations = pieces
+        .iter()
+        .filter(|piece| piece.template.foundation)
+        .flat_map(move |piece| {
+            let schematic = &piece.template.schematic;
+            let [size_x, _, size_z] = schematic.size();
+
+            (0..size_x)
+                .flat_map(move |x| (0..size_z).map(move |z| [x, 0, z]))
+                .filter_map(move |position| {
+                    let block = schematic.get(position)?;
+                    let [x, y, z] = piece.to_world(position);
+                    let bottom = height_at(x, z)?.max(y - MAX_FOUNDATION);
+
+                    Some((bottom + 1..y).map(move |y| ([x, y, z], Some(block))))
+                })
+                .flatten()
+        });
+
+    pieces.iter().flat_map(Piece::blocks).chain(foundations)
+}
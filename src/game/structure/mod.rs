@@ -0,0 +1,381 @@
+use std::{collections::HashMap, fmt, fs, io, path::Path, sync::Arc};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::AppState;
+
+use self::{
+    jigsaw::{Assembler, Piece},
+    template::{StructureTemplate, TemplateFile},
+};
+
+use super::{
+    chunk::{
+        anvil::AnvilWorld,
+        block_mapping::BlockMapping,
+        budget::IntegrationBudget,
+        generator::{biome::Biome, random::Random, ChunkGenerator},
+        grid::{ChunkGrid, ChunkGridInner, GridCoordinates},
+        Chunk, ChunkEdited, DespawnChunk,
+    },
+    schematic::SchematicError,
+};
+
+pub mod jigsaw;
+pub mod template;
+
+/// Directory the structure sets are loaded from. Paths of templates are relative to it.
+pub const STRUCTURES_PATH: &str = "assets/structures";
+
+pub struct StructurePlugin;
+
+impl Plugin for StructurePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StructureSets>()
+            .add_systems(Startup, StructureSets::load_all)
+            .add_systems(
+                Update,
+                place_structures.run_if(
+                    // imported worlds already have their structures
+                    in_state(AppState::InGame).and_then(not(resource_exists::<AnvilWorld>())),
+                ),
+            );
+    }
+}
+
+/// Where a structure starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Placement {
+    /// On the terrain, e.g. villages.
+    Surface,
+    /// At a random height within the range, e.g. dungeons.
+    Underground {
+        min_height: isize,
+        max_height: isize,
+    },
+}
+
+/// A kind of structure, e.g. villages, and the rules for scattering it across the world.
+///
+/// The world is divided into square regions of `spacing` chunks, each of which contains
+/// the start of one structure at a random chunk. Starts keep at least `separation` chunks
+/// away from the borders of their regions' neighbours.
+#[derive(Debug, Clone)]
+pub struct StructureSet {
+    pub name: String,
+    /// Biomes the structure can start in, any biome if empty.
+    pub biomes: Vec<Biome>,
+    pub placement: Placement,
+    pub spacing: isize,
+    pub separation: isize,
+    /// Separates the random numbers of different structure sets, so it has to be unique.
+    pub salt: u32,
+    /// How many pieces may be chained to the start piece at most.
+    pub max_depth: usize,
+    /// How far in blocks the pieces may reach from the start.
+    pub max_distance: isize,
+    pub start_pool: String,
+    pub pools: HashMap<String, Vec<StructureTemplate>>,
+}
+
+#[derive(Deserialize)]
+struct StructureSetFile {
+    name: String,
+    #[serde(default)]
+    biomes: Vec<Biome>,
+    placement: Placement,
+    spacing: isize,
+    separation: isize,
+    salt: u32,
+    max_depth: usize,
+    max_distance: isize,
+    start_pool: String,
+    pools: HashMap<String, Vec<TemplateFile>>,
+}
+
+#[derive(Debug)]
+pub enum StructureError {
+    Io(io::Error),
+    Ron(ron::error::SpannedError),
+    /// A template schematic failed to load.
+    Schematic(String, SchematicError),
+    UnknownPool(String),
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StructureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StructureError::Io(error) => write!(f, "{error}"),
+            StructureError::Ron(error) => write!(f, "{error}"),
+            StructureError::Schematic(path, error) => write!(f, "{path}: {error}"),
+            StructureError::UnknownPool(pool) => write!(f, "unknown pool '{pool}'"),
+            StructureError::Invalid(reason) => write!(f, "invalid structure set: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for StructureError {}
+
+impl From<io::Error> for StructureError {
+    fn from(error: io::Error) -> Self {
+        StructureError::Io(error)
+    }
+}
+
+impl StructureSet {
+    /// Salt of the random numbers which place structures within their regions.
+    const REGION_SALT: u32 = 40;
+    /// Salt of the random numbers which assemble structures.
+    const ASSEMBLY_SALT: u32 = 41;
+
+    /// Loads a structure set from a RON file. Template schematics are loaded relative
+    /// to the directory of the file.
+    pub fn load(path: impl AsRef<Path>, mapping: &BlockMapping) -> Result<Self, StructureError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        let file: StructureSetFile = ron::from_str(&source).map_err(StructureError::Ron)?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        if file.spacing <= file.separation || file.separation < 0 {
+            return Err(StructureError::Invalid(
+                "spacing has to be larger than separation",
+            ));
+        }
+
+        let pools = file
+            .pools
+            .into_iter()
+            .map(|(name, templates)| {
+                let templates = templates
+                    .into_iter()
+                    .map(|template| template.load(directory, mapping))
+                    .collect::<Result<_, _>>()?;
+                Ok((name, templates))
+            })
+            .collect::<Result<HashMap<_, Vec<StructureTemplate>>, StructureError>>()?;
+
+        let referenced_pools = pools
+            .values()
+            .flatten()
+            .flat_map(|template| &template.connectors)
+            .filter_map(|connector| connector.pool.as_ref())
+            .chain([&file.start_pool]);
+
+        for pool in referenced_pools {
+            if !pools.contains_key(pool) {
+                return Err(StructureError::UnknownPool(pool.clone()));
+            }
+        }
+
+        Ok(Self {
+            name: file.name,
+            biomes: file.biomes,
+            placement: file.placement,
+            spacing: file.spacing,
+            separation: file.separation,
+            salt: file.salt,
+            max_depth: file.max_depth,
+            max_distance: file.max_distance,
+            start_pool: file.start_pool,
+            pools,
+        })
+    }
+
+    /// Chunk in which the structure of a region starts.
+    pub fn start_chunk(&self, seed: u32, [region_x, region_z]: [isize; 2]) -> GridCoordinates {
+        let mut random = Random::at(
+            seed,
+            Self::REGION_SALT,
+            &[self.salt as isize, region_x, region_z],
+        );
+        let range = 0..self.spacing - self.separation;
+        let chunk_x = region_x * self.spacing + random.range(range.clone());
+        let chunk_z = region_z * self.spacing + random.range(range);
+
+        GridCoordinates::new(chunk_x * Chunk::WIDTH, 0, chunk_z * Chunk::WIDTH)
+    }
+
+    /// Whether a structure starts in the chunk.
+    pub fn starts_in(&self, seed: u32, coordinates: GridCoordinates) -> bool {
+        let region = [coordinates.x, coordinates.z]
+            .map(|n| n.div_euclid(Chunk::WIDTH).div_euclid(self.spacing));
+
+        self.start_chunk(seed, region) == coordinates
+    }
+
+    /// Pieces of the structure starting in a chunk, placed on the terrain returned by `height_at`.
+    /// The start piece comes first.
+    pub fn assemble(
+        &self,
+        seed: u32,
+        start: GridCoordinates,
+        height_at: impl Fn(isize, isize) -> Option<isize>,
+    ) -> Vec<Piece<'_>> {
+        let random = Random::at(
+            seed,
+            Self::ASSEMBLY_SALT,
+            &[self.salt as isize, start.x, start.z],
+        );
+        let center = [start.x, start.z].map(|n| n + Chunk::WIDTH / 2);
+
+        Assembler::new(self, random, center, &height_at).assemble()
+    }
+
+    /// Places the structure starting in a chunk once the terrain of all chunks it may reach has
+    /// been generated, and the biome allows it. The pieces are placed on the terrain as it was
+    /// generated, see [`ChunkGridInner::generated_height`].
+    ///
+    /// Returns the loaded chunks which have been changed, or `None` if the structure
+    /// has already been placed or the terrain isn't ready yet.
+    pub fn place(
+        &self,
+        grid: &ChunkGridInner,
+        generator: &ChunkGenerator,
+        start: GridCoordinates,
+    ) -> Option<Vec<GridCoordinates>> {
+        if grid.is_structure_placed(start, self.salt) {
+            return None;
+        }
+
+        let [x, z] = [start.x, start.z].map(|n| n + Chunk::WIDTH / 2);
+        let [min, max] = [-self.max_distance, self.max_distance]
+            .map(|offset| [x + offset, z + offset].map(|n| n.div_euclid(Chunk::WIDTH)));
+        let terrain_ready = (min[0]..=max[0])
+            .flat_map(|chunk_x| (min[1]..=max[1]).map(move |chunk_z| (chunk_x, chunk_z)))
+            .all(|(chunk_x, chunk_z)| {
+                grid.has_terrain(GridCoordinates::new(
+                    chunk_x * Chunk::WIDTH,
+                    0,
+                    chunk_z * Chunk::WIDTH,
+                ))
+            });
+
+        if !terrain_ready {
+            return None;
+        }
+
+        let allowed = self.biomes.is_empty()
+            || generator
+                .biome_at(x, z)
+                .is_some_and(|biome| self.biomes.contains(&biome));
+        let height_at = |x, z| grid.generated_height(x, z);
+        let pieces = if allowed {
+            self.assemble(generator.seed(), start, height_at)
+        } else {
+            Vec::new()
+        };
+        let flooded = self.placement == Placement::Surface
+            && pieces
+                .first()
+                .is_some_and(|piece| Self::in_river(generator, piece));
+        let blocks = if flooded {
+            Vec::new()
+        } else {
+            jigsaw::blocks(&pieces, height_at).collect()
+        };
+
+        grid.place_structure(start, self.salt, blocks)
+    }
+
+    /// Whether the footprint of a piece reaches into the channel of a river, where surface
+    /// structures would be flooded.
+    fn in_river(generator: &ChunkGenerator, piece: &Piece) -> bool {
+        let [size_x, _, size_z] = piece.size();
+        let min = [piece.origin[0], piece.origin[2]];
+        let max = [min[0] + size_x - 1, min[1] + size_z - 1];
+        let rivers = generator.rivers_near(min, max);
+
+        (min[0]..=max[0])
+            .flat_map(|x| (min[1]..=max[1]).map(move |z| (x, z)))
+            .any(|(x, z)| rivers.iter().any(|river| river.contains(x, z)))
+    }
+}
+
+/// All structure sets which are placed in the world.
+#[derive(Resource, Clone, Default, Deref)]
+pub struct StructureSets(Arc<Vec<StructureSet>>);
+
+impl StructureSets {
+    /// Loads every `.ron` file in the directory as a structure set. Sets which fail to load are skipped.
+    pub fn load(directory: impl AsRef<Path>) -> Self {
+        let directory = directory.as_ref();
+        let mapping = BlockMapping::minecraft();
+        let mut paths: Vec<_> = match fs::read_dir(directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+                .collect(),
+            Err(error) => {
+                error!(
+                    "failed to read structures from {}: {error}",
+                    directory.display()
+                );
+                Vec::new()
+            }
+        };
+        // sets are placed in the order they're listed, which must not depend on the file system
+        paths.sort();
+
+        let sets = paths
+            .into_iter()
+            .filter_map(|path| {
+                StructureSet::load(&path, &mapping)
+                    .map_err(|error| {
+                        error!("failed to load structure set {}: {error}", path.display())
+                    })
+                    .ok()
+            })
+            .collect();
+
+        Self(Arc::new(sets))
+    }
+
+    fn load_all(mut sets: ResMut<StructureSets>) {
+        *sets = Self::load(STRUCTURES_PATH);
+    }
+}
+
+/// Places the structures starting in loaded chunks. Starts are looked for once when a chunk
+/// is loaded, and kept in `pending` until they're placed or their chunk is unloaded.
+fn place_structures(
+    chunks: Query<Ref<GridCoordinates>, Without<DespawnChunk>>,
+    mut pending: Local<Vec<(GridCoordinates, usize)>>,
+    mut edited: EventWriter<ChunkEdited>,
+    grid: Res<ChunkGrid>,
+    generator: Res<ChunkGenerator>,
+    sets: Res<StructureSets>,
+    mut budget: ResMut<IntegrationBudget>,
+) {
+    // all chunks are looked at again when the world or the structure sets change
+    let rescan = generator.is_changed() || sets.is_changed();
+    if rescan {
+        pending.clear();
+    }
+
+    let seed = generator.seed();
+    let starts = chunks
+        .iter()
+        .filter(|coordinates| rescan || coordinates.is_added())
+        .flat_map(|coordinates| {
+            let coordinates = *coordinates;
+            sets.iter()
+                .enumerate()
+                .filter(move |(_, set)| set.starts_in(seed, coordinates))
+                .map(move |(index, _)| (coordinates, index))
+        });
+    pending.extend(starts);
+
+    pending.retain(|(start, index)| {
+        grid.contains_key(start) && !grid.is_structure_placed(*start, sets[*index].salt)
+    });
+
+    let placed = pending
+        .iter()
+        .filter_map(|(start, index)| sets[*index].place(&grid, &generator, *start));
+
+    budget.run(placed, |changed| {
+        edited.send_batch(changed.into_iter().map(ChunkEdited));
+    });
+}
@@ -0,0 +1,142 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::game::{chunk::block_mapping::BlockMapping, schematic::Schematic};
+
+use super::StructureError;
+
+/// Horizontal direction, e.g. the one a connector faces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Direction {
+    /// Towards negative z.
+    North,
+    /// Towards positive x.
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    const CLOCKWISE: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    /// Offset to the neighbouring block in this direction, on the x and z axes.
+    pub fn offset(self) -> [isize; 2] {
+        match self {
+            Direction::North => [0, -1],
+            Direction::East => [1, 0],
+            Direction::South => [0, 1],
+            Direction::West => [-1, 0],
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        self.rotated(2)
+    }
+
+    /// Turns the direction clockwise, seen from above.
+    pub fn rotated(self, quarter_turns: usize) -> Self {
+        Self::CLOCKWISE[(self as usize + quarter_turns) % 4]
+    }
+}
+
+/// How the height of a piece is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum Projection {
+    /// Placed on the terrain in the middle of the piece, e.g. houses.
+    #[default]
+    Terrain,
+    /// At the height of the connector it's attached to, e.g. the corridors of dungeons.
+    Rigid,
+}
+
+/// A point where pieces are attached to each other.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Connector {
+    /// Position of the connector within the template, on the side it faces.
+    pub position: [usize; 3],
+    pub facing: Direction,
+    /// Pool of the pieces which are attached here. Connectors without a pool are only
+    /// used to attach the template itself to other pieces.
+    #[serde(default)]
+    pub pool: Option<String>,
+}
+
+/// One piece of a structure, e.g. a house or a corridor, made from a schematic.
+#[derive(Debug, Clone)]
+pub struct StructureTemplate {
+    pub schematic: Schematic,
+    /// Relative chance of being picked from its pool.
+    pub weight: u32,
+    pub projection: Projection,
+    /// Layer which lies on top of the terrain. The layers below it are buried.
+    pub ground: usize,
+    /// Whether the air within the template replaces terrain. Otherwise only its blocks are placed,
+    /// so e.g. ruins end up partly buried.
+    pub clear_air: bool,
+    /// Whether the blocks of the bottom layer are extended down to the terrain below.
+    pub foundation: bool,
+    pub connectors: Vec<Connector>,
+}
+
+/// Entry of a template in a structure set file.
+#[derive(Deserialize)]
+pub(super) struct TemplateFile {
+    /// Path of the schematic, relative to the structure directory.
+    schematic: String,
+    #[serde(default = "TemplateFile::default_weight")]
+    weight: u32,
+    #[serde(default)]
+    projection: Projection,
+    #[serde(default)]
+    ground: usize,
+    #[serde(default)]
+    clear_air: bool,
+    #[serde(default)]
+    foundation: bool,
+    #[serde(default)]
+    connectors: Vec<Connector>,
+}
+
+impl TemplateFile {
+    fn default_weight() -> u32 {
+        1
+    }
+
+    pub(super) fn load(
+        self,
+        directory: &Path,
+        mapping: &BlockMapping,
+    ) -> Result<StructureTemplate, StructureError> {
+        let schematic = Schematic::load(directory.join(&self.schematic), mapping)
+            .map_err(|error| StructureError::Schematic(self.schematic.clone(), error))?;
+        let size = schematic.size();
+
+        let connectors_inside = self
+            .connectors
+            .iter()
+            .all(|connector| (0..3).all(|axis| connector.position[axis] < size[axis]));
+
+        if !connectors_inside {
+            return Err(StructureError::Invalid("connector outside of its template"));
+        }
+        if self.ground >= size[1] {
+            return Err(StructureError::Invalid("ground layer above the template"));
+        }
+
+        Ok(StructureTemplate {
+            schematic,
+            weight: self.weight,
+            projection: self.projection,
+            ground: self.ground,
+            clear_air: self.clear_air,
+            foundation: self.foundation,
+            connectors: self.connectors,
+        })
+    }
+}
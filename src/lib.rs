@@ -4,6 +4,7 @@ pub mod daylight_cycle;
 pub mod game;
 pub mod menu;
 pub mod my_material;
+pub mod nbt;
 pub mod settings;
 pub mod utils;
 pub mod wireframe_controller;
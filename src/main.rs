@@ -3,7 +3,12 @@ use bevy::{prelude::*, window::WindowMode};
 use bevy_3d::array_texture::{ArrayTextureMaterial, ArrayTexturePlugin, ATTRIBUTE_TEXTURE_INDEX};
 use bevy_3d::daylight_cycle::{DaylightCyclePlugin, Sun};
 use bevy_3d::game::camera_controller::CameraController;
+use bevy_3d::game::chunk::anvil::AnvilWorld;
+use bevy_3d::game::chunk::block_mapping::BlockMapping;
 use bevy_3d::game::chunk::ChunkPlugin;
+use bevy_3d::game::editor::EditorPlugin;
+use bevy_3d::game::schematic::SchematicPlugin;
+use bevy_3d::game::structure::StructurePlugin;
 use bevy_3d::game::{camera_controller::CameraControllerPlugin, debug_info::DebugInfoPlugin};
 use bevy_3d::menu::MenuPlugin;
 use bevy_3d::my_material::MyMaterialPlugin;
@@ -16,8 +21,13 @@ use bevy_inspector_egui::DefaultInspectorConfigPlugin;
 use bevy_rapier3d::prelude::*;
 
 fn main() {
-    App::new()
-        .add_state::<AppState>()
+    let mut app = App::new();
+
+    if let Some(world) = import_world_from_args() {
+        app.insert_resource(world);
+    }
+
+    app.add_state::<AppState>()
         .init_schedule(OnEnter(AppState::Menu))
         .init_schedule(OnExit(AppState::Menu))
         .init_schedule(OnEnter(AppState::InGame))
@@ -48,6 +58,9 @@ fn main() {
             MyMaterialPlugin,
             WireframeControllerPlugin,
             DaylightCyclePlugin,
+            EditorPlugin,
+            SchematicPlugin,
+            StructurePlugin,
         ))
         .add_systems(PreStartup, setup_config)
         .add_systems(Startup, (setup_light, textured_cube))
@@ -56,6 +69,27 @@ fn main() {
         .run();
 }
 
+/// Parses `--world <region directory>` and `--mapping <block mapping file>`
+/// for exploring an existing Minecraft world.
+fn import_world_from_args() -> Option<AnvilWorld> {
+    let args: Vec<String> = std::env::args().collect();
+    let value_of = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .and_then(|index| args.get(index + 1))
+    };
+
+    let directory = value_of("--world")?;
+    let mapping = match value_of("--mapping") {
+        Some(path) => BlockMapping::load(path)
+            .map_err(|error| eprintln!("failed to load block mapping {path}: {error}"))
+            .unwrap_or_default(),
+        None => BlockMapping::minecraft(),
+    };
+
+    Some(AnvilWorld::open(directory, mapping))
+}
+
 fn spawn_ball(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
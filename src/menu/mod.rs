@@ -4,15 +4,7 @@ use bevy_egui::{
     EguiContexts,
 };
 
-use crate::{
-    game::chunk::{
-        generator::ChunkGenerator,
-        grid::{ChunkGrid, GridCoordinates},
-        DespawnChunk,
-    },
-    settings::Settings,
-    AppState,
-};
+use crate::AppState;
 
 pub struct MenuPlugin;
 
@@ -20,10 +12,7 @@ impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(AppState::Menu), free_cursor)
             //.add_systems(Update, show_menu.run_if(in_state(AppState::Menu)))
-            .add_systems(
-                OnEnter(AppState::InGame),
-                (capture_cursor, apply_noise_settings),
-            );
+            .add_systems(OnEnter(AppState::InGame), capture_cursor);
     }
 }
 
@@ -68,20 +57,3 @@ fn show_menu(
             }
         });
 }
-
-fn apply_noise_settings(
-    mut commands: Commands,
-    mut settings: ResMut<Settings>,
-    generator: Res<ChunkGenerator>,
-    chunks: Query<(Entity, &GridCoordinates)>,
-    grid: Res<ChunkGrid>,
-) {
-    if settings.detect_changes() {
-        generator.set_scale(settings.noise.scale);
-
-        for (entity, coordinates) in chunks.iter() {
-            grid.remove(coordinates);
-            commands.entity(entity).insert(DespawnChunk);
-        }
-    }
-}
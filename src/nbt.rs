@@ -0,0 +1,459 @@
+//! Reader and writer for Minecraft's Named Binary Tag format.
+//! https://minecraft.wiki/w/NBT_format
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Read, Write},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    /// Stored unsigned since byte arrays usually contain raw data rather than numbers.
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+#[derive(Debug)]
+pub enum NbtError {
+    Io(io::Error),
+    InvalidTagType(u8),
+    /// The root tag of a file has to be a compound.
+    InvalidRoot,
+    /// Lists and compounds are nested deeper than [`MAX_DEPTH`].
+    TooDeep,
+    /// An array or list is longer than [`MAX_LENGTH`].
+    TooLong(usize),
+}
+
+impl fmt::Display for NbtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NbtError::Io(error) => write!(f, "{error}"),
+            NbtError::InvalidTagType(id) => write!(f, "invalid tag type {id}"),
+            NbtError::InvalidRoot => write!(f, "root tag is not a compound"),
+            NbtError::TooDeep => write!(f, "tags are nested deeper than {MAX_DEPTH} levels"),
+            NbtError::TooLong(length) => write!(f, "array of {length} elements is too long"),
+        }
+    }
+}
+
+impl std::error::Error for NbtError {}
+
+impl From<io::Error> for NbtError {
+    fn from(error: io::Error) -> Self {
+        NbtError::Io(error)
+    }
+}
+
+const TAG_END: u8 = 0;
+const TAG_BYTE: u8 = 1;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_DOUBLE: u8 = 6;
+const TAG_BYTE_ARRAY: u8 = 7;
+const TAG_STRING: u8 = 8;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+const TAG_INT_ARRAY: u8 = 11;
+const TAG_LONG_ARRAY: u8 = 12;
+
+/// Lists and compounds nested deeper than this are rejected, like Minecraft does,
+/// so malformed files can't overflow the stack.
+pub const MAX_DEPTH: usize = 512;
+/// Arrays and lists longer than this are rejected. Shorter ones still only grow as their
+/// elements are read, so a length alone can't make the reader allocate much.
+pub const MAX_LENGTH: usize = 1 << 28;
+
+impl Tag {
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(entries) => entries.get(key),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of any integer tag, widened to `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value.into()),
+            Tag::Short(value) => Some(value.into()),
+            Tag::Int(value) => Some(value.into()),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&BTreeMap<String, Tag>> {
+        match self {
+            Tag::Compound(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&[u8]> {
+        match self {
+            Tag::ByteArray(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => TAG_BYTE,
+            Tag::Short(_) => TAG_SHORT,
+            Tag::Int(_) => TAG_INT,
+            Tag::Long(_) => TAG_LONG,
+            Tag::Float(_) => TAG_FLOAT,
+            Tag::Double(_) => TAG_DOUBLE,
+            Tag::ByteArray(_) => TAG_BYTE_ARRAY,
+            Tag::String(_) => TAG_STRING,
+            Tag::List(_) => TAG_LIST,
+            Tag::Compound(_) => TAG_COMPOUND,
+            Tag::IntArray(_) => TAG_INT_ARRAY,
+            Tag::LongArray(_) => TAG_LONG_ARRAY,
+        }
+    }
+}
+
+/// Reads the named root compound of an uncompressed NBT stream.
+pub fn read(reader: &mut impl Read) -> Result<(String, Tag), NbtError> {
+    match read_u8(reader)? {
+        TAG_COMPOUND => {
+            let name = read_string(reader)?;
+            let tag = read_payload(reader, TAG_COMPOUND, 0)?;
+            Ok((name, tag))
+        }
+        _ => Err(NbtError::InvalidRoot),
+    }
+}
+
+/// Writes a named root compound as an uncompressed NBT stream.
+pub fn write(writer: &mut impl Write, name: &str, tag: &Tag) -> Result<(), NbtError> {
+    if !matches!(tag, Tag::Compound(_)) {
+        return Err(NbtError::InvalidRoot);
+    }
+
+    writer.write_all(&[TAG_COMPOUND])?;
+    write_string(writer, name)?;
+    write_payload(writer, tag)?;
+
+    Ok(())
+}
+
+fn read_payload(reader: &mut impl Read, id: u8, depth: usize) -> Result<Tag, NbtError> {
+    if depth > MAX_DEPTH {
+        return Err(NbtError::TooDeep);
+    }
+
+    let tag = match id {
+        TAG_BYTE => Tag::Byte(read_u8(reader)? as i8),
+        TAG_SHORT => Tag::Short(i16::from_be_bytes(read_array(reader)?)),
+        TAG_INT => Tag::Int(read_i32(reader)?),
+        TAG_LONG => Tag::Long(i64::from_be_bytes(read_array(reader)?)),
+        TAG_FLOAT => Tag::Float(f32::from_be_bytes(read_array(reader)?)),
+        TAG_DOUBLE => Tag::Double(f64::from_be_bytes(read_array(reader)?)),
+        TAG_BYTE_ARRAY => {
+            let length = read_length(reader)?;
+            let mut values = Vec::new();
+            reader.take(length as u64).read_to_end(&mut values)?;
+
+            if values.len() < length {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+
+            Tag::ByteArray(values)
+        }
+        TAG_STRING => Tag::String(read_string(reader)?),
+        TAG_LIST => {
+            let element_id = read_u8(reader)?;
+            let length = read_length(reader)?;
+            let values = (0..length)
+                .map(|_| read_payload(reader, element_id, depth + 1))
+                .collect::<Result<_, _>>()?;
+            Tag::List(values)
+        }
+        TAG_COMPOUND => {
+            let mut entries = BTreeMap::new();
+
+            loop {
+                let id = read_u8(reader)?;
+
+                if id == TAG_END {
+                    break;
+                }
+
+                let name = read_string(reader)?;
+                entries.insert(name, read_payload(reader, id, depth + 1)?);
+            }
+
+            Tag::Compound(entries)
+        }
+        TAG_INT_ARRAY => {
+            let length = read_length(reader)?;
+            let values = (0..length)
+                .map(|_| read_i32(reader))
+                .collect::<Result<_, _>>()?;
+            Tag::IntArray(values)
+        }
+        TAG_LONG_ARRAY => {
+            let length = read_length(reader)?;
+            let values = (0..length)
+                .map(|_| read_array(reader).map(i64::from_be_bytes))
+                .collect::<Result<_, _>>()?;
+            Tag::LongArray(values)
+        }
+        id => return Err(NbtError::InvalidTagType(id)),
+    };
+
+    Ok(tag)
+}
+
+fn write_payload(writer: &mut impl Write, tag: &Tag) -> io::Result<()> {
+    match tag {
+        Tag::Byte(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Short(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Int(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Long(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Float(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Double(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::ByteArray(values) => {
+            write_length(writer, values.len())?;
+            writer.write_all(values)
+        }
+        Tag::String(value) => write_string(writer, value),
+        Tag::List(values) => {
+            let element_id = values.first().map(Tag::id).unwrap_or(TAG_END);
+            writer.write_all(&[element_id])?;
+            write_length(writer, values.len())?;
+            values
+                .iter()
+                .try_for_each(|value| write_payload(writer, value))
+        }
+        Tag::Compound(entries) => {
+            for (name, value) in entries {
+                writer.write_all(&[value.id()])?;
+                write_string(writer, name)?;
+                write_payload(writer, value)?;
+            }
+            writer.write_all(&[TAG_END])
+        }
+        Tag::IntArray(values) => {
+            write_length(writer, values.len())?;
+            values
+                .iter()
+                .try_for_each(|value| writer.write_all(&value.to_be_bytes()))
+        }
+        Tag::LongArray(values) => {
+            write_length(writer, values.len())?;
+            values
+                .iter()
+                .try_for_each(|value| writer.write_all(&value.to_be_bytes()))
+        }
+    }
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    read_array::<1>(reader).map(|[byte]| byte)
+}
+
+fn read_i32(reader: &mut impl Read) -> io::Result<i32> {
+    read_array(reader).map(i32::from_be_bytes)
+}
+
+fn read_length(reader: &mut impl Read) -> Result<usize, NbtError> {
+    // negative lengths are treated as empty, like the vanilla implementation does
+    let length = read_i32(reader)?.max(0) as usize;
+
+    if length > MAX_LENGTH {
+        return Err(NbtError::TooLong(length));
+    }
+
+    Ok(length)
+}
+
+fn write_length(writer: &mut impl Write, length: usize) -> io::Result<()> {
+    let length = i32::try_from(length)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "NBT array is too long"))?;
+    writer.write_all(&length.to_be_bytes())
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let length = u16::from_be_bytes(read_array(reader)?);
+    let mut bytes = vec![0; length.into()];
+    reader.read_exact(&mut bytes)?;
+
+    // strings are stored as modified UTF-8, which only differs from UTF-8 for exotic characters
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn write_string(writer: &mut impl Write, value: &str) -> io::Result<()> {
+    let length = u16::try_from(value.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "NBT string is too long"))?;
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(value.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_tag() -> Tag {
+        Tag::Compound(BTreeMap::from([
+            ("byte".to_string(), Tag::Byte(-3)),
+            ("short".to_string(), Tag::Short(-300)),
+            ("int".to_string(), Tag::Int(70_000)),
+            ("long".to_string(), Tag::Long(-(1 << 40))),
+            ("float".to_string(), Tag::Float(0.5)),
+            ("double".to_string(), Tag::Double(-2.25)),
+            ("bytes".to_string(), Tag::ByteArray(vec![0, 255, 7])),
+            ("string".to_string(), Tag::String("grass_block".to_string())),
+            (
+                "list".to_string(),
+                Tag::List(vec![Tag::Short(1), Tag::Short(2)]),
+            ),
+            ("empty list".to_string(), Tag::List(Vec::new())),
+            (
+                "compound".to_string(),
+                Tag::Compound(BTreeMap::from([("nested".to_string(), Tag::Int(1))])),
+            ),
+            ("ints".to_string(), Tag::IntArray(vec![-1, 0, 1])),
+            (
+                "longs".to_string(),
+                Tag::LongArray(vec![i64::MIN, i64::MAX]),
+            ),
+        ]))
+    }
+
+    fn written(name: &str, tag: &Tag) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(&mut bytes, name, tag).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips_every_tag() {
+        let bytes = written("root", &every_tag());
+
+        assert_eq!(
+            read(&mut bytes.as_slice()).unwrap(),
+            ("root".to_string(), every_tag())
+        );
+    }
+
+    #[test]
+    fn rejects_non_compound_roots() {
+        assert!(matches!(
+            write(&mut Vec::new(), "", &Tag::Int(1)),
+            Err(NbtError::InvalidRoot)
+        ));
+        assert!(matches!(
+            read(&mut [TAG_INT, 0, 0, 0, 0, 0, 1].as_slice()),
+            Err(NbtError::InvalidRoot)
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let bytes = written("root", &every_tag());
+
+        for length in 0..bytes.len() {
+            assert!(read(&mut &bytes[..length]).is_err(), "{length} bytes");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_tag_types() {
+        let mut bytes = vec![TAG_COMPOUND, 0, 0, 13, 0, 0];
+        bytes.push(TAG_END);
+
+        assert!(matches!(
+            read(&mut bytes.as_slice()),
+            Err(NbtError::InvalidTagType(13))
+        ));
+    }
+
+    #[test]
+    fn rejects_oversized_lengths() {
+        let mut bytes = vec![TAG_COMPOUND, 0, 0, TAG_BYTE_ARRAY, 0, 0];
+        bytes.extend((MAX_LENGTH as i32 + 1).to_be_bytes());
+
+        assert!(matches!(
+            read(&mut bytes.as_slice()),
+            Err(NbtError::TooLong(length)) if length == MAX_LENGTH + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_long_arrays_without_their_data() {
+        // allowed lengths only allocate as far as the data goes
+        for id in [TAG_BYTE_ARRAY, TAG_INT_ARRAY, TAG_LONG_ARRAY] {
+            let mut bytes = vec![TAG_COMPOUND, 0, 0, id, 0, 0];
+            bytes.extend((MAX_LENGTH as i32).to_be_bytes());
+            bytes.extend([0; 8]);
+
+            assert!(matches!(read(&mut bytes.as_slice()), Err(NbtError::Io(_))));
+        }
+
+        let mut bytes = vec![TAG_COMPOUND, 0, 0, TAG_LIST, 0, 0, TAG_LONG];
+        bytes.extend((MAX_LENGTH as i32).to_be_bytes());
+        assert!(matches!(read(&mut bytes.as_slice()), Err(NbtError::Io(_))));
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let nested = |depth: usize| {
+            let mut bytes = vec![TAG_COMPOUND, 0, 0];
+            for _ in 0..depth {
+                bytes.extend([TAG_COMPOUND, 0, 0]);
+            }
+            bytes.extend(vec![TAG_END; depth + 1]);
+            bytes
+        };
+
+        assert!(read(&mut nested(MAX_DEPTH).as_slice()).is_ok());
+        assert!(matches!(
+            read(&mut nested(MAX_DEPTH + 1).as_slice()),
+            Err(NbtError::TooDeep)
+        ));
+    }
+}
@@ -3,7 +3,11 @@ use bevy_inspector_egui::{
     prelude::ReflectInspectorOptions, quick::ResourceInspectorPlugin, InspectorOptions,
 };
 
-use crate::game::chunk::mesh_builder::MeshBuilderSettings;
+use crate::game::chunk::{
+    generator::{ChunkGenerator, WorldPreset},
+    mesh_builder::MeshBuilderSettings,
+    pipeline::PipelineWorkers,
+};
 
 pub struct SettingsPlugin;
 
@@ -38,14 +42,28 @@ fn update_render_distance(input: Res<Input<KeyCode>>, mut settings: ResMut<Setti
     }
 }
 
-#[derive(Clone, Copy, Resource, Reflect, InspectorOptions)]
+#[derive(Clone, Resource, Reflect, InspectorOptions)]
 #[reflect(InspectorOptions)]
 pub struct Settings {
+    /// Radius of the circle around the player in which chunks are loaded, in chunks.
     #[inspector(min = 0, max = 32)]
     pub render_distance: isize,
+    /// Chunks are only unloaded this many chunks beyond the render distance, so chunks at its
+    /// edge aren't unloaded and loaded again as the player moves back and forth.
+    #[inspector(min = 0)]
+    pub unload_margin: isize,
+    /// Unloaded chunks kept in memory, so they don't have to be generated again when the
+    /// player returns. 0 disables the cache.
+    pub cached_chunks: usize,
     pub update_chunks: bool,
-    pub task_polls_per_frame: usize,
-    pub mesh_updates_per_frame: usize,
+    /// Chunks generated at the same time, further chunks wait in a queue ordered by distance.
+    #[inspector(min = 1)]
+    pub max_generation_tasks: usize,
+    pub pipeline: PipelineWorkers,
+    /// Frame time in milliseconds chunks are integrated into the world within, as long as
+    /// the rest of the frame leaves time for it.
+    #[inspector(min = 1.0)]
+    pub target_frame_time: f32,
     pub mesh_builder: MeshBuilderSettings,
     pub noise: NoiseSettings,
     prev_noise: NoiseSettings,
@@ -54,7 +72,7 @@ pub struct Settings {
 impl Settings {
     pub fn detect_changes(&mut self) -> bool {
         if self.noise != self.prev_noise {
-            self.prev_noise = self.noise;
+            self.prev_noise = self.noise.clone();
             true
         } else {
             false
@@ -62,15 +80,27 @@ impl Settings {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Reflect, InspectorOptions)]
+#[derive(Debug, Clone, PartialEq, Reflect, InspectorOptions)]
 #[reflect(InspectorOptions)]
 pub struct NoiseSettings {
-    pub scale: u32,
+    /// World seed, all noise used for terrain generation is derived from it.
+    pub seed: u32,
+    pub preset: WorldPreset,
+}
+
+impl NoiseSettings {
+    /// Creates the generator these settings describe.
+    pub fn generator(&self) -> ChunkGenerator {
+        ChunkGenerator::new(self.seed, self.preset.clone())
+    }
 }
 
 impl Default for NoiseSettings {
     fn default() -> Self {
-        Self { scale: 100 }
+        Self {
+            seed: ChunkGenerator::DEFAULT_SEED,
+            preset: WorldPreset::default(),
+        }
     }
 }
 
@@ -78,9 +108,12 @@ impl Default for Settings {
     fn default() -> Self {
         Self {
             render_distance: 16,
+            unload_margin: 2,
+            cached_chunks: 256,
             update_chunks: true,
-            task_polls_per_frame: 1,
-            mesh_updates_per_frame: 1,
+            max_generation_tasks: 16,
+            pipeline: Default::default(),
+            target_frame_time: 1000.0 / 60.0,
             mesh_builder: Default::default(),
             noise: Default::default(),
             prev_noise: Default::default(),